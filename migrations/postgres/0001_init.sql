@@ -0,0 +1,28 @@
+CREATE TABLE IF NOT EXISTS messages (
+    id BIGSERIAL PRIMARY KEY,
+    ts TIMESTAMPTZ NOT NULL,
+    method TEXT NOT NULL,
+    client_id TEXT NOT NULL,
+    topic TEXT NOT NULL,
+    message_id TEXT NOT NULL,
+    message TEXT NOT NULL,
+    UNIQUE (client_id, topic, message_id)
+);
+
+CREATE INDEX IF NOT EXISTS messages_topic_ts_idx ON messages (topic, ts);
+CREATE INDEX IF NOT EXISTS messages_client_id_topic_ts_idx ON messages (client_id, topic, ts);
+
+CREATE TABLE IF NOT EXISTS topics (
+    client_id TEXT NOT NULL,
+    topic TEXT NOT NULL,
+    ts TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (client_id, topic)
+);
+
+CREATE INDEX IF NOT EXISTS topics_client_id_ts_idx ON topics (client_id, ts DESC);
+
+CREATE TABLE IF NOT EXISTS registrations (
+    client_id TEXT PRIMARY KEY,
+    tags TEXT[] NOT NULL,
+    relay_url TEXT NOT NULL
+);
@@ -0,0 +1,3 @@
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS tag BIGINT;
+
+CREATE INDEX IF NOT EXISTS messages_client_id_topic_tag_idx ON messages (client_id, topic, tag);
@@ -0,0 +1,30 @@
+CREATE TABLE IF NOT EXISTS client_usage (
+    client_id TEXT PRIMARY KEY,
+    messages BIGINT NOT NULL DEFAULT 0,
+    bytes BIGINT NOT NULL DEFAULT 0
+);
+
+INSERT INTO client_usage (client_id, messages, bytes)
+SELECT client_id, COUNT(*), SUM(octet_length(message)) FROM messages GROUP BY client_id
+ON CONFLICT (client_id) DO UPDATE SET messages = EXCLUDED.messages, bytes = EXCLUDED.bytes;
+
+CREATE OR REPLACE FUNCTION messages_usage() RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP IN ('UPDATE', 'DELETE') THEN
+        UPDATE client_usage
+        SET messages = messages - 1, bytes = bytes - octet_length(OLD.message)
+        WHERE client_id = OLD.client_id;
+    END IF;
+    IF TG_OP IN ('INSERT', 'UPDATE') THEN
+        INSERT INTO client_usage (client_id, messages, bytes)
+        VALUES (NEW.client_id, 1, octet_length(NEW.message))
+        ON CONFLICT (client_id) DO UPDATE
+        SET messages = client_usage.messages + 1, bytes = client_usage.bytes + EXCLUDED.bytes;
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS messages_usage ON messages;
+CREATE TRIGGER messages_usage AFTER INSERT OR UPDATE OR DELETE ON messages
+FOR EACH ROW EXECUTE FUNCTION messages_usage();
@@ -0,0 +1,5 @@
+ALTER TABLE messages ADD COLUMN expires_at INTEGER;
+
+CREATE INDEX IF NOT EXISTS messages_expires_at_idx ON messages (expires_at) WHERE expires_at IS NOT NULL;
+
+ALTER TABLE registrations ADD COLUMN max_age INTEGER;
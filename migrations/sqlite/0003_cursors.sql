@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS messages_client_id_topic_ts_idx;
+
+CREATE INDEX IF NOT EXISTS messages_client_id_topic_ts_id_idx ON messages (client_id, topic, ts, id);
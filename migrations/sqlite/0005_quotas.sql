@@ -0,0 +1,30 @@
+CREATE TABLE IF NOT EXISTS client_usage (
+    client_id TEXT PRIMARY KEY,
+    messages INTEGER NOT NULL DEFAULT 0,
+    bytes INTEGER NOT NULL DEFAULT 0
+);
+
+INSERT OR REPLACE INTO client_usage (client_id, messages, bytes)
+SELECT client_id, COUNT(*), SUM(length(CAST(message AS BLOB))) FROM messages GROUP BY client_id;
+
+CREATE TRIGGER IF NOT EXISTS messages_usage_insert AFTER INSERT ON messages
+BEGIN
+    INSERT OR IGNORE INTO client_usage (client_id) VALUES (NEW.client_id);
+    UPDATE client_usage
+    SET messages = messages + 1, bytes = bytes + length(CAST(NEW.message AS BLOB))
+    WHERE client_id = NEW.client_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS messages_usage_update AFTER UPDATE OF message ON messages
+BEGIN
+    UPDATE client_usage
+    SET bytes = bytes - length(CAST(OLD.message AS BLOB)) + length(CAST(NEW.message AS BLOB))
+    WHERE client_id = NEW.client_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS messages_usage_delete AFTER DELETE ON messages
+BEGIN
+    UPDATE client_usage
+    SET messages = messages - 1, bytes = bytes - length(CAST(OLD.message AS BLOB))
+    WHERE client_id = OLD.client_id;
+END;
@@ -1,17 +1,19 @@
 use {
+    crate::{
+        config::Configuration,
+        error::{self, Error},
+    },
     async_trait::async_trait,
     axum::{
         extract::FromRequestParts,
-        http::{header::AUTHORIZATION, request::Parts, StatusCode},
+        http::{header::AUTHORIZATION, request::Parts},
     },
+    relay_rpc::jwt::{JwtBasicClaims, VerifyableClaims},
+    std::collections::HashSet,
 };
 
-const ERR_MISSING: &str = "`Authorization` header is missing";
-const ERR_CHARS: &str = "`Authorization` header contains invalid characters";
-const ERR_WRONG_BEARER: &str = "`Authorization` header must be a bearer token";
-
 /// Rejection error used in the [AuthBearer] extractors.
-pub type Rejection = (StatusCode, &'static str);
+pub type Rejection = Error;
 
 /// Bearer token extractor which contains the innards of a bearer header as a
 /// string.
@@ -31,15 +33,14 @@ pub type Rejection = (StatusCode, &'static str);
 ///
 /// # Errors
 ///
-/// There are a few errors which this extractor can make. By default, all
-/// invalid responses are `400 BAD REQUEST` with one of these messages:
+/// There are a few errors which this extractor can make. All of them are
+/// reported as `401 UNAUTHORIZED` through [Error]:
 ///
-/// - \`Authorization\` header must be a bearer token – Somebody tried to but
-///   basic auth here instead of bearer
-/// - \`Authorization\` header is missing – The header was required but it
+/// - [Error::InvalidAuthorizationHeader] – Somebody tried to but basic auth
+///   here instead of bearer, or the header couldn't be processed because of
+///   invalid characters
+/// - [Error::MissingAuthorizationHeader] – The header was required but it
 ///   wasn't found
-/// - \`Authorization\` header contains invalid characters – The header couldn't
-///   be processed because of invalid characters
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct AuthBearer(pub String);
 
@@ -65,9 +66,9 @@ impl AuthBearer {
         let authorization = req
             .headers
             .get(AUTHORIZATION)
-            .ok_or((StatusCode::BAD_REQUEST, ERR_MISSING))?
+            .ok_or(Error::MissingAuthorizationHeader)?
             .to_str()
-            .map_err(|_| (StatusCode::BAD_REQUEST, ERR_CHARS))?;
+            .map_err(|_| Error::InvalidAuthorizationHeader)?;
 
         // Check that its a well-formed bearer and return
         let split = authorization.split_once(' ');
@@ -77,7 +78,39 @@ impl AuthBearer {
             // Found empty bearer
             _ if authorization == "Bearer" => Ok(Self::from_header("")),
             // Found nothing
-            _ => Err((StatusCode::BAD_REQUEST, ERR_WRONG_BEARER)),
+            _ => Err(Error::InvalidAuthorizationHeader),
+        }
+    }
+}
+
+/// How the clients' JWTs are verified.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthPolicy {
+    /// The accepted `aud` claims.
+    pub audiences: HashSet<String>,
+    /// How far off the `iat` and `exp` claims may be, in seconds.
+    pub clock_skew: i64,
+    /// Whether tokens without an `exp` claim are rejected.
+    pub require_exp: bool,
+}
+
+impl AuthPolicy {
+    pub fn new(config: &Configuration) -> Self {
+        Self {
+            audiences: config.auth_audiences.iter().cloned().collect(),
+            clock_skew: config.auth_clock_skew as i64,
+            require_exp: config.auth_require_exp,
+        }
+    }
+
+    /// Decodes `token` and checks its claims against the policy.
+    pub fn verify(&self, token: &str) -> error::Result<JwtBasicClaims> {
+        let claims = JwtBasicClaims::try_from_str(token)?;
+        if self.require_exp && claims.exp.is_none() {
+            return Err(Error::MissingJwtExpiration);
         }
+        claims.verify_basic(&self.audiences, self.clock_skew)?;
+
+        Ok(claims)
     }
 }
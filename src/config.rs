@@ -1,9 +1,66 @@
-use {super::error, serde::Deserialize, std::str::FromStr};
+use {
+    super::{
+        error::{self, Error},
+        rate_limit::RateLimit,
+    },
+    reqwest::Url,
+    serde::Deserialize,
+    std::{collections::HashMap, env, fs, str::FromStr},
+    tracing_subscriber::EnvFilter,
+};
 
 const DEFAULT_PORT_NUMBER: u16 = 3001;
+const DEFAULT_PROMETHEUS_PORT: u16 = 3002;
 const DEFAULT_LOG_LEVEL: &str = "WARN";
 const DEFAULT_RELAY_URL: &str = "https://relay.walletconnect.com";
 const DEFAULT_VALIDATE_SIGNATURES: bool = true;
+const DEFAULT_RETENTION_PRUNE_INTERVAL: u64 = 60 * 60;
+const DEFAULT_SIGNATURE_MAX_AGE: u64 = 5 * 60;
+const DEFAULT_MAX_BODY_SIZE: usize = 4 * 1024 * 1024;
+const DEFAULT_AUTH_AUDIENCES: [&str; 2] = [
+    "wss://relay.walletconnect.com",
+    "https://history.walletconnect.com",
+];
+const DEFAULT_AUTH_CLOCK_SKEW: u64 = 2 * 60;
+const MAX_AUTH_CLOCK_SKEW: u64 = 60 * 60;
+/// The max age of stored messages, in seconds, about a hundred years.
+pub const MAX_MESSAGE_MAX_AGE: u64 = 100 * 365 * 24 * 60 * 60;
+const INVALID_RELAY_KEYPAIR_SEED: &str = "`RELAY_KEYPAIR_SEED` must be 32 hex encoded bytes";
+
+/// The environment variable naming the optional TOML configuration file.
+const CONFIG_FILE_VAR: &str = "config_file";
+/// The fields which may instead be read from the file named by their `*_FILE`
+/// variable, to keep secrets out of the environment.
+const SECRET_FIELDS: [&str; 3] = ["mongo_address", "postgres_address", "relay_keypair_seed"];
+const SECRET_FILE_SUFFIX: &str = "_file";
+
+/// The database used to store messages and registrations.
+#[derive(Deserialize, Debug, Clone, Copy, Default, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    #[default]
+    Mongo,
+    /// Requires the `postgres-store` feature.
+    #[cfg(feature = "postgres-store")]
+    Postgres,
+    /// Requires the `sqlite-store` feature.
+    #[cfg(feature = "sqlite-store")]
+    Sqlite,
+    /// Nothing is persisted, requires the `memory-store` feature.
+    #[cfg(feature = "memory-store")]
+    Memory,
+}
+
+/// What happens to the new messages of a client over its quota.
+#[derive(Deserialize, Debug, Clone, Copy, Default, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum QuotaPolicy {
+    /// New messages are rejected until the client deletes some.
+    #[default]
+    Reject,
+    /// The client's oldest messages are deleted to make room.
+    Evict,
+}
 
 /// The server configuration.
 #[derive(Deserialize, Debug, Clone, Eq, PartialEq)]
@@ -17,11 +74,72 @@ pub struct Configuration {
     /// The URL of the Relay server.
     #[serde(default = "default_relay_url")]
     pub relay_url: String,
+    /// The hex encoded 32 bytes seed of the keypair identifying the server to
+    /// the relay. When set, the server registers a watch with the relay and
+    /// accepts its webhook events.
+    pub relay_keypair_seed: Option<String>,
     /// A flag to enable or disable the signature validation.
     #[serde(default = "default_validate_signatures")]
     pub validate_signatures: bool,
-    /// The address of the MongoDB instance.
+    /// How far from now the timestamp of a signed request may be, in
+    /// seconds. Older requests are rejected, as are replays of recent ones.
+    #[serde(default = "default_signature_max_age")]
+    pub signature_max_age: u64,
+    /// The max size of the body of the requests delivered by the relay, in
+    /// bytes.
+    #[serde(default = "default_max_body_size")]
+    pub max_body_size: usize,
+    /// The storage backend to use.
+    #[serde(default)]
+    pub storage_backend: StorageBackend,
+    /// The address of the MongoDB instance, required by the `mongo` backend.
+    #[serde(default)]
     pub mongo_address: String,
+    /// The address of the PostgreSQL instance, required by the `postgres`
+    /// backend.
+    pub postgres_address: Option<String>,
+    /// The path of the SQLite database file, required by the `sqlite` backend.
+    /// The file is created if it doesn't exist.
+    pub sqlite_path: Option<String>,
+    /// The default maximum age of stored messages, in seconds, applying to
+    /// the messages already stored as well. Messages are kept forever when
+    /// neither this nor the registration's `maxAge` is set.
+    pub message_max_age: Option<u64>,
+    /// The interval between two prunings of expired messages, in seconds.
+    #[serde(default = "default_retention_prune_interval")]
+    pub retention_prune_interval: u64,
+    /// The max size of a message, in bytes. Only `max_body_size` bounds it
+    /// when unset.
+    pub max_message_size: Option<usize>,
+    /// The max number of messages stored for a client, unlimited if not set.
+    pub client_max_messages: Option<u64>,
+    /// The max total size of the messages stored for a client, in bytes,
+    /// unlimited if not set.
+    pub client_max_bytes: Option<u64>,
+    /// What happens to the new messages of a client over its quota.
+    #[serde(default)]
+    pub quota_policy: QuotaPolicy,
+    /// The rate limit of `/register`, per client. Rates are written
+    /// `requests/seconds`, e.g. `20/60`, and the routes are unlimited if not
+    /// set.
+    pub register_rate_limit: Option<RateLimit>,
+    /// The rate limit of the routes reading and deleting messages, per
+    /// client, shared by `/messages`, `/messages/query`, `/messages/stream`
+    /// and `/topics`.
+    pub messages_rate_limit: Option<RateLimit>,
+    /// The rate limit of the relay's deliveries to `/messages`,
+    /// `/messages/batch` and `/messages/watch`, per IP address. The requests
+    /// verified to come from the relay aren't limited.
+    pub relay_rate_limit: Option<RateLimit>,
+    /// The `aud` claims accepted in the clients' JWTs, comma separated.
+    #[serde(default = "default_auth_audiences")]
+    pub auth_audiences: Vec<String>,
+    /// How far off the `iat` and `exp` claims of a JWT may be, in seconds.
+    #[serde(default = "default_auth_clock_skew")]
+    pub auth_clock_skew: u64,
+    /// Whether the clients' JWTs must have an `exp` claim.
+    #[serde(default)]
+    pub auth_require_exp: bool,
     /// An internal flag to disable logging, cannot be defined by user.
     #[serde(default = "default_is_test", skip)]
     pub is_test: bool,
@@ -31,9 +149,147 @@ pub struct Configuration {
 }
 
 impl Configuration {
-    /// Validate the configuration.
+    /// Validate the configuration, reporting all of its problems at once.
     pub fn is_valid(&self) -> error::Result<()> {
-        Ok(())
+        let mut problems = vec![];
+
+        match self.storage_backend {
+            StorageBackend::Mongo if self.mongo_address.is_empty() => problems
+                .push("`MONGO_ADDRESS` is required by the `mongo` storage backend".to_string()),
+            StorageBackend::Mongo => {
+                check_scheme(&mut problems, "MONGO_ADDRESS", &self.mongo_address, &[
+                    "mongodb",
+                    "mongodb+srv",
+                ])
+            }
+            #[cfg(feature = "postgres-store")]
+            StorageBackend::Postgres => match &self.postgres_address {
+                Some(address) => check_scheme(&mut problems, "POSTGRES_ADDRESS", address, &[
+                    "postgres",
+                    "postgresql",
+                ]),
+                None => problems.push(
+                    "`POSTGRES_ADDRESS` is required by the `postgres` storage backend".to_string(),
+                ),
+            },
+            #[cfg(feature = "sqlite-store")]
+            StorageBackend::Sqlite => {
+                if self.sqlite_path.is_none() {
+                    problems.push(
+                        "`SQLITE_PATH` is required by the `sqlite` storage backend".to_string(),
+                    )
+                }
+            }
+            #[cfg(feature = "memory-store")]
+            StorageBackend::Memory => {}
+        }
+
+        check_url(&mut problems, "PUBLIC_URL", &self.public_url, &[
+            "http", "https",
+        ]);
+        check_url(&mut problems, "RELAY_URL", &self.relay_url, &[
+            "http", "https",
+        ]);
+        if let Some(endpoint) = &self.otel_exporter_otlp_endpoint {
+            check_url(&mut problems, "OTEL_EXPORTER_OTLP_ENDPOINT", endpoint, &[
+                "http", "https",
+            ]);
+        }
+
+        if self.port == self.prometheus_port() {
+            problems.push(format!(
+                "`PORT` and `TELEMETRY_PROMETHEUS_PORT` must differ, both are {}{}",
+                self.port,
+                if self.telemetry_prometheus_port.is_none() {
+                    ", the latter by default"
+                } else {
+                    ""
+                }
+            ));
+        }
+
+        if let Err(e) = EnvFilter::try_new(&self.log_level) {
+            problems.push(format!(
+                "`LOG_LEVEL` must be a level or a list of `target=level` directives: {e}"
+            ));
+        }
+
+        for (name, value) in [
+            (
+                "RETENTION_PRUNE_INTERVAL",
+                Some(self.retention_prune_interval),
+            ),
+            ("MESSAGE_MAX_AGE", self.message_max_age),
+            ("SIGNATURE_MAX_AGE", Some(self.signature_max_age)),
+            ("MAX_BODY_SIZE", Some(self.max_body_size as u64)),
+            (
+                "MAX_MESSAGE_SIZE",
+                self.max_message_size.map(|size| size as u64),
+            ),
+            ("CLIENT_MAX_MESSAGES", self.client_max_messages),
+            ("CLIENT_MAX_BYTES", self.client_max_bytes),
+        ] {
+            if value == Some(0) {
+                problems.push(format!("`{name}` must be greater than zero"));
+            }
+        }
+
+        if self.auth_audiences.is_empty() {
+            problems.push("`AUTH_AUDIENCES` must not be empty".to_string());
+        }
+
+        if let Some(audience) = self
+            .auth_audiences
+            .iter()
+            .find(|audience| audience.is_empty() || audience.contains(char::is_whitespace))
+        {
+            problems.push(format!(
+                "`AUTH_AUDIENCES` must not be blank or contain whitespace, found `{audience}`"
+            ));
+        }
+
+        if self.auth_clock_skew > MAX_AUTH_CLOCK_SKEW {
+            problems.push(format!(
+                "`AUTH_CLOCK_SKEW` must be at most {MAX_AUTH_CLOCK_SKEW} seconds"
+            ));
+        }
+
+        if matches!(self.message_max_age, Some(max_age) if max_age > MAX_MESSAGE_MAX_AGE) {
+            problems.push(format!(
+                "`MESSAGE_MAX_AGE` must be at most {MAX_MESSAGE_MAX_AGE} seconds"
+            ));
+        }
+
+        if self.relay_keypair_seed().is_err() {
+            problems.push(INVALID_RELAY_KEYPAIR_SEED.to_string());
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::InvalidConfiguration(problems.join("; ")))
+        }
+    }
+
+    /// The decoded seed of the relay keypair, if any.
+    pub fn relay_keypair_seed(&self) -> error::Result<Option<[u8; 32]>> {
+        self.relay_keypair_seed
+            .as_deref()
+            .map(|seed| {
+                hex::decode(seed)
+                    .ok()
+                    .and_then(|seed| seed.try_into().ok())
+                    .ok_or_else(|| {
+                        Error::InvalidConfiguration(INVALID_RELAY_KEYPAIR_SEED.to_string())
+                    })
+            })
+            .transpose()
+    }
+
+    /// The port of the metrics server.
+    pub fn prometheus_port(&self) -> u16 {
+        self.telemetry_prometheus_port
+            .unwrap_or(DEFAULT_PROMETHEUS_PORT)
     }
 
     pub fn log_level(&self) -> tracing::Level {
@@ -57,12 +313,170 @@ fn default_validate_signatures() -> bool {
     DEFAULT_VALIDATE_SIGNATURES
 }
 
+fn default_retention_prune_interval() -> u64 {
+    DEFAULT_RETENTION_PRUNE_INTERVAL
+}
+
+fn default_auth_audiences() -> Vec<String> {
+    DEFAULT_AUTH_AUDIENCES.map(String::from).to_vec()
+}
+
+fn default_auth_clock_skew() -> u64 {
+    DEFAULT_AUTH_CLOCK_SKEW
+}
+
+fn default_signature_max_age() -> u64 {
+    DEFAULT_SIGNATURE_MAX_AGE
+}
+
+fn default_max_body_size() -> usize {
+    DEFAULT_MAX_BODY_SIZE
+}
+
 fn default_is_test() -> bool {
     false
 }
 
-/// Create a new configuration from the environment variables.
+/// Create a new configuration from the environment variables, over the TOML
+/// file named by `CONFIG_FILE` if any.
 pub fn get_config() -> error::Result<Configuration> {
-    let config = envy::from_env::<Configuration>()?;
+    get_config_from(env::vars())
+}
+
+/// Create a new configuration from `vars`, over the TOML file named by their
+/// `CONFIG_FILE` if any.
+///
+/// The file's keys are the lowercase names of the variables, e.g.
+/// `mongo_address`, whose values override the file's. In both, the secrets may
+/// instead be read from the file named by their `*_FILE` key, e.g.
+/// `MONGO_ADDRESS_FILE`.
+pub fn get_config_from(
+    vars: impl IntoIterator<Item = (String, String)>,
+) -> error::Result<Configuration> {
+    let mut vars: HashMap<String, String> = vars
+        .into_iter()
+        .map(|(name, value)| (name.to_lowercase(), value))
+        .collect();
+
+    let mut values = match vars.remove(CONFIG_FILE_VAR) {
+        Some(path) => read_config_file(&path)?,
+        None => HashMap::new(),
+    };
+    read_secrets(&mut values)?;
+    read_secrets(&mut vars)?;
+    values.extend(vars);
+
+    let config = envy::from_iter(values)?;
     Ok(config)
 }
+
+/// Reads the TOML file at `path` into the values of the variables it sets.
+fn read_config_file(path: &str) -> error::Result<HashMap<String, String>> {
+    let contents = fs::read_to_string(path).map_err(|e| {
+        Error::InvalidConfiguration(format!("cannot read the config file `{path}`: {e}"))
+    })?;
+    let table: toml::Table = toml::from_str(&contents).map_err(|e| {
+        Error::InvalidConfiguration(format!("cannot parse the config file `{path}`: {e}"))
+    })?;
+
+    let mut values = HashMap::new();
+    let mut problems = vec![];
+    for (key, value) in table {
+        match toml_to_var(value) {
+            Some(value) => {
+                values.insert(key.to_lowercase(), value);
+            }
+            None => problems.push(format!(
+                "`{key}` must be a string, a number, a boolean or an array of those"
+            )),
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(values)
+    } else {
+        Err(Error::InvalidConfiguration(format!(
+            "invalid config file `{path}`: {}",
+            problems.join("; ")
+        )))
+    }
+}
+
+/// Formats `value` as an environment variable, arrays being comma separated.
+fn toml_to_var(value: toml::Value) -> Option<String> {
+    match value {
+        toml::Value::Array(values) => values
+            .into_iter()
+            .map(|value| match value {
+                toml::Value::Array(_) => None,
+                value => toml_to_var(value),
+            })
+            .collect::<Option<Vec<_>>>()
+            .map(|values| values.join(",")),
+        toml::Value::String(value) => Some(value),
+        toml::Value::Integer(value) => Some(value.to_string()),
+        toml::Value::Float(value) => Some(value.to_string()),
+        toml::Value::Boolean(value) => Some(value.to_string()),
+        toml::Value::Datetime(value) => Some(value.to_string()),
+        toml::Value::Table(_) => None,
+    }
+}
+
+/// Replaces the `*_FILE` keys of `values` by the trimmed contents of the files
+/// they name.
+fn read_secrets(values: &mut HashMap<String, String>) -> error::Result<()> {
+    for field in SECRET_FIELDS {
+        let file_key = format!("{field}{SECRET_FILE_SUFFIX}");
+        let Some(path) = values.remove(&file_key) else {
+            continue;
+        };
+
+        if values.contains_key(field) {
+            return Err(Error::InvalidConfiguration(format!(
+                "only one of `{}` and `{}` may be set",
+                field.to_uppercase(),
+                file_key.to_uppercase()
+            )));
+        }
+
+        let secret = fs::read_to_string(&path).map_err(|e| {
+            Error::InvalidConfiguration(format!(
+                "cannot read `{}` from `{path}`: {e}",
+                field.to_uppercase()
+            ))
+        })?;
+        values.insert(field.to_string(), secret.trim().to_string());
+    }
+
+    Ok(())
+}
+
+/// Reports `value` unless it's an address with one of the `schemes`. Unlike
+/// [`check_url`] the rest isn't checked, as databases have their own syntax
+/// for e.g. multiple hosts.
+fn check_scheme(problems: &mut Vec<String>, name: &str, value: &str, schemes: &[&str]) {
+    match value.split_once("://") {
+        Some((scheme, _)) if schemes.contains(&scheme) => {}
+        _ => problems.push(format!(
+            "`{name}` must be an address starting with {}",
+            schemes
+                .iter()
+                .map(|scheme| format!("`{scheme}://`"))
+                .collect::<Vec<_>>()
+                .join(" or ")
+        )),
+    }
+}
+
+/// Reports `value` unless it's a URL with one of the `schemes`.
+fn check_url(problems: &mut Vec<String>, name: &str, value: &str, schemes: &[&str]) {
+    match Url::parse(value) {
+        Ok(url) if schemes.contains(&url.scheme()) => {}
+        Ok(url) => problems.push(format!(
+            "`{name}` must be a {} URL, found the `{}` scheme",
+            schemes.join(" or "),
+            url.scheme()
+        )),
+        Err(e) => problems.push(format!("`{name}` must be a valid URL: {e}")),
+    }
+}
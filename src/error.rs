@@ -4,9 +4,14 @@ use {
         log::prelude::*,
         relay::signature::{SIGNATURE_HEADER_NAME, TIMESTAMP_HEADER_NAME},
         store::StoreError,
+        tags::TagPatternError,
+    },
+    axum::{
+        http::header::{AUTHORIZATION, RETRY_AFTER},
+        response::{IntoResponse, Response},
     },
-    axum::response::{IntoResponse, Response},
     hyper::StatusCode,
+    relay_rpc::jwt::JwtError,
 };
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -67,6 +72,15 @@ pub enum Error {
     #[error("middleware failed to parse body")]
     ToBytesError,
 
+    #[error("the body is larger than the {0} bytes limit")]
+    PayloadTooLarge(usize),
+
+    #[error("timestamp header is invalid or more than {0} seconds away")]
+    StaleSignature(u64),
+
+    #[error("the signed request was already received")]
+    ReplayedSignature,
+
     #[error("neither signature or timestamp header cannot not found")]
     MissingAllSignatureHeader,
 
@@ -79,6 +93,51 @@ pub enum Error {
     #[error("invalid update request")]
     InvalidUpdateRequest,
 
+    #[error("invalid delete request")]
+    InvalidDeleteRequest,
+
+    #[error("invalid stream request: {0}")]
+    InvalidStreamRequest(String),
+
+    #[error("at most {0} streams can be open per client")]
+    TooManyStreams(usize),
+
+    #[error("invalid query request: {0}")]
+    InvalidQueryRequest(String),
+
+    #[error("`since` must be before `until`")]
+    InvalidTimeRange,
+
+    #[error("messages are limited to {0} bytes")]
+    MessageTooLarge(usize),
+
+    #[error("`maxAge` must be at most {0} seconds")]
+    InvalidMaxAge(u64),
+
+    #[error("the client's storage quota is exceeded")]
+    QuotaExceeded,
+
+    #[error("too many requests, retry in {0} seconds")]
+    RateLimited(u64),
+
+    #[error("batches are limited to {0} messages")]
+    BatchTooLarge(usize),
+
+    #[error("invalid watch event: {0}")]
+    InvalidWatchEvent(String),
+
+    #[error("the watch event was issued more than {0} seconds ago")]
+    StaleWatchEvent(u64),
+
+    #[error("the watch event was already received")]
+    ReplayedWatchEvent,
+
+    #[error("the relay responded with an error: {0}")]
+    RelayRpc(String),
+
+    #[error("invalid tag pattern `{0}`: {1}")]
+    InvalidTagPattern(String, TagPatternError),
+
     #[error(transparent)]
     FromUtf8Error(#[from] std::string::FromUtf8Error),
 
@@ -94,20 +153,82 @@ pub enum Error {
     #[error(transparent)]
     AuthError(#[from] relay_rpc::auth::Error),
 
+    #[error("the JWT must have an `exp` claim")]
+    MissingJwtExpiration,
+
     #[error("the provided authentication does not authenticate the request")]
     InvalidAuthentication,
+
+    #[error("`Authorization` header is missing")]
+    MissingAuthorizationHeader,
+
+    #[error("`Authorization` header must be a valid bearer token")]
+    InvalidAuthorizationHeader,
+
+    #[error("no messages were delivered to the client on topic {0}")]
+    TopicAccessDenied(String),
+
+    #[error("the configuration can only be reloaded from the loopback interface")]
+    ReloadForbidden,
 }
 
 impl IntoResponse for Error {
     fn into_response(self) -> Response {
+        // Not logged as an error, limited clients would flood the logs
+        if let e @ Error::RateLimited(retry_after) = self {
+            let mut response = crate::handlers::Response::new_failure(
+                StatusCode::TOO_MANY_REQUESTS,
+                vec![ResponseError {
+                    name: "rate_limit".to_string(),
+                    message: e.to_string(),
+                }],
+                vec![],
+            )
+            .into_response();
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, retry_after.into());
+            return response;
+        }
+
         error!("responding with error ({:?})", self);
         match self {
+            Error::JwtError(e @ JwtError::InvalidAudience) => crate::handlers::Response::new_failure(StatusCode::FORBIDDEN, vec![
+                ResponseError {
+                    name: "jwt".to_string(),
+                    message: e.to_string(),
+                }
+            ], vec![]),
             Error::JwtError(e) => crate::handlers::Response::new_failure(StatusCode::UNAUTHORIZED, vec![
                 ResponseError {
                     name: "jwt".to_string(),
                     message: e.to_string(),
                 }
             ], vec![]),
+            e @ Error::MissingJwtExpiration => crate::handlers::Response::new_failure(StatusCode::UNAUTHORIZED, vec![
+                ResponseError {
+                    name: "jwt".to_string(),
+                    message: e.to_string(),
+                }
+            ], vec![]),
+            Error::InvalidAuthentication => crate::handlers::Response::new_failure(StatusCode::UNAUTHORIZED, vec![
+                ResponseError {
+                    name: "authentication".to_string(),
+                    message: Error::InvalidAuthentication.to_string(),
+                }
+            ], vec![]),
+            e @ (Error::MissingAuthorizationHeader | Error::InvalidAuthorizationHeader) => crate::handlers::Response::new_failure(StatusCode::UNAUTHORIZED, vec![
+                ResponseError {
+                    name: "authorization".to_string(),
+                    message: e.to_string(),
+                }
+            ], vec![
+                ErrorField {
+                    field: AUTHORIZATION.to_string(),
+                    description: e.to_string(),
+                    location: ErrorLocation::Header,
+                }
+            ]),
             Error::Database(e) => crate::handlers::Response::new_failure(StatusCode::INTERNAL_SERVER_ERROR, vec![
                 ResponseError {
                     name: "mongodb".to_string(),
@@ -141,6 +262,40 @@ impl IntoResponse for Error {
                     }],
                     vec![],
                 ),
+                #[cfg(any(feature = "postgres-store", feature = "sqlite-store"))]
+                StoreError::Sql(e) => crate::handlers::Response::new_failure(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    vec![ResponseError {
+                        name: "sql".to_string(),
+                        message: e.to_string(),
+                    }],
+                    vec![],
+                ),
+                StoreError::Json(e) => crate::handlers::Response::new_failure(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    vec![ResponseError {
+                        name: "json".to_string(),
+                        message: e.to_string(),
+                    }],
+                    vec![],
+                ),
+                e @ StoreError::BulkWrite(_) => crate::handlers::Response::new_failure(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    vec![ResponseError {
+                        name: "bulk_write".to_string(),
+                        message: e.to_string(),
+                    }],
+                    vec![],
+                ),
+                e @ StoreError::InvalidCursor(_) => crate::handlers::Response::new_failure(
+                    StatusCode::BAD_REQUEST,
+                    vec![],
+                    vec![ErrorField {
+                        field: "cursor".to_string(),
+                        description: e.to_string(),
+                        location: ErrorLocation::Query,
+                    }],
+                ),
                 StoreError::NotFound(entity, id) => crate::handlers::Response::new_failure(
                     StatusCode::NOT_FOUND,
                     vec![],
@@ -192,6 +347,18 @@ impl IntoResponse for Error {
                     location: ErrorLocation::Header
                 }
             ]),
+            e @ (Error::StaleSignature(_) | Error::ReplayedSignature) => crate::handlers::Response::new_failure(StatusCode::UNAUTHORIZED, vec![
+                ResponseError {
+                    name: "history_item_validation_failed".to_string(),
+                    message: e.to_string(),
+                }
+            ], vec![
+                ErrorField {
+                    field: TIMESTAMP_HEADER_NAME.to_string(),
+                    description: e.to_string(),
+                    location: ErrorLocation::Header
+                }
+            ]),
             Error::MissingTopic => crate::handlers::Response::new_failure(
                 StatusCode::BAD_REQUEST,
                 vec![ResponseError {
@@ -200,6 +367,14 @@ impl IntoResponse for Error {
                 }],
                 vec![],
             ),
+            e @ Error::TopicAccessDenied(_) => crate::handlers::Response::new_failure(
+                StatusCode::FORBIDDEN,
+                vec![ResponseError {
+                    name: "topic".to_string(),
+                    message: e.to_string(),
+                }],
+                vec![],
+            ),
             Error::InvalidUpdateRequest => crate::handlers::Response::new_failure(
                 StatusCode::BAD_REQUEST,
                 vec![ResponseError {
@@ -208,6 +383,132 @@ impl IntoResponse for Error {
                 }],
                 vec![],
             ),
+            e @ Error::InvalidStreamRequest(_) => crate::handlers::Response::new_failure(
+                StatusCode::BAD_REQUEST,
+                vec![],
+                vec![ErrorField {
+                    field: "topics".to_string(),
+                    description: e.to_string(),
+                    location: ErrorLocation::Query,
+                }],
+            ),
+            e @ Error::ReloadForbidden => crate::handlers::Response::new_failure(
+                StatusCode::FORBIDDEN,
+                vec![ResponseError {
+                    name: "reload".to_string(),
+                    message: e.to_string(),
+                }],
+                vec![],
+            ),
+            e @ Error::TooManyStreams(_) => crate::handlers::Response::new_failure(
+                StatusCode::TOO_MANY_REQUESTS,
+                vec![ResponseError {
+                    name: "stream".to_string(),
+                    message: e.to_string(),
+                }],
+                vec![],
+            ),
+            e @ Error::InvalidTimeRange => crate::handlers::Response::new_failure(
+                StatusCode::BAD_REQUEST,
+                vec![ResponseError {
+                    name: "since/until".to_string(),
+                    message: e.to_string(),
+                }],
+                vec![],
+            ),
+            e @ Error::InvalidQueryRequest(_) => crate::handlers::Response::new_failure(
+                StatusCode::BAD_REQUEST,
+                vec![],
+                vec![ErrorField {
+                    field: "topics".to_string(),
+                    description: e.to_string(),
+                    location: ErrorLocation::Body,
+                }],
+            ),
+            e @ Error::PayloadTooLarge(_) => crate::handlers::Response::new_failure(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                vec![ResponseError {
+                    name: "body".to_string(),
+                    message: e.to_string(),
+                }],
+                vec![],
+            ),
+            e @ Error::MessageTooLarge(_) => crate::handlers::Response::new_failure(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                vec![ResponseError {
+                    name: "message".to_string(),
+                    message: e.to_string(),
+                }],
+                vec![],
+            ),
+            e @ Error::InvalidMaxAge(_) => crate::handlers::Response::new_failure(
+                StatusCode::BAD_REQUEST,
+                vec![],
+                vec![ErrorField {
+                    field: "maxAge".to_string(),
+                    description: e.to_string(),
+                    location: ErrorLocation::Body,
+                }],
+            ),
+            e @ Error::QuotaExceeded => crate::handlers::Response::new_failure(
+                StatusCode::FORBIDDEN,
+                vec![ResponseError {
+                    name: "quota".to_string(),
+                    message: e.to_string(),
+                }],
+                vec![],
+            ),
+            e @ Error::BatchTooLarge(_) => crate::handlers::Response::new_failure(
+                StatusCode::BAD_REQUEST,
+                vec![ResponseError {
+                    name: "batch".to_string(),
+                    message: e.to_string(),
+                }],
+                vec![],
+            ),
+            e @ Error::InvalidWatchEvent(_) => crate::handlers::Response::new_failure(
+                StatusCode::BAD_REQUEST,
+                vec![ResponseError {
+                    name: "eventAuth".to_string(),
+                    message: e.to_string(),
+                }],
+                vec![],
+            ),
+            e @ (Error::StaleWatchEvent(_) | Error::ReplayedWatchEvent) => {
+                crate::handlers::Response::new_failure(
+                    StatusCode::UNAUTHORIZED,
+                    vec![ResponseError {
+                        name: "eventAuth".to_string(),
+                        message: e.to_string(),
+                    }],
+                    vec![],
+                )
+            }
+            e @ Error::InvalidTagPattern(..) => crate::handlers::Response::new_failure(
+                StatusCode::BAD_REQUEST,
+                vec![],
+                vec![ErrorField {
+                    field: "tags".to_string(),
+                    description: e.to_string(),
+                    location: ErrorLocation::Body,
+                }],
+            ),
+            e @ (Error::InvalidConfiguration(_) | Error::Envy(_)) => crate::handlers::Response::new_failure(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                vec![ResponseError {
+                    name: "configuration".to_string(),
+                    message: e.to_string(),
+                }],
+                vec![],
+            ),
+            Error::InvalidDeleteRequest => crate::handlers::Response::new_failure(
+                StatusCode::BAD_REQUEST,
+                vec![ResponseError {
+                    name: "topic/messageIds/all".to_string(),
+                    message: "either `all` or at least one of `topic` and `messageIds` must be set".to_string(),
+                }],
+                vec![],
+            ),
             e => crate::handlers::Response::new_failure(StatusCode::INTERNAL_SERVER_ERROR, vec![
                 ResponseError {
                     name: "unknown_error".to_string(),
@@ -0,0 +1,75 @@
+use {
+    crate::{
+        auth::AuthBearer,
+        error::{self, Error},
+        increment_counter_with,
+        log::prelude::*,
+        state::AppState,
+    },
+    axum::{extract::State, Json},
+    relay_rpc::domain::ClientId,
+    serde::{Deserialize, Serialize},
+    std::sync::Arc,
+};
+
+/// The request body for the delete messages endpoint.
+///
+/// Either `all` is set, or `topic` and/or `messageIds` select the messages to
+/// delete: a whole topic, some messages of a topic or some messages across
+/// topics.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteMessagesBody {
+    pub topic: Option<Arc<str>>,
+    pub message_ids: Option<Vec<Arc<str>>>,
+    #[serde(default)]
+    pub all: bool,
+}
+
+/// The response body for the delete messages endpoint.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteMessagesResponse {
+    pub deleted_count: u64,
+}
+
+/// The handler for the delete messages endpoint.
+///
+/// Only the messages delivered to the client identified by the JWT's `iss`
+/// are deleted.
+pub async fn handler(
+    State(state): State<Arc<AppState>>,
+    AuthBearer(token): AuthBearer,
+    Json(body): Json<DeleteMessagesBody>,
+) -> error::Result<Json<DeleteMessagesResponse>> {
+    let claims = state.auth_policy.verify(&token)?;
+    let client_id = ClientId::from(claims.iss);
+
+    let deleted_count = match (body.all, body.topic, body.message_ids) {
+        (true, None, None) => {
+            state
+                .messages_store
+                .delete_all_messages(client_id.as_ref())
+                .await?
+        }
+        (false, Some(topic), None) => {
+            state
+                .messages_store
+                .delete_topic(client_id.as_ref(), topic.as_ref())
+                .await?
+        }
+        (false, topic, Some(message_ids)) if !message_ids.is_empty() => {
+            let message_ids: Vec<&str> = message_ids.iter().map(AsRef::as_ref).collect();
+            state
+                .messages_store
+                .delete_messages(client_id.as_ref(), topic.as_deref(), &message_ids)
+                .await?
+        }
+        _ => return Err(Error::InvalidDeleteRequest),
+    };
+
+    debug!("deleted {deleted_count} messages");
+    increment_counter_with!(state.metrics, deleted_items, deleted_count);
+
+    Ok(Json(DeleteMessagesResponse { deleted_count }))
+}
@@ -0,0 +1,70 @@
+use {
+    crate::{
+        auth::AuthBearer,
+        error,
+        handlers::Response,
+        increment_counter,
+        increment_counter_with,
+        state::AppState,
+        store::StoreError,
+    },
+    axum::extract::{Query, State},
+    relay_rpc::domain::ClientId,
+    serde::{Deserialize, Serialize},
+    std::sync::Arc,
+};
+
+/// The query parameters of the delete registration endpoint.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteRegistrationQuery {
+    /// Also delete all the messages stored for the client.
+    #[serde(default)]
+    pub purge_messages: bool,
+}
+
+/// The handler for the delete registration endpoint.
+///
+/// Stops storing the messages of the client identified by the JWT's `iss`.
+/// Responds with a 404 if the client isn't registered, after purging its
+/// messages if requested all the same. Purging also ends the client's streams.
+pub async fn handler(
+    State(state): State<Arc<AppState>>,
+    AuthBearer(token): AuthBearer,
+    Query(query): Query<DeleteRegistrationQuery>,
+) -> error::Result<Response> {
+    let claims = state.auth_policy.verify(&token)?;
+    let client_id = ClientId::from(claims.iss);
+
+    increment_counter!(state.metrics, unregister);
+
+    // Stop storing the client's messages before purging them, so that no
+    // delivery is stored after the purge
+    let deleted = match state
+        .registration_store
+        .delete_registration(client_id.as_ref())
+        .await
+    {
+        Ok(()) => Ok(()),
+        Err(e @ StoreError::NotFound(..)) => Err(e),
+        Err(e) => return Err(e.into()),
+    };
+
+    increment_counter!(state.metrics, registration_cache_invalidation);
+    state
+        .registration_cache
+        .invalidate(client_id.as_ref())
+        .await;
+
+    if query.purge_messages {
+        let deleted_count = state
+            .messages_store
+            .delete_all_messages(client_id.as_ref())
+            .await?;
+        increment_counter_with!(state.metrics, deleted_items, deleted_count);
+        state.message_hub.close_streams(client_id.as_ref());
+    }
+
+    deleted?;
+    Ok(Response::default())
+}
@@ -1,22 +1,30 @@
 use {
     crate::{
-        error,
+        auth::AuthBearer,
+        error::{self, Error},
         increment_counter,
         increment_counter_with,
         state::AppState,
-        store::messages::{Message, StoreMessages},
+        store::messages::{Message, MessageCursor, MessageFilter, Origin, StoreMessages},
+        tags::TagPattern,
     },
     axum::{
         extract::{Query, State},
         Json,
     },
+    chrono::{DateTime, Utc},
+    relay_rpc::domain::ClientId,
     serde::{Deserialize, Serialize},
     std::{cmp, sync::Arc},
+    tokio::time::{timeout_at, Duration, Instant},
 };
 
 /// The absolute max number of messages to return in the response.
 pub const MAX_MESSAGE_COUNT: usize = 500;
 
+/// The absolute max number of seconds to wait for new messages.
+pub const MAX_WAIT: u64 = 60;
+
 /////////////////////////
 
 /// The direction to return messages in.
@@ -48,10 +56,25 @@ impl MessageCount {
 #[serde(rename_all = "camelCase")]
 pub struct GetMessagesBody {
     pub topic: Arc<str>,
+    /// Starts the page at this message, included. Superseded by `cursor`.
     pub origin_id: Option<Arc<str>>,
+    /// Continues from a `nextCursor` or `prevCursor` of a previous response.
+    pub cursor: Option<Arc<str>>,
     #[serde(default)]
     pub message_count: MessageCount,
     pub direction: Option<Direction>,
+    /// When going forward and there are no messages after the origin, waits
+    /// up to this number of seconds for one to be stored.
+    pub wait: Option<u64>,
+    /// Only returns the messages stored at or after this time.
+    pub since: Option<DateTime<Utc>>,
+    /// Only returns the messages stored before this time.
+    pub until: Option<DateTime<Utc>>,
+    /// Only returns the messages with this method, e.g. `subscription`.
+    pub method: Option<Arc<str>>,
+    /// Only returns the messages whose relay tag matches this pattern, with
+    /// the syntax of the registered tags.
+    pub tag: Option<String>,
 }
 
 /////////////////////////
@@ -63,50 +86,199 @@ pub struct GetMessagesResponse {
     pub topic: Arc<str>,
     pub direction: Direction,
     pub next_id: Option<Arc<str>>,
+    /// Fetches the next page in `direction`, unset if there are no more
+    /// messages.
+    pub next_cursor: Option<String>,
+    /// Fetches the previous page, in the opposite `direction`.
+    pub prev_cursor: Option<String>,
+    /// Continues in `direction` right after the page's last message, set
+    /// whenever messages are returned. Unlike `next_cursor`, it's also set on
+    /// the last page, to resume from once more messages are stored.
+    pub resume_cursor: Option<String>,
     pub messages: Vec<Message>,
 }
 
 /////////////////////////
 
 /// The handler for the get messages endpoint.
+///
+/// Only the messages delivered to the client identified by the JWT's `iss`
+/// are returned, and only for topics that client has been delivered messages
+/// on.
 pub async fn handler(
     State(state): State<Arc<AppState>>,
+    AuthBearer(token): AuthBearer,
     query: Query<GetMessagesBody>,
 ) -> Result<Json<GetMessagesResponse>, error::Error> {
+    let claims = state.auth_policy.verify(&token)?;
+    let client_id = ClientId::from(claims.iss);
+
+    if !state
+        .messages_store
+        .has_topic(client_id.as_ref(), query.topic.as_ref())
+        .await?
+    {
+        return Err(Error::TopicAccessDenied(query.topic.to_string()));
+    }
+
     let direction = query.direction.unwrap_or(Direction::Forward);
+    let filter = message_filter(
+        query.since,
+        query.until,
+        query.method.clone(),
+        query.tag.as_deref(),
+    )?;
+
+    let cursor = query
+        .cursor
+        .as_deref()
+        .map(MessageCursor::decode)
+        .transpose()?;
+    let origin = match (&cursor, &query.origin_id) {
+        (Some(cursor), _) => Origin::Cursor(cursor),
+        (None, Some(origin_id)) => Origin::MessageId(origin_id.as_ref()),
+        (None, None) => Origin::Start,
+    };
+
+    // Subscribe before reading the store, so that the messages stored in
+    // between aren't missed
+    let wait = match (direction, query.wait) {
+        (Direction::Forward, Some(wait)) if wait > 0 => {
+            let updates = state
+                .message_hub
+                .subscribe(client_id.as_ref(), query.topic.as_ref());
+            Some((
+                updates,
+                Instant::now() + Duration::from_secs(wait.min(MAX_WAIT)),
+            ))
+        }
+        _ => None,
+    };
+
+    let mut page = get_page(
+        &state,
+        client_id.as_ref(),
+        &query,
+        origin,
+        &filter,
+        direction,
+    )
+    .await?;
+    if let Some((mut updates, deadline)) = wait {
+        while !has_new_messages(&page, origin) {
+            match timeout_at(deadline, updates.changed()).await {
+                Ok(Ok(())) => {
+                    page = get_page(
+                        &state,
+                        client_id.as_ref(),
+                        &query,
+                        origin,
+                        &filter,
+                        direction,
+                    )
+                    .await?;
+                }
+                // Timed out, or the hub was dropped
+                Ok(Err(_)) | Err(_) => break,
+            }
+        }
+    }
 
-    let StoreMessages { messages, next_id } = match (&query.origin_id, direction) {
-        (origin_id, Direction::Forward) => {
+    let StoreMessages {
+        messages,
+        cursors,
+        next_id,
+        next_cursor,
+        prev_cursor,
+    } = page;
+
+    increment_counter!(state.metrics, get_queries);
+    increment_counter_with!(state.metrics, served_items, messages.len() as u64);
+
+    let response = GetMessagesResponse {
+        topic: query.topic.clone(),
+        direction,
+        next_id,
+        next_cursor: next_cursor.map(|cursor| cursor.encode()),
+        prev_cursor: prev_cursor.map(|cursor| cursor.encode()),
+        resume_cursor: cursors.last().map(MessageCursor::encode),
+        messages,
+    };
+
+    Ok(Json(response))
+}
+
+/// Builds the filter of a query, any of whose criteria may be unset.
+pub(crate) fn message_filter(
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+    method: Option<Arc<str>>,
+    tag: Option<&str>,
+) -> error::Result<MessageFilter> {
+    if let (Some(since), Some(until)) = (since, until) {
+        if since >= until {
+            return Err(Error::InvalidTimeRange);
+        }
+    }
+
+    let tag = tag
+        .map(|tag| TagPattern::parse(tag).map_err(|e| Error::InvalidTagPattern(tag.to_string(), e)))
+        .transpose()?;
+
+    Ok(MessageFilter {
+        since,
+        until,
+        method,
+        tag,
+    })
+}
+
+/// Whether the page has messages beyond its origin, which is included when
+/// it's a message ID.
+fn has_new_messages(page: &StoreMessages, origin: Origin<'_>) -> bool {
+    match origin {
+        Origin::MessageId(origin_id) => page
+            .messages
+            .iter()
+            .any(|message| message.message_id.as_ref() != origin_id),
+        Origin::Start | Origin::Cursor(_) => !page.messages.is_empty(),
+    }
+}
+
+async fn get_page(
+    state: &AppState,
+    client_id: &str,
+    query: &GetMessagesBody,
+    origin: Origin<'_>,
+    filter: &MessageFilter,
+    direction: Direction,
+) -> error::Result<StoreMessages> {
+    let page = match direction {
+        Direction::Forward => {
             state
                 .messages_store
                 .get_messages_after(
+                    client_id,
                     query.topic.as_ref(),
-                    origin_id.as_deref(),
+                    origin,
+                    filter,
                     query.message_count.limit(),
                 )
                 .await?
         }
-        (origin_id, Direction::Backward) => {
+        Direction::Backward => {
             state
                 .messages_store
                 .get_messages_before(
+                    client_id,
                     query.topic.as_ref(),
-                    origin_id.as_deref(),
+                    origin,
+                    filter,
                     query.message_count.limit(),
                 )
                 .await?
         }
     };
 
-    increment_counter!(state.metrics, get_queries);
-    increment_counter_with!(state.metrics, served_items, messages.len() as u64);
-
-    let response = GetMessagesResponse {
-        topic: query.topic.clone(),
-        direction,
-        next_id,
-        messages,
-    };
-
-    Ok(Json(response))
+    Ok(page)
 }
@@ -7,10 +7,7 @@ use {
         state::{AppState, CachedRegistration},
     },
     axum::{extract::State, Json},
-    relay_rpc::{
-        domain::ClientId,
-        jwt::{JwtBasicClaims, VerifyableClaims},
-    },
+    relay_rpc::domain::ClientId,
     std::sync::Arc,
 };
 
@@ -18,8 +15,7 @@ pub async fn handler(
     State(state): State<Arc<AppState>>,
     AuthBearer(token): AuthBearer,
 ) -> Result<Json<RegisterPayload>, error::Error> {
-    let claims = JwtBasicClaims::try_from_str(&token)?;
-    claims.verify_basic(&state.auth_aud, None)?;
+    let claims = state.auth_policy.verify(&token)?;
     let client_id = ClientId::from(claims.iss);
 
     increment_counter!(state.metrics, registration_cache_invalidation);
@@ -35,10 +31,10 @@ pub async fn handler(
 
     state
         .registration_cache
-        .insert(client_id.into_value(), CachedRegistration {
-            tags: registration.tags.clone(),
-            relay_url: registration.relay_url.clone(),
-        })
+        .insert(
+            client_id.into_value(),
+            CachedRegistration::from(registration.clone()),
+        )
         .await;
 
     Ok(Json(RegisterPayload {
@@ -46,5 +42,6 @@ pub async fn handler(
         append_tags: None,
         remove_tags: None,
         relay_url: registration.relay_url,
+        max_age: registration.max_age,
     }))
 }
@@ -0,0 +1,36 @@
+use {
+    crate::{auth::AuthBearer, error, state::AppState},
+    axum::{extract::State, Json},
+    relay_rpc::domain::ClientId,
+    serde::{Deserialize, Serialize},
+    std::sync::Arc,
+};
+
+/// The response body for the get topics endpoint.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetTopicsResponse {
+    pub topics: Vec<Arc<str>>,
+}
+
+/// The handler for the get topics endpoint.
+///
+/// Lists the topics the client identified by the JWT's `iss` has been
+/// delivered messages on, most recent first.
+pub async fn handler(
+    State(state): State<Arc<AppState>>,
+    AuthBearer(token): AuthBearer,
+) -> Result<Json<GetTopicsResponse>, error::Error> {
+    let claims = state.auth_policy.verify(&token)?;
+    let client_id = ClientId::from(claims.iss);
+
+    let topics = state
+        .messages_store
+        .get_topics(client_id.as_ref())
+        .await?
+        .into_iter()
+        .map(|topic| topic.topic)
+        .collect();
+
+    Ok(Json(GetTopicsResponse { topics }))
+}
@@ -4,18 +4,27 @@ use {
     serde_json::{json, Value},
 };
 
+pub mod delete_messages;
+pub mod delete_registration;
 pub mod get_messages;
 pub mod get_registration;
+pub mod get_topics;
 pub mod health;
 pub mod metrics;
+pub mod query_messages;
 pub mod register;
+pub mod reload;
 pub mod save_message;
+pub mod save_messages;
+pub mod save_watch_events;
+pub mod stream_messages;
 
 #[derive(serde::Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum ErrorLocation {
     Body,
     Header,
+    Query,
 }
 
 #[derive(serde::Serialize)]
@@ -0,0 +1,162 @@
+use {
+    crate::{
+        auth::AuthBearer,
+        error::{self, Error},
+        handlers::get_messages::{message_filter, Direction, MessageCount},
+        increment_counter,
+        increment_counter_with,
+        state::AppState,
+        store::messages::{Message, MessageCursor},
+    },
+    axum::{extract::State, Json},
+    chrono::{DateTime, Utc},
+    relay_rpc::domain::ClientId,
+    serde::{Deserialize, Serialize},
+    std::{collections::HashSet, sync::Arc},
+};
+
+/// The max number of topics queried at once.
+pub const MAX_QUERY_TOPICS: usize = 100;
+
+/////////////////////////
+
+/// The request body for the query messages endpoint.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryMessagesBody {
+    pub topics: Vec<Arc<str>>,
+    /// Continues from a `nextCursor` or `prevCursor` of a previous response
+    /// for the same topics.
+    pub cursor: Option<Arc<str>>,
+    #[serde(default)]
+    pub message_count: MessageCount,
+    pub direction: Option<Direction>,
+    /// Only returns the messages stored at or after this time.
+    pub since: Option<DateTime<Utc>>,
+    /// Only returns the messages stored before this time.
+    pub until: Option<DateTime<Utc>>,
+    /// Only returns the messages with this method.
+    pub method: Option<Arc<str>>,
+    /// Only returns the messages whose relay tag matches this pattern.
+    pub tag: Option<String>,
+}
+
+/////////////////////////
+
+/// The response body for the query messages endpoint.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryMessagesResponse {
+    pub topics: Vec<Arc<str>>,
+    pub direction: Direction,
+    /// Fetches the next page in `direction`, unset if there are no more
+    /// messages.
+    pub next_cursor: Option<String>,
+    /// Fetches the previous page, in the opposite `direction`.
+    pub prev_cursor: Option<String>,
+    /// The messages of all the topics, merged in timestamp order.
+    pub messages: Vec<Message>,
+}
+
+/////////////////////////
+
+/// The handler for the query messages endpoint.
+///
+/// Pages through the messages of many topics at once with a single store
+/// query, for instance to restore all the sessions of a wallet. Like the get
+/// messages endpoint, only the topics the client identified by the JWT's
+/// `iss` has been delivered messages on can be queried.
+pub async fn handler(
+    State(state): State<Arc<AppState>>,
+    AuthBearer(token): AuthBearer,
+    Json(body): Json<QueryMessagesBody>,
+) -> error::Result<Json<QueryMessagesResponse>> {
+    let claims = state.auth_policy.verify(&token)?;
+    let client_id = ClientId::from(claims.iss);
+
+    validate_topics(&body.topics)?;
+
+    // Check each topic like the get messages endpoint does, so that the topics
+    // of the messages stored before topics were tracked are found too
+    for topic in &body.topics {
+        if !state
+            .messages_store
+            .has_topic(client_id.as_ref(), topic.as_ref())
+            .await?
+        {
+            return Err(Error::TopicAccessDenied(topic.to_string()));
+        }
+    }
+
+    let direction = body.direction.unwrap_or(Direction::Forward);
+    let filter = message_filter(
+        body.since,
+        body.until,
+        body.method.clone(),
+        body.tag.as_deref(),
+    )?;
+    let cursor = body
+        .cursor
+        .as_deref()
+        .map(MessageCursor::decode)
+        .transpose()?;
+    let topics: Vec<&str> = body.topics.iter().map(AsRef::as_ref).collect();
+
+    let page = match direction {
+        Direction::Forward => {
+            state
+                .messages_store
+                .get_topics_messages_after(
+                    client_id.as_ref(),
+                    &topics,
+                    cursor.as_ref(),
+                    &filter,
+                    body.message_count.limit(),
+                )
+                .await?
+        }
+        Direction::Backward => {
+            state
+                .messages_store
+                .get_topics_messages_before(
+                    client_id.as_ref(),
+                    &topics,
+                    cursor.as_ref(),
+                    &filter,
+                    body.message_count.limit(),
+                )
+                .await?
+        }
+    };
+
+    increment_counter!(state.metrics, get_queries);
+    increment_counter_with!(state.metrics, served_items, page.messages.len() as u64);
+
+    Ok(Json(QueryMessagesResponse {
+        topics: body.topics,
+        direction,
+        next_cursor: page.next_cursor.map(|cursor| cursor.encode()),
+        prev_cursor: page.prev_cursor.map(|cursor| cursor.encode()),
+        messages: page.messages,
+    }))
+}
+
+fn validate_topics(topics: &[Arc<str>]) -> error::Result<()> {
+    if topics.is_empty() {
+        return Err(Error::InvalidQueryRequest(
+            "at least one topic must be queried".to_string(),
+        ));
+    }
+    if topics.len() > MAX_QUERY_TOPICS {
+        return Err(Error::InvalidQueryRequest(format!(
+            "at most {MAX_QUERY_TOPICS} topics can be queried"
+        )));
+    }
+    if topics.iter().collect::<HashSet<_>>().len() != topics.len() {
+        return Err(Error::InvalidQueryRequest(
+            "topics must not be repeated".to_string(),
+        ));
+    }
+
+    Ok(())
+}
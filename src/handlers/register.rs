@@ -1,17 +1,16 @@
 use {
     crate::{
         auth::AuthBearer,
+        config::MAX_MESSAGE_MAX_AGE,
         error::{self, Error},
         handlers::Response,
         increment_counter,
         log::prelude::*,
         state::{AppState, CachedRegistration},
+        tags::TagPattern,
     },
     axum::{extract::State, Json},
-    relay_rpc::{
-        domain::ClientId,
-        jwt::{JwtBasicClaims, VerifyableClaims},
-    },
+    relay_rpc::domain::ClientId,
     serde::{Deserialize, Serialize},
     std::{collections::HashSet, sync::Arc},
 };
@@ -23,6 +22,10 @@ pub struct RegisterPayload {
     pub append_tags: Option<Vec<Arc<str>>>,
     pub remove_tags: Option<Vec<Arc<str>>>,
     pub relay_url: Arc<str>,
+    /// The maximum age of the stored messages in seconds, overrides the
+    /// server's default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_age: Option<u64>,
 }
 
 pub async fn handler(
@@ -30,17 +33,26 @@ pub async fn handler(
     AuthBearer(token): AuthBearer,
     Json(body): Json<RegisterPayload>,
 ) -> error::Result<Response> {
-    let claims = JwtBasicClaims::try_from_str(&token)?;
-    claims.verify_basic(&state.auth_aud, None)?;
+    let claims = state.auth_policy.verify(&token)?;
     let client_id = ClientId::from(claims.iss);
 
     increment_counter!(state.metrics, register);
 
+    validate_tags(body.tags.iter().chain(body.append_tags.iter()).flatten())?;
+    validate_max_age(body.max_age)?;
+
     if let Some(tags) = body.tags {
         increment_counter!(state.metrics, registration_overwrite);
 
         let tags = tags.into_iter().collect::<HashSet<_>>();
-        overwrite_registration(&state, client_id.clone(), tags, body.relay_url).await?;
+        overwrite_registration(
+            &state,
+            client_id.clone(),
+            tags,
+            body.relay_url,
+            body.max_age,
+        )
+        .await?;
     } else {
         increment_counter!(state.metrics, registration_update);
 
@@ -57,6 +69,7 @@ pub async fn handler(
             append_tags,
             remove_tags,
             body.relay_url,
+            body.max_age,
         )
         .await?;
     }
@@ -64,11 +77,29 @@ pub async fn handler(
     Ok(Response::default())
 }
 
+fn validate_tags<'a>(tags: impl Iterator<Item = &'a Arc<str>>) -> error::Result<()> {
+    for tag in tags {
+        TagPattern::parse(tag).map_err(|e| Error::InvalidTagPattern(tag.to_string(), e))?;
+    }
+
+    Ok(())
+}
+
+fn validate_max_age(max_age: Option<u64>) -> error::Result<()> {
+    match max_age {
+        Some(max_age) if max_age > MAX_MESSAGE_MAX_AGE => {
+            Err(Error::InvalidMaxAge(MAX_MESSAGE_MAX_AGE))
+        }
+        _ => Ok(()),
+    }
+}
+
 async fn overwrite_registration(
     state: &Arc<AppState>,
     client_id: ClientId,
     tags: HashSet<Arc<str>>,
     relay_url: Arc<str>,
+    max_age: Option<u64>,
 ) -> error::Result<Response> {
     state
         .registration_store
@@ -76,15 +107,16 @@ async fn overwrite_registration(
             client_id.value(),
             tags.iter().map(AsRef::as_ref).collect(),
             relay_url.as_ref(),
+            max_age,
         )
         .await?;
 
     state
         .registration_cache
-        .insert(client_id.into_value(), CachedRegistration {
-            tags: tags.into_iter().collect::<Vec<_>>(),
-            relay_url,
-        })
+        .insert(
+            client_id.into_value(),
+            CachedRegistration::new(tags.into_iter().collect(), relay_url, max_age),
+        )
         .await;
 
     Ok(Response::default())
@@ -96,6 +128,7 @@ async fn update_registration(
     append_tags: Option<HashSet<Arc<str>>>,
     remove_tags: Option<HashSet<Arc<str>>>,
     relay_url: Arc<str>,
+    max_age: Option<u64>,
 ) -> error::Result<Response> {
     let append_tags = append_tags.unwrap_or_default();
     let remove_tags = remove_tags.unwrap_or_default();
@@ -120,5 +153,8 @@ async fn update_registration(
         .cloned()
         .collect();
 
-    overwrite_registration(state, client_id, tags, relay_url).await
+    // Keep the current retention when the update doesn't specify one.
+    let max_age = max_age.or(registration.max_age);
+
+    overwrite_registration(state, client_id, tags, relay_url, max_age).await
 }
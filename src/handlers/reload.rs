@@ -0,0 +1,23 @@
+use {
+    crate::{
+        error::{Error, Result},
+        reload::{ReloadReport, Reloader},
+    },
+    axum::{extract::ConnectInfo, Extension, Json},
+    std::{net::SocketAddr, sync::Arc},
+};
+
+/// The handler for the reload endpoint, only served to the loopback
+/// interface as it's unauthenticated.
+pub async fn handler(
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Extension(reloader): Extension<Arc<Reloader>>,
+) -> Result<Json<ReloadReport>> {
+    if !addr.ip().is_loopback() {
+        return Err(Error::ReloadForbidden);
+    }
+
+    let report = reloader.reload().await?;
+
+    Ok(Json(report))
+}
@@ -1,15 +1,17 @@
 use {
     crate::{
-        error,
+        config::MAX_MESSAGE_MAX_AGE,
+        error::{self, Error},
         handlers::Response,
         increment_counter,
         log::prelude::*,
+        quota,
         relay::signature::RequireValidSignature,
         state::{AppState, CachedRegistration},
-        store::{registrations::Registration, StoreError},
-        tags::match_tag,
+        store::{messages::NewMessage, StoreError},
     },
     axum::{extract::State as StateExtractor, Json},
+    chrono::{DateTime, Duration, Utc},
     serde::{Deserialize, Serialize},
     std::sync::Arc,
 };
@@ -33,64 +35,103 @@ pub async fn handler(
 
     increment_counter!(state.metrics, received_items);
 
-    let registration = if let Some(registration) = state
-        .registration_cache
-        .get(payload.client_id.as_ref())
-        .map(|r| Registration {
-            id: None,
-            client_id: payload.client_id.clone(),
-            tags: r.tags,
-            relay_url: r.relay_url,
-        }) {
-        debug!("loaded registration from cache");
-        increment_counter!(state.metrics, cached_registrations);
-        registration
-    } else {
-        debug!("loading registration from database");
-        let registration = match state
-            .registration_store
-            .get_registration(payload.client_id.as_ref())
-            .await
-        {
-            Ok(registration) => registration,
-            Err(StoreError::NotFound(_, _)) => return Ok(Response::default()),
-            Err(e) => return Err(e.into()),
-        };
+    quota::check_message_size(&state, &payload.message)?;
 
-        state
-            .registration_cache
-            .insert(payload.client_id.clone(), CachedRegistration {
-                tags: registration.tags.clone(),
-                relay_url: registration.relay_url.clone(),
-            })
-            .await;
-
-        increment_counter!(state.metrics, fetched_registrations);
-        registration
-    };
+    let registration =
+        if let Some(registration) = state.registration_cache.get(payload.client_id.as_ref()) {
+            debug!("loaded registration from cache");
+            increment_counter!(state.metrics, cached_registrations);
+            registration
+        } else {
+            debug!("loading registration from database");
+            let registration = match state
+                .registration_store
+                .get_registration(payload.client_id.as_ref())
+                .await
+            {
+                Ok(registration) => registration,
+                Err(StoreError::NotFound(_, _)) => return Ok(Response::default()),
+                Err(e) => return Err(e.into()),
+            };
 
-    let tags = registration.tags;
-    for tag in &tags {
-        if match_tag(payload.tag, tag) {
-            debug!("tag matching, storing message");
+            let registration = CachedRegistration::from(registration);
             state
-                .messages_store
-                .upsert_message(
-                    payload.method.as_ref(),
-                    payload.client_id.as_ref(),
-                    payload.topic.as_ref(),
-                    payload.message_id.as_ref(),
-                    payload.message.as_ref(),
-                )
-                .await?;
+                .registration_cache
+                .insert(payload.client_id.clone(), registration.clone())
+                .await;
+
+            increment_counter!(state.metrics, fetched_registrations);
+            registration
+        };
 
-            debug!("message stored, sending ack");
+    let expires_at = expires_at(registration.max_age)?;
+
+    if registration.matches_tag(payload.tag) {
+        debug!("tag matching, storing message");
+        let admitted = quota::admit(&state, &[NewMessage {
+            method: payload.method.as_ref(),
+            tag: payload.tag,
+            client_id: payload.client_id.as_ref(),
+            topic: payload.topic.as_ref(),
+            message_id: payload.message_id.as_ref(),
+            message: payload.message.as_ref(),
+            expires_at,
+        }])
+        .await?;
+        if admitted.contains(&false) {
+            return Err(Error::QuotaExceeded);
+        }
+
+        state
+            .messages_store
+            .upsert_message(
+                payload.method.as_ref(),
+                payload.tag,
+                payload.client_id.as_ref(),
+                payload.topic.as_ref(),
+                payload.message_id.as_ref(),
+                payload.message.as_ref(),
+                expires_at,
+            )
+            .await?;
 
-            increment_counter!(state.metrics, stored_items);
+        state
+            .messages_store
+            .upsert_topic(payload.client_id.as_ref(), payload.topic.as_ref())
+            .await?;
+        state
+            .message_hub
+            .notify(payload.client_id.as_ref(), payload.topic.as_ref());
 
-            return Ok(Response::default());
+        if let Err(e) = quota::evict(&state, [payload.client_id.as_ref()]).await {
+            warn!("failed to evict messages over quota: {e}");
         }
+
+        debug!("message stored, sending ack");
+
+        increment_counter!(state.metrics, stored_items);
+
+        return Ok(Response::default());
     }
 
     Ok(Response::default())
 }
+
+/// When a message stored now expires, given its client's registered max age.
+/// The messages of clients without one expire along the `MESSAGE_MAX_AGE`
+/// configured as they are pruned instead.
+///
+/// Fails if the max age overflows the expiry, which may only happen with
+/// registrations stored before max ages were bounded.
+pub(crate) fn expires_at(max_age: Option<u64>) -> error::Result<Option<DateTime<Utc>>> {
+    let Some(max_age) = max_age else {
+        return Ok(None);
+    };
+
+    i64::try_from(max_age)
+        .ok()
+        .and_then(|max_age| max_age.checked_mul(1000))
+        .and_then(|max_age| Utc::now().checked_add_signed(Duration::milliseconds(max_age)))
+        .map(Some)
+        .ok_or(Error::InvalidMaxAge(MAX_MESSAGE_MAX_AGE))
+}
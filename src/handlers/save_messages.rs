@@ -0,0 +1,221 @@
+use {
+    super::save_message::{expires_at, HistoryPayload},
+    crate::{
+        error::{self, Error},
+        increment_counter_with,
+        log::prelude::*,
+        quota,
+        relay::signature::RequireValidSignature,
+        state::{AppState, CachedRegistration},
+        store::messages::NewMessage,
+    },
+    axum::{extract::State as StateExtractor, Json},
+    serde::{Deserialize, Serialize},
+    std::{
+        collections::{HashMap, HashSet},
+        sync::Arc,
+    },
+};
+
+/// The absolute max number of messages in a batch.
+pub const MAX_BATCH_SIZE: usize = 500;
+
+/// What happened to a message of the batch.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum SaveMessageStatus {
+    Stored,
+    /// The client isn't registered or hasn't registered the message's tag.
+    Skipped,
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SaveMessageResult {
+    pub message_id: Arc<str>,
+    pub status: SaveMessageStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The response body for the save messages endpoint, with one result per
+/// message in the order they were sent.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SaveMessagesResponse {
+    pub results: Vec<SaveMessageResult>,
+}
+
+/// The handler for the batch save messages endpoint.
+///
+/// Stores the batch in a single write, the registrations of its clients being
+/// resolved at once.
+pub async fn handler(
+    StateExtractor(state): StateExtractor<Arc<AppState>>,
+    RequireValidSignature(Json(payloads)): RequireValidSignature<Json<Vec<HistoryPayload>>>,
+) -> error::Result<Json<SaveMessagesResponse>> {
+    debug!(
+        "Received `save_messages` query with {} items",
+        payloads.len()
+    );
+
+    if payloads.len() > MAX_BATCH_SIZE {
+        return Err(Error::BatchTooLarge(MAX_BATCH_SIZE));
+    }
+
+    save_messages(&state, &payloads).await.map(Json)
+}
+
+/// Stores the messages of the batch, skipping those of clients that aren't
+/// registered or haven't registered the message's tag. Messages too large or
+/// over their client's quota fail without failing the batch.
+pub(crate) async fn save_messages(
+    state: &Arc<AppState>,
+    payloads: &[HistoryPayload],
+) -> error::Result<SaveMessagesResponse> {
+    increment_counter_with!(state.metrics, received_items, payloads.len() as u64);
+
+    let registrations = get_registrations(state, payloads).await?;
+
+    let mut results: Vec<SaveMessageResult> = payloads
+        .iter()
+        .map(|payload| SaveMessageResult {
+            message_id: payload.message_id.clone(),
+            status: SaveMessageStatus::Skipped,
+            error: None,
+        })
+        .collect();
+
+    let mut indexes = vec![];
+    let mut messages = vec![];
+    for (index, payload) in payloads.iter().enumerate() {
+        let Some(registration) = registrations.get(payload.client_id.as_ref()) else {
+            continue;
+        };
+        if !registration.matches_tag(payload.tag) {
+            continue;
+        }
+        if let Err(e) = quota::check_message_size(state, &payload.message) {
+            results[index].status = SaveMessageStatus::Failed;
+            results[index].error = Some(e.to_string());
+            continue;
+        }
+        let expires_at = match expires_at(registration.max_age) {
+            Ok(expires_at) => expires_at,
+            Err(e) => {
+                results[index].status = SaveMessageStatus::Failed;
+                results[index].error = Some(e.to_string());
+                continue;
+            }
+        };
+
+        indexes.push(index);
+        messages.push(NewMessage {
+            method: payload.method.as_ref(),
+            tag: payload.tag,
+            client_id: payload.client_id.as_ref(),
+            topic: payload.topic.as_ref(),
+            message_id: payload.message_id.as_ref(),
+            message: payload.message.as_ref(),
+            expires_at,
+        });
+    }
+
+    let admitted = quota::admit(state, &messages).await?;
+    let (indexes, messages): (Vec<usize>, Vec<NewMessage>) = indexes
+        .into_iter()
+        .zip(messages)
+        .zip(admitted)
+        .filter_map(|((index, message), admitted)| {
+            if !admitted {
+                results[index].status = SaveMessageStatus::Failed;
+                results[index].error = Some(Error::QuotaExceeded.to_string());
+            }
+            admitted.then_some((index, message))
+        })
+        .unzip();
+
+    let outcomes = state.messages_store.upsert_messages(&messages).await?;
+
+    let mut topics = HashSet::new();
+    for ((index, message), outcome) in indexes.into_iter().zip(&messages).zip(outcomes) {
+        match outcome {
+            Ok(()) => {
+                results[index].status = SaveMessageStatus::Stored;
+                topics.insert((message.client_id, message.topic));
+            }
+            Err(e) => {
+                warn!("failed to store message {}: {e}", message.message_id);
+                results[index].status = SaveMessageStatus::Failed;
+                results[index].error = Some(e);
+            }
+        }
+    }
+
+    let topics: Vec<(&str, &str)> = topics.into_iter().collect();
+    state.messages_store.upsert_topics(&topics).await?;
+    for &(client_id, topic) in &topics {
+        state.message_hub.notify(client_id, topic);
+    }
+
+    let client_ids: HashSet<&str> = topics.iter().map(|&(client_id, _)| client_id).collect();
+    if let Err(e) = quota::evict(state, client_ids).await {
+        warn!("failed to evict messages over quota: {e}");
+    }
+
+    let stored_count = results
+        .iter()
+        .filter(|result| result.status == SaveMessageStatus::Stored)
+        .count();
+    debug!("{stored_count} messages stored, sending ack");
+    increment_counter_with!(state.metrics, stored_items, stored_count as u64);
+
+    Ok(SaveMessagesResponse { results })
+}
+
+/// Gets the registrations of the batch's clients, from the cache if possible
+/// and with a single query otherwise.
+async fn get_registrations(
+    state: &Arc<AppState>,
+    payloads: &[HistoryPayload],
+) -> error::Result<HashMap<Arc<str>, CachedRegistration>> {
+    let client_ids: HashSet<&Arc<str>> =
+        payloads.iter().map(|payload| &payload.client_id).collect();
+
+    let mut registrations = HashMap::with_capacity(client_ids.len());
+    let mut missing = vec![];
+    for client_id in client_ids {
+        match state.registration_cache.get(client_id.as_ref()) {
+            Some(registration) => {
+                registrations.insert(client_id.clone(), registration);
+            }
+            None => missing.push(client_id.as_ref()),
+        }
+    }
+    increment_counter_with!(
+        state.metrics,
+        cached_registrations,
+        registrations.len() as u64
+    );
+
+    if missing.is_empty() {
+        return Ok(registrations);
+    }
+
+    debug!("loading {} registrations from database", missing.len());
+    let fetched = state.registration_store.get_registrations(&missing).await?;
+    increment_counter_with!(state.metrics, fetched_registrations, fetched.len() as u64);
+
+    for registration in fetched {
+        let client_id = registration.client_id.clone();
+        let cached = CachedRegistration::from(registration);
+        state
+            .registration_cache
+            .insert(client_id.clone(), cached.clone())
+            .await;
+        registrations.insert(client_id, cached);
+    }
+
+    Ok(registrations)
+}
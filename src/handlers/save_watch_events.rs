@@ -0,0 +1,62 @@
+use {
+    super::{
+        save_message::HistoryPayload,
+        save_messages::{save_messages, SaveMessagesResponse, MAX_BATCH_SIZE},
+    },
+    crate::{
+        error::{self, Error},
+        log::prelude::*,
+        relay::watch::{RequireValidWatchEvents, WatchEventClaims, WatchType},
+        state::AppState,
+    },
+    axum::{extract::State as StateExtractor, Json},
+    relay_rpc::domain::{ClientId, DecodedClientId},
+    std::sync::Arc,
+};
+
+/// The methods the signed deliveries of the same messages are stored with.
+const PUBLISH_METHOD: &str = "publish";
+const SUBSCRIPTION_METHOD: &str = "subscription";
+
+/// The handler for the relay's watch webhook.
+///
+/// Stores the watched messages like the batch save messages endpoint does,
+/// the events being authenticated by the relay's JWTs instead of a signature
+/// header.
+pub async fn handler(
+    StateExtractor(state): StateExtractor<Arc<AppState>>,
+    RequireValidWatchEvents(events): RequireValidWatchEvents,
+) -> error::Result<Json<SaveMessagesResponse>> {
+    debug!("Received {} watch events", events.len());
+
+    if events.len() > MAX_BATCH_SIZE {
+        return Err(Error::BatchTooLarge(MAX_BATCH_SIZE));
+    }
+
+    let payloads = events
+        .into_iter()
+        .map(history_payload)
+        .collect::<error::Result<Vec<_>>>()?;
+
+    save_messages(&state, &payloads).await.map(Json)
+}
+
+fn history_payload(event: WatchEventClaims) -> error::Result<HistoryPayload> {
+    let client_id = DecodedClientId::try_from_did_key(&event.basic.sub)
+        .map_err(|e| Error::InvalidWatchEvent(format!("invalid `sub`: {e}")))?;
+    let client_id = ClientId::from(client_id);
+
+    let method = match event.typ {
+        WatchType::Publisher => PUBLISH_METHOD,
+        WatchType::Subscriber => SUBSCRIPTION_METHOD,
+    };
+
+    Ok(HistoryPayload {
+        method: Arc::from(method),
+        client_id: Arc::from(client_id.as_ref()),
+        topic: event.evt.topic,
+        message_id: Arc::from(event.evt.message_id.to_string()),
+        tag: event.evt.tag,
+        message: event.evt.message,
+    })
+}
@@ -0,0 +1,286 @@
+use {
+    crate::{
+        auth::AuthBearer,
+        error::{self, Error},
+        handlers::get_messages::MAX_MESSAGE_COUNT,
+        hub::Subscription,
+        increment_counter_with,
+        log::prelude::*,
+        state::AppState,
+        store::messages::{Message, MessageCursor, MessageFilter, Origin},
+    },
+    axum::{
+        extract::{Query, State},
+        response::sse::{Event, KeepAlive, Sse},
+    },
+    chrono::Utc,
+    futures::{
+        future::select_all,
+        stream::{self, Stream},
+    },
+    relay_rpc::domain::ClientId,
+    serde::{Deserialize, Serialize},
+    std::{collections::HashSet, convert::Infallible, future, sync::Arc, time::Duration},
+    tokio::{sync::mpsc, time::sleep},
+};
+
+/// The max number of topics streamed at once.
+pub const MAX_STREAM_TOPICS: usize = 50;
+
+/// The name of the events carrying a [`StreamedMessage`].
+pub const MESSAGE_EVENT: &str = "message";
+
+/// The max number of streams a client can have open at once.
+pub const MAX_CLIENT_STREAMS: usize = 5;
+
+/// The max number of events buffered for a slow client.
+const STREAM_BUFFER: usize = 64;
+
+const LIST_SEPARATOR: char = ',';
+
+/// The query parameters of the stream messages endpoint.
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamMessagesQuery {
+    /// The comma separated topics to stream.
+    pub topics: String,
+    /// The comma separated cursors to replay each of `topics` from, in the
+    /// same order. Topics without a cursor only stream new messages.
+    pub cursors: Option<String>,
+}
+
+/// The data of the stream's `message` events.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamedMessage {
+    pub topic: Arc<str>,
+    /// Replays the topic from right after this message.
+    pub cursor: String,
+    pub message: Message,
+}
+
+struct TopicStream {
+    topic: Arc<str>,
+    /// Where the next messages start, the topic's start if unset.
+    position: Option<MessageCursor>,
+    updates: Subscription,
+}
+
+/// The handler for the stream messages endpoint.
+///
+/// Streams the messages of the client identified by the JWT's `iss` as
+/// Server-Sent Events, first replaying the topics from their cursors and then
+/// sending the messages as they are stored.
+///
+/// The stream ends as the JWT expires, and as the client's messages are
+/// purged.
+pub async fn handler(
+    State(state): State<Arc<AppState>>,
+    AuthBearer(token): AuthBearer,
+    Query(query): Query<StreamMessagesQuery>,
+) -> error::Result<Sse<impl Stream<Item = Result<Event, Infallible>>>> {
+    let claims = state.auth_policy.verify(&token)?;
+    let expires_in = claims.exp.map(|exp| {
+        Duration::from_secs(
+            (exp - Utc::now().timestamp())
+                .try_into()
+                .unwrap_or_default(),
+        )
+    });
+    let client_id = ClientId::from(claims.iss).into_value();
+
+    let topics = parse_topics(&query)?;
+    for (topic, _) in &topics {
+        if !state
+            .messages_store
+            .has_topic(client_id.as_ref(), topic.as_ref())
+            .await?
+        {
+            return Err(Error::TopicAccessDenied(topic.to_string()));
+        }
+    }
+
+    let Some(mut guard) = state
+        .message_hub
+        .open_stream(client_id.as_ref(), MAX_CLIENT_STREAMS)
+    else {
+        return Err(Error::TooManyStreams(MAX_CLIENT_STREAMS));
+    };
+
+    // Subscribe before reading the store, so that the messages stored in
+    // between aren't missed
+    let mut streams = Vec::with_capacity(topics.len());
+    for (topic, cursor) in topics {
+        let updates = state
+            .message_hub
+            .subscribe(client_id.as_ref(), topic.as_ref());
+        let position = match cursor {
+            Some(cursor) => Some(cursor),
+            // Only stream the messages stored from now on
+            None => state
+                .messages_store
+                .get_messages_before(
+                    client_id.as_ref(),
+                    topic.as_ref(),
+                    Origin::Start,
+                    &MessageFilter::default(),
+                    1,
+                )
+                .await?
+                .cursors
+                .into_iter()
+                .next(),
+        };
+        streams.push(TopicStream {
+            topic,
+            position,
+            updates,
+        });
+    }
+
+    let (sender, receiver) = mpsc::channel(STREAM_BUFFER);
+    tokio::spawn(async move {
+        let expired = async {
+            match expires_in {
+                Some(expires_in) => sleep(expires_in).await,
+                None => future::pending().await,
+            }
+        };
+
+        tokio::select! {
+            result = stream_messages(&state, client_id.as_ref(), streams, &sender) => {
+                if let Err(e) = result {
+                    warn!("Failed to stream messages: {e}");
+                }
+            }
+            _ = guard.closed() => debug!("the client's messages were purged, ending its stream"),
+            _ = expired => debug!("the client's token expired, ending its stream"),
+        }
+    });
+
+    let stream = stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|event| (Ok(event), receiver))
+    });
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+fn parse_topics(
+    query: &StreamMessagesQuery,
+) -> error::Result<Vec<(Arc<str>, Option<MessageCursor>)>> {
+    let topics: Vec<&str> = query.topics.split(LIST_SEPARATOR).map(str::trim).collect();
+    if topics.iter().any(|topic| topic.is_empty()) {
+        return Err(Error::InvalidStreamRequest(
+            "topics must not be empty".to_string(),
+        ));
+    }
+    if topics.len() > MAX_STREAM_TOPICS {
+        return Err(Error::InvalidStreamRequest(format!(
+            "at most {MAX_STREAM_TOPICS} topics can be streamed"
+        )));
+    }
+    if topics.iter().collect::<HashSet<_>>().len() != topics.len() {
+        return Err(Error::InvalidStreamRequest(
+            "topics must not be repeated".to_string(),
+        ));
+    }
+
+    let cursors = match &query.cursors {
+        Some(cursors) => {
+            let cursors: Vec<&str> = cursors.split(LIST_SEPARATOR).map(str::trim).collect();
+            if cursors.len() != topics.len() {
+                return Err(Error::InvalidStreamRequest(
+                    "there must be as many cursors as topics".to_string(),
+                ));
+            }
+            cursors
+                .into_iter()
+                .map(|cursor| {
+                    (!cursor.is_empty())
+                        .then(|| MessageCursor::decode(cursor))
+                        .transpose()
+                })
+                .collect::<Result<Vec<_>, _>>()?
+        }
+        None => vec![None; topics.len()],
+    };
+
+    Ok(topics.into_iter().map(Arc::from).zip(cursors).collect())
+}
+
+async fn stream_messages(
+    state: &AppState,
+    client_id: &str,
+    mut topics: Vec<TopicStream>,
+    sender: &mpsc::Sender<Event>,
+) -> error::Result<()> {
+    for topic in &mut topics {
+        send_messages(state, client_id, topic, sender).await?;
+    }
+
+    loop {
+        let updated = select_all(
+            topics
+                .iter_mut()
+                .map(|topic| Box::pin(topic.updates.changed())),
+        );
+        let index = tokio::select! {
+            _ = sender.closed() => return Ok(()),
+            (result, index, _) = updated => {
+                result.map_err(|_| Error::InternalServerError)?;
+                index
+            }
+        };
+
+        send_messages(state, client_id, &mut topics[index], sender).await?;
+    }
+}
+
+/// Sends the messages stored after the topic's position, and moves it past
+/// them.
+async fn send_messages(
+    state: &AppState,
+    client_id: &str,
+    topic: &mut TopicStream,
+    sender: &mpsc::Sender<Event>,
+) -> error::Result<()> {
+    loop {
+        let origin = match &topic.position {
+            Some(cursor) => Origin::Cursor(cursor),
+            None => Origin::Start,
+        };
+        let page = state
+            .messages_store
+            .get_messages_after(
+                client_id,
+                topic.topic.as_ref(),
+                origin,
+                &MessageFilter::default(),
+                MAX_MESSAGE_COUNT,
+            )
+            .await?;
+        increment_counter_with!(state.metrics, served_items, page.messages.len() as u64);
+
+        for (message, cursor) in page.messages.into_iter().zip(page.cursors) {
+            let data = serde_json::to_string(&StreamedMessage {
+                topic: topic.topic.clone(),
+                cursor: cursor.encode(),
+                message,
+            })?;
+            topic.position = Some(cursor);
+
+            if sender
+                .send(Event::default().event(MESSAGE_EVENT).data(data))
+                .await
+                .is_err()
+            {
+                // The client is gone
+                return Ok(());
+            }
+        }
+
+        if page.next_cursor.is_none() {
+            return Ok(());
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use {
+    std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    },
+    tokio::sync::watch,
+};
+
+type TopicKey = (Arc<str>, Arc<str>);
+type Topics = Arc<Mutex<HashMap<TopicKey, watch::Sender<()>>>>;
+type Clients = Arc<Mutex<HashMap<Arc<str>, watch::Sender<()>>>>;
+
+/// Wakes up the requests waiting for new messages on a client's topics, as the
+/// ingestion path stores them.
+///
+/// Only the fact that there are new messages is broadcast, the waiting
+/// requests then read them from the store.
+#[derive(Default)]
+pub struct MessageHub {
+    topics: Topics,
+    /// The clients with open streams, which are closed as a value is sent.
+    clients: Clients,
+}
+
+impl MessageHub {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Subscribes to the messages stored on a client's topic, the
+    /// subscription is marked as changed for each of them.
+    pub fn subscribe(&self, client_id: &str, topic: &str) -> Subscription {
+        let key: TopicKey = (Arc::from(client_id), Arc::from(topic));
+        let updates = self
+            .topics
+            .lock()
+            .unwrap()
+            .entry(key.clone())
+            .or_insert_with(|| watch::channel(()).0)
+            .subscribe();
+
+        Subscription {
+            topics: self.topics.clone(),
+            key,
+            updates: Some(updates),
+        }
+    }
+
+    /// Notifies the subscribers of a client's topic that messages were stored
+    /// on it.
+    pub fn notify(&self, client_id: &str, topic: &str) {
+        let topics = self.topics.lock().unwrap();
+        let key: TopicKey = (Arc::from(client_id), Arc::from(topic));
+        if let Some(sender) = topics.get(&key) {
+            // Only fails when no one is subscribed, which the subscriptions
+            // prevent by removing their topic as the last one is dropped
+            let _ = sender.send(());
+        }
+    }
+
+    /// The number of topics with subscribers.
+    pub fn topic_count(&self) -> usize {
+        self.topics.lock().unwrap().len()
+    }
+
+    /// Opens a stream of a client's messages, unless `max_streams` of its
+    /// streams are already open.
+    pub fn open_stream(&self, client_id: &str, max_streams: usize) -> Option<StreamGuard> {
+        let mut clients = self.clients.lock().unwrap();
+        let sender = clients
+            .entry(Arc::from(client_id))
+            .or_insert_with(|| watch::channel(()).0);
+        if sender.receiver_count() >= max_streams {
+            return None;
+        }
+
+        Some(StreamGuard {
+            clients: self.clients.clone(),
+            client_id: Arc::from(client_id),
+            closed: Some(sender.subscribe()),
+        })
+    }
+
+    /// Closes the open streams of a client, e.g. as its messages are purged.
+    pub fn close_streams(&self, client_id: &str) {
+        if let Some(sender) = self.clients.lock().unwrap().get(client_id) {
+            let _ = sender.send(());
+        }
+    }
+
+    /// The number of open streams of a client.
+    pub fn stream_count(&self, client_id: &str) -> usize {
+        self.clients
+            .lock()
+            .unwrap()
+            .get(client_id)
+            .map_or(0, |sender| sender.receiver_count())
+    }
+}
+
+/// A subscription to a client's topic, which stops the hub from tracking the
+/// topic once the last one is dropped.
+pub struct Subscription {
+    topics: Topics,
+    key: TopicKey,
+    /// Only unset while dropping.
+    updates: Option<watch::Receiver<()>>,
+}
+
+impl Subscription {
+    /// Waits for messages to be stored on the topic since the last call.
+    pub async fn changed(&mut self) -> Result<(), watch::error::RecvError> {
+        match &mut self.updates {
+            Some(updates) => updates.changed().await,
+            None => unreachable!("the subscription is being dropped"),
+        }
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        // Drop the receiver under the lock, so that no subscription is made to
+        // the topic between the count and the removal
+        let mut topics = self.topics.lock().unwrap();
+        self.updates.take();
+        if matches!(topics.get(&self.key), Some(sender) if sender.receiver_count() == 0) {
+            topics.remove(&self.key);
+        }
+    }
+}
+
+/// An open stream of a client's messages, which stops counting towards the
+/// client's streams once dropped.
+pub struct StreamGuard {
+    clients: Clients,
+    client_id: Arc<str>,
+    /// Only unset while dropping.
+    closed: Option<watch::Receiver<()>>,
+}
+
+impl StreamGuard {
+    /// Waits for the client's streams to be closed.
+    pub async fn closed(&mut self) {
+        match &mut self.closed {
+            // Never fails, the sender being kept while the stream is open
+            Some(closed) => {
+                let _ = closed.changed().await;
+            }
+            None => unreachable!("the stream is being dropped"),
+        }
+    }
+}
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        let mut clients = self.clients.lock().unwrap();
+        self.closed.take();
+        if matches!(clients.get(&self.client_id), Some(sender) if sender.receiver_count() == 0) {
+            clients.remove(&self.client_id);
+        }
+    }
+}
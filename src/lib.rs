@@ -5,16 +5,18 @@ use {
     },
     axum::{
         http,
-        routing::{get, post},
+        routing::{delete, get, post},
+        Extension,
         Router,
     },
     config::Configuration,
     http::Request,
     hyper::Body,
     opentelemetry::{sdk::Resource, KeyValue},
+    rate_limit::{ClientKey, RateLimitLayer},
+    reload::{ConfigSource, RateLimitLayers, Reloader},
     state::AppState,
     std::{net::SocketAddr, sync::Arc},
-    store::mongo::MongoStore,
     tokio::{select, sync::broadcast},
     tower::ServiceBuilder,
     tower_http::{
@@ -27,10 +29,15 @@ pub mod auth;
 pub mod config;
 pub mod error;
 pub mod handlers;
+pub mod hub;
 pub mod log;
 pub mod macros;
 pub mod metrics;
+pub mod quota;
+pub mod rate_limit;
 pub mod relay;
+pub mod reload;
+pub mod retention;
 pub mod state;
 pub mod store;
 pub mod tags;
@@ -39,6 +46,11 @@ pub mod tags;
 pub struct Options {
     pub messages_store: Option<MessagesStorageArc>,
     pub registration_store: Option<RegistrationStorageArc>,
+    /// The handle changing the filter of the logger, following `LOG_LEVEL`.
+    pub log_filter: Option<log::LogFilterHandle>,
+    /// Where the configuration is reloaded from, the environment variables and
+    /// `CONFIG_FILE` by default.
+    pub config_source: Option<ConfigSource>,
 }
 
 pub async fn bootstrap(
@@ -49,53 +61,49 @@ pub async fn bootstrap(
     // Check config is valid and then throw the error if its not
     config.is_valid()?;
 
+    if let Some(log_filter) = &options.log_filter {
+        log::set_filter(log_filter, &config.log_level)?;
+    }
+
     let (messages_store, registration_store) =
         match (options.messages_store, options.registration_store) {
             (Some(messages_store), Some(registration_store)) => {
                 (messages_store, registration_store)
             }
-            (Some(messages_store), None) => {
-                let store = Arc::new(MongoStore::new(&config).await?);
-                (messages_store, store as RegistrationStorageArc)
-            }
-            (None, Some(registration_store)) => {
-                let store = Arc::new(MongoStore::new(&config).await?);
-                (store as MessagesStorageArc, registration_store)
-            }
-            _ => {
-                let store = Arc::new(MongoStore::new(&config).await?);
+            (messages_store, registration_store) => {
+                let (default_messages_store, default_registration_store) =
+                    store::connect(&config).await?;
                 (
-                    store.clone() as MessagesStorageArc,
-                    store as RegistrationStorageArc,
+                    messages_store.unwrap_or(default_messages_store),
+                    registration_store.unwrap_or(default_registration_store),
                 )
             }
         };
 
     let mut state = AppState::new(config.clone(), messages_store, registration_store)?;
 
-    if config.validate_signatures {
-        // Fetch public key so it's cached for the first 6hrs
-        let public_key = state.relay_client.public_key().await;
-        if public_key.is_err() {
-            warn!("Failed initial fetch of Relay's Public Key, this may prevent items validation.")
-        }
-    }
-
-    if state.config.telemetry_prometheus_port.is_some() {
+    if config.telemetry_prometheus_port.is_some() {
         state.set_metrics(metrics::Metrics::new(Resource::new(vec![
             KeyValue::new("service_name", "history-server"),
             KeyValue::new(
                 "service_version",
                 state.build_info.crate_info.version.clone().to_string(),
             ),
-        ]))?);
+        ]))?)?;
     }
 
-    let port = state.config.port;
-    let private_port = state.config.telemetry_prometheus_port.unwrap_or(3001);
+    let port = config.port;
+    let private_port = config.prometheus_port();
 
     let state_arc = Arc::new(state);
 
+    // Keep the relay's public keys fresh, the first refresh being immediate
+    let key_refresh = config
+        .validate_signatures
+        .then(|| tokio::spawn(relay::refresh_public_keys(state_arc.relay_client.clone())));
+    let pruning = tokio::spawn(retention::prune_expired_messages(state_arc.clone()));
+    let watch = tokio::spawn(relay::watch::register_watch(state_arc.clone()));
+
     let global_middleware = ServiceBuilder::new().layer(
         TraceLayer::new_for_http()
             .make_span_with(|request: &Request<Body>| {
@@ -113,28 +121,111 @@ pub async fn bootstrap(
         .allow_origin(Any)
         .allow_headers([http::header::CONTENT_TYPE, http::header::AUTHORIZATION]);
 
-    let app = Router::new()
+    let jwt_key = ClientKey::Jwt(state_arc.auth_policy.clone());
+    let register_limit = RateLimitLayer::new(
+        config.register_rate_limit,
+        jwt_key.clone(),
+        state_arc.metrics.clone(),
+    );
+    let messages_limit = RateLimitLayer::new(
+        config.messages_rate_limit,
+        jwt_key,
+        state_arc.metrics.clone(),
+    );
+    let relay_limit = RateLimitLayer::new(
+        config.relay_rate_limit,
+        ClientKey::Relay(state_arc.clone()),
+        state_arc.metrics.clone(),
+    );
+
+    let reloader = Arc::new(Reloader::new(
+        state_arc.clone(),
+        options.config_source,
+        options.log_filter,
+        RateLimitLayers {
+            register: register_limit.clone(),
+            messages: messages_limit.clone(),
+            relay: relay_limit.clone(),
+        },
+    ));
+    let sighup = tokio::spawn(reload::reload_on_sighup(reloader.clone()));
+
+    let mut app = Router::new()
         .route("/health", get(handlers::health::handler))
-        .route("/messages", get(handlers::get_messages::handler))
-        .route("/messages", post(handlers::save_message::handler))
-        .route("/register", get(handlers::get_registration::handler))
-        .route("/register", post(handlers::register::handler))
+        .route(
+            "/messages",
+            get(handlers::get_messages::handler).route_layer(messages_limit.clone()),
+        )
+        .route(
+            "/messages",
+            post(handlers::save_message::handler).route_layer(relay_limit.clone()),
+        )
+        .route(
+            "/messages",
+            delete(handlers::delete_messages::handler).route_layer(messages_limit.clone()),
+        )
+        .route(
+            "/messages/batch",
+            post(handlers::save_messages::handler).route_layer(relay_limit.clone()),
+        )
+        .route(
+            "/messages/query",
+            post(handlers::query_messages::handler).route_layer(messages_limit.clone()),
+        )
+        .route(
+            "/messages/stream",
+            get(handlers::stream_messages::handler).route_layer(messages_limit.clone()),
+        )
+        .route(
+            "/register",
+            get(handlers::get_registration::handler).route_layer(register_limit.clone()),
+        )
+        .route(
+            "/register",
+            post(handlers::register::handler).route_layer(register_limit.clone()),
+        )
+        .route(
+            "/register",
+            delete(handlers::delete_registration::handler).route_layer(register_limit),
+        )
+        .route(
+            "/topics",
+            get(handlers::get_topics::handler).route_layer(messages_limit),
+        );
+
+    if state_arc.relay_keypair.is_some() {
+        app = app.route(
+            relay::watch::WATCH_WEBHOOK_PATH,
+            post(handlers::save_watch_events::handler).route_layer(relay_limit),
+        );
+    }
+
+    let app = app
         .layer(global_middleware)
         .layer(cors)
         .with_state(state_arc.clone());
 
     let private_app = Router::new()
         .route("/metrics", get(handlers::metrics::handler))
+        .route("/reload", post(handlers::reload::handler))
+        .layer(Extension(reloader))
         .with_state(state_arc);
 
     let addr = SocketAddr::from(([0, 0, 0, 0], port));
     let private_addr = SocketAddr::from(([0, 0, 0, 0], private_port));
 
     select! {
-        _ = axum::Server::bind(&addr).serve(app.into_make_service()) => info!("Server terminating"),
-        _ = axum::Server::bind(&private_addr).serve(private_app.into_make_service()) => info!("Internal Server terminating"),
+        _ = axum::Server::bind(&addr).serve(app.into_make_service_with_connect_info::<SocketAddr>()) => info!("Server terminating"),
+        _ = axum::Server::bind(&private_addr).serve(private_app.into_make_service_with_connect_info::<SocketAddr>()) => info!("Internal Server terminating"),
         _ = shutdown.recv() => info!("Shutdown signal received, killing servers"),
     }
 
+    if let Some(key_refresh) = key_refresh {
+        key_refresh.abort();
+    }
+    pruning.abort();
+    watch.abort();
+    sighup.abort();
+
     Ok(())
 }
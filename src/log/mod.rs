@@ -12,7 +12,7 @@ use {
     opentelemetry::sdk::trace,
     opentelemetry_otlp::WithExportConfig,
     tracing_appender::non_blocking::WorkerGuard,
-    tracing_subscriber::{prelude::*, EnvFilter},
+    tracing_subscriber::{prelude::*, reload, EnvFilter, Registry},
 };
 
 pub mod prelude {
@@ -41,8 +41,12 @@ const ENV_LOG_LEVEL_OTEL: &str = "LOG_LEVEL_OTEL";
 /// The endpoint for the OpenTelemetry gRPC collector, e.g. "localhost:4317".
 const OTEL_EXPORTER_OTLP_ENDPOINT: &str = "OTEL_EXPORTER_OTLP_ENDPOINT";
 
+/// A handle changing the filter of the stderr logger while it's running.
+pub type LogFilterHandle = reload::Handle<EnvFilter, Registry>;
+
 pub struct Logger {
     _guard: WorkerGuard,
+    filter: LogFilterHandle,
 }
 
 impl Logger {
@@ -50,6 +54,8 @@ impl Logger {
         let stderr_filter = EnvFilter::try_from_env(ENV_LOG_LEVEL_STDERR)
             .unwrap_or_else(|_| EnvFilter::new(DEFAULT_LOG_LEVEL_STDERR.to_string()));
 
+        let (stderr_filter, filter) = reload::Layer::new(stderr_filter);
+
         let (writer, guard) = tracing_appender::non_blocking(std::io::stderr());
 
         let logger = tracing_subscriber::fmt::layer()
@@ -85,7 +91,16 @@ impl Logger {
             subscriber.init();
         };
 
-        Ok(Self { _guard: guard })
+        Ok(Self {
+            _guard: guard,
+            filter,
+        })
+    }
+
+    /// The handle changing the filter of the stderr logger, e.g. from the
+    /// configuration's `LOG_LEVEL`.
+    pub fn filter_handle(&self) -> LogFilterHandle {
+        self.filter.clone()
     }
 
     pub fn stop(self) {
@@ -93,6 +108,16 @@ impl Logger {
     }
 }
 
+/// Replaces the filter of the stderr logger by the `directives` of `LOG_LEVEL`.
+pub fn set_filter(handle: &LogFilterHandle, directives: &str) -> crate::error::Result<()> {
+    let filter = EnvFilter::try_new(directives).map_err(|e| {
+        crate::error::Error::InvalidConfiguration(format!("`LOG_LEVEL` is invalid: {e}"))
+    })?;
+    handle
+        .reload(filter)
+        .map_err(|e| crate::error::Error::Anyhow(e.into()))
+}
+
 impl Drop for Logger {
     fn drop(&mut self) {
         opentelemetry::global::shutdown_tracer_provider();
@@ -11,10 +11,14 @@ async fn main() -> error::Result<()> {
     let (_signal, shutdown) = broadcast::channel(1);
     dotenv().ok();
     let config = config::get_config().expect(
-        "Failed to load configuration, please ensure that all environment variables are defined.",
+        "Failed to load configuration, please ensure that all environment variables and the \
+         config file are valid.",
     );
 
-    let options = gilgamesh::Options::default();
+    let options = gilgamesh::Options {
+        log_filter: Some(logger.filter_handle()),
+        ..Default::default()
+    };
     let result = gilgamesh::bootstrap(shutdown, config, options).await;
 
     logger.stop();
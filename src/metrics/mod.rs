@@ -1,7 +1,8 @@
 use {
     crate::error::{Error, Result},
+    chrono::{DateTime, Utc},
     opentelemetry::{
-        metrics::Counter,
+        metrics::{Counter, ObservableGauge},
         sdk::{
             self,
             export::metrics::aggregation,
@@ -11,8 +12,15 @@ use {
     },
     opentelemetry_prometheus::PrometheusExporter,
     prometheus_core::TextEncoder,
+    std::sync::{
+        atomic::{AtomicI64, Ordering},
+        Arc,
+    },
 };
 
+/// The sentinel of [`Metrics::relay_key_fetched_at`] while no key is fetched.
+const NOT_FETCHED: i64 = i64::MIN;
+
 #[derive(Clone)]
 pub struct Metrics {
     pub prometheus_exporter: PrometheusExporter,
@@ -26,9 +34,25 @@ pub struct Metrics {
     pub register: Counter<u64>,
     pub registration_overwrite: Counter<u64>,
     pub registration_update: Counter<u64>,
+    pub unregister: Counter<u64>,
     pub cached_registrations: Counter<u64>,
     pub fetched_registrations: Counter<u64>,
     pub registration_cache_invalidation: Counter<u64>,
+
+    pub pruned_items: Counter<u64>,
+    pub deleted_items: Counter<u64>,
+
+    pub oversized_items: Counter<u64>,
+    pub quota_rejected_items: Counter<u64>,
+    pub quota_evicted_items: Counter<u64>,
+
+    pub relay_key_fetch_failures: Counter<u64>,
+    pub rejected_replays: Counter<u64>,
+    pub rate_limited_requests: Counter<u64>,
+    pub relay_key_age: ObservableGauge<u64>,
+    /// When the relay's public key was last fetched, as a UNIX timestamp
+    /// observed as `relay_key_age`.
+    relay_key_fetched_at: Arc<AtomicI64>,
 }
 
 impl Metrics {
@@ -86,6 +110,11 @@ impl Metrics {
             .with_description("The number of calls to the register method in update mode")
             .init();
 
+        let unregister = meter
+            .u64_counter("unregister")
+            .with_description("The number of calls to the unregister method")
+            .init();
+
         let cached_registrations = meter
             .u64_counter("cached_registrations")
             .with_description("The number of registrations retrieved from the in-memory cache")
@@ -101,6 +130,65 @@ impl Metrics {
             .with_description("The number of registrations cache invalidations")
             .init();
 
+        let pruned_items = meter
+            .u64_counter("pruned_items")
+            .with_description("The number of expired messages pruned from the database")
+            .init();
+
+        let deleted_items = meter
+            .u64_counter("deleted_items")
+            .with_description("The number of messages deleted on clients' request")
+            .init();
+
+        let oversized_items = meter
+            .u64_counter("oversized_items")
+            .with_description("The number of messages rejected as larger than the max size")
+            .init();
+
+        let quota_rejected_items = meter
+            .u64_counter("quota_rejected_items")
+            .with_description("The number of messages rejected as over their client's quota")
+            .init();
+
+        let quota_evicted_items = meter
+            .u64_counter("quota_evicted_items")
+            .with_description("The number of messages evicted to fit their client's quota")
+            .init();
+
+        let relay_key_fetch_failures = meter
+            .u64_counter("relay_key_fetch_failures")
+            .with_description("The number of failed fetches of the relay's public key")
+            .init();
+
+        let rejected_replays = meter
+            .u64_counter("rejected_replays")
+            .with_description("The number of signed requests rejected as stale or replayed")
+            .init();
+
+        let rate_limited_requests = meter
+            .u64_counter("rate_limited_requests")
+            .with_description("The number of requests rejected by the rate limits")
+            .init();
+
+        let relay_key_age = meter
+            .u64_observable_gauge("relay_key_age")
+            .with_description("The number of seconds since the relay's public key was fetched")
+            .init();
+
+        // Registered once here, as the global meter keeps its callbacks
+        let relay_key_fetched_at = Arc::new(AtomicI64::new(NOT_FETCHED));
+        {
+            let relay_key_age = relay_key_age.clone();
+            let relay_key_fetched_at = relay_key_fetched_at.clone();
+            meter.register_callback(move |cx| {
+                let fetched_at = relay_key_fetched_at.load(Ordering::Relaxed);
+                if fetched_at != NOT_FETCHED {
+                    let age = (Utc::now().timestamp() - fetched_at).max(0);
+                    relay_key_age.observe(cx, age as u64, &[]);
+                }
+            })?;
+        }
+
         Ok(Metrics {
             prometheus_exporter,
             received_items,
@@ -110,12 +198,30 @@ impl Metrics {
             register,
             registration_overwrite,
             registration_update,
+            unregister,
             cached_registrations,
             fetched_registrations,
             registration_cache_invalidation,
+            pruned_items,
+            deleted_items,
+            oversized_items,
+            quota_rejected_items,
+            quota_evicted_items,
+            relay_key_fetch_failures,
+            rejected_replays,
+            rate_limited_requests,
+            relay_key_age,
+            relay_key_fetched_at,
         })
     }
 
+    /// Records when the relay's public key was last fetched, if it was.
+    pub fn set_relay_key_fetched_at(&self, fetched_at: Option<DateTime<Utc>>) {
+        let fetched_at = fetched_at.map_or(NOT_FETCHED, |fetched_at| fetched_at.timestamp());
+        self.relay_key_fetched_at
+            .store(fetched_at, Ordering::Relaxed);
+    }
+
     pub fn export(&self) -> Result<String> {
         let data = self.prometheus_exporter.registry().gather();
         TextEncoder::new()
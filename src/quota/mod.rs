@@ -0,0 +1,134 @@
+use {
+    crate::{
+        config::{Configuration, QuotaPolicy},
+        error::{self, Error},
+        increment_counter,
+        increment_counter_with,
+        log::prelude::*,
+        state::AppState,
+        store::messages::{ClientUsage, NewMessage},
+    },
+    std::collections::HashMap,
+};
+
+/// Rejects `message` if it's larger than `MAX_MESSAGE_SIZE`.
+pub fn check_message_size(state: &AppState, message: &str) -> error::Result<()> {
+    match state.config().max_message_size {
+        Some(max) if message.len() > max => {
+            increment_counter!(state.metrics, oversized_items);
+            Err(Error::MessageTooLarge(max))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Checks which of the messages about to be stored fit in their client's
+/// quota, in order.
+///
+/// With the `reject` policy the messages are added to their client's current
+/// usage, so that a batch is admitted up to the quota. Messages replacing a
+/// stored one only count for their change in size, so that redelivered
+/// messages are admitted at the quota. With the `evict` policy only the
+/// messages too large to ever fit are rejected, [`evict`] makes room for the
+/// others once stored.
+pub async fn admit(state: &AppState, messages: &[NewMessage<'_>]) -> error::Result<Vec<bool>> {
+    let config = state.config();
+    if !is_limited(&config) {
+        return Ok(vec![true; messages.len()]);
+    }
+
+    let mut usages: HashMap<&str, ClientUsage> = HashMap::new();
+    // The sizes of the messages stored or admitted so far, by key
+    let mut sizes: HashMap<(&str, &str, &str), Option<u64>> = HashMap::new();
+    let mut admitted = vec![];
+    for message in messages {
+        let mut usage = match config.quota_policy {
+            QuotaPolicy::Reject => match usages.get(message.client_id) {
+                Some(usage) => *usage,
+                None => state.messages_store.get_usage(message.client_id).await?,
+            },
+            QuotaPolicy::Evict => ClientUsage::default(),
+        };
+
+        let key = (message.client_id, message.topic, message.message_id);
+        let replaced = match (config.quota_policy, sizes.get(&key)) {
+            (QuotaPolicy::Reject, Some(size)) => *size,
+            (QuotaPolicy::Reject, None) => {
+                state
+                    .messages_store
+                    .get_message_size(message.client_id, message.topic, message.message_id)
+                    .await?
+            }
+            (QuotaPolicy::Evict, _) => None,
+        };
+        let current = usage;
+        match replaced {
+            Some(replaced) => usage.replace(replaced, message.message),
+            None => usage.add(message.message),
+        }
+
+        if fits(&config, &usage) {
+            usages.insert(message.client_id, usage);
+            sizes.insert(key, Some(message.message.len() as u64));
+            admitted.push(true);
+        } else {
+            usages.insert(message.client_id, current);
+            sizes.insert(key, replaced);
+            admitted.push(false);
+        }
+    }
+
+    let rejected = admitted.iter().filter(|admitted| !**admitted).count();
+    if rejected > 0 {
+        debug!("{rejected} messages rejected over quota");
+        increment_counter_with!(state.metrics, quota_rejected_items, rejected as u64);
+    }
+
+    Ok(admitted)
+}
+
+/// Deletes the oldest messages of the clients over their quota, with the
+/// `evict` policy.
+pub async fn evict<'a>(
+    state: &AppState,
+    client_ids: impl IntoIterator<Item = &'a str>,
+) -> error::Result<()> {
+    let config = state.config();
+    if config.quota_policy != QuotaPolicy::Evict || !is_limited(&config) {
+        return Ok(());
+    }
+
+    for client_id in client_ids {
+        let usage = state.messages_store.get_usage(client_id).await?;
+        if fits(&config, &usage) {
+            continue;
+        }
+
+        let evicted = state
+            .messages_store
+            .evict_messages(
+                client_id,
+                config.client_max_messages,
+                config.client_max_bytes,
+            )
+            .await?;
+        debug!("evicted {evicted} messages over quota");
+        increment_counter_with!(state.metrics, quota_evicted_items, evicted);
+    }
+
+    Ok(())
+}
+
+fn is_limited(config: &Configuration) -> bool {
+    config.client_max_messages.is_some() || config.client_max_bytes.is_some()
+}
+
+/// Whether `usage` is within the clients' quota.
+fn fits(config: &Configuration, usage: &ClientUsage) -> bool {
+    config
+        .client_max_messages
+        .map_or(true, |max| usage.messages <= max)
+        && config
+            .client_max_bytes
+            .map_or(true, |max| usage.bytes <= max)
+}
@@ -0,0 +1,359 @@
+use {
+    crate::{
+        auth::AuthPolicy,
+        error::{self, Error},
+        increment_counter,
+        log::prelude::*,
+        metrics::Metrics,
+        relay::{
+            signature::{read_body, verify_signature, VerifiedSignature},
+            watch::{verify_watch_events, VerifiedWatchEvents, WATCH_WEBHOOK_PATH},
+        },
+        state::{AppState, State},
+    },
+    axum::{
+        body::{Bytes, HttpBody},
+        extract::ConnectInfo,
+        http::{header::AUTHORIZATION, Request},
+        response::{IntoResponse, Response},
+    },
+    futures::future::BoxFuture,
+    moka::future::Cache,
+    relay_rpc::domain::ClientId,
+    serde::Deserialize,
+    std::{
+        net::SocketAddr,
+        str::FromStr,
+        sync::{Arc, Mutex, RwLock},
+        task::{Context, Poll},
+        time::{Duration, Instant},
+    },
+    tower::{Layer, Service},
+};
+
+/// The max number of clients whose buckets are remembered per limit.
+const BUCKETS_CAPACITY: u64 = 1_000_000;
+const RATE_LIMIT_SEPARATOR: char = '/';
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("`{0}` is not valid, expected a `requests/seconds` rate such as `20/60`")]
+pub struct RateLimitError(String);
+
+/// A rate of `requests` per `period`, with bursts of up to `requests`.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub struct RateLimit {
+    pub requests: u32,
+    pub period: Duration,
+}
+
+impl FromStr for RateLimit {
+    type Err = RateLimitError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || RateLimitError(s.to_string());
+
+        let (requests, seconds) = s
+            .trim()
+            .split_once(RATE_LIMIT_SEPARATOR)
+            .ok_or_else(invalid)?;
+        let requests: u32 = requests.trim().parse().map_err(|_| invalid())?;
+        let seconds: u64 = seconds.trim().parse().map_err(|_| invalid())?;
+        if requests == 0 || seconds == 0 {
+            return Err(invalid());
+        }
+
+        Ok(RateLimit {
+            requests,
+            period: Duration::from_secs(seconds),
+        })
+    }
+}
+
+impl TryFrom<String> for RateLimit {
+    type Error = RateLimitError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// The tokens left to a client, one being taken per request.
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl Bucket {
+    fn full(limit: &RateLimit, now: Instant) -> Self {
+        Bucket {
+            tokens: limit.requests as f64,
+            refilled_at: now,
+        }
+    }
+
+    /// Takes a token, or returns how long until one is available.
+    fn take(&mut self, limit: &RateLimit, now: Instant) -> Result<(), Duration> {
+        let capacity = limit.requests as f64;
+        let rate = capacity / limit.period.as_secs_f64();
+
+        let elapsed = now.saturating_duration_since(self.refilled_at);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * rate).min(capacity);
+        self.refilled_at = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / rate))
+        }
+    }
+}
+
+struct Limiter {
+    limit: RateLimit,
+    /// The clients' buckets, those idle for a whole period being full again.
+    buckets: Cache<Arc<str>, Arc<Mutex<Bucket>>>,
+    metrics: Option<Metrics>,
+}
+
+impl Limiter {
+    async fn take(&self, key: Arc<str>) -> Result<(), Duration> {
+        let now = Instant::now();
+        let bucket = self
+            .buckets
+            .get_with(key, async {
+                Arc::new(Mutex::new(Bucket::full(&self.limit, now)))
+            })
+            .await;
+
+        let mut bucket = bucket.lock().unwrap();
+        bucket.take(&self.limit, now)
+    }
+}
+
+/// How a layer identifies the clients of its routes, by their IP address
+/// when they can't be authenticated.
+#[derive(Clone)]
+pub enum ClientKey {
+    /// By the `iss` of their JWT bearer token, once verified with the policy.
+    Jwt(AuthPolicy),
+    /// The requests signed by the relay, and its verified watch events,
+    /// aren't limited, the relay's deliveries being only limited by its own
+    /// pace. The verification is passed on to the routes' extractors as a
+    /// request extension, and the requests failing it are rejected once
+    /// limited by their IP address.
+    Relay(Arc<AppState>),
+}
+
+impl ClientKey {
+    /// Identifies the client of `request`, unset if it isn't limited. The
+    /// request is returned as its body may have been read, or the error it's
+    /// rejected with if it failed the relay's authentication.
+    async fn identify<B>(
+        &self,
+        request: Request<B>,
+    ) -> error::Result<(Option<Arc<str>>, error::Result<Request<B>>)>
+    where
+        B: HttpBody + From<Bytes> + Send,
+        B::Data: Send,
+    {
+        match self {
+            ClientKey::Jwt(policy) => {
+                let issuer = request
+                    .headers()
+                    .get(AUTHORIZATION)
+                    .and_then(|header| header.to_str().ok())
+                    .and_then(|header| header.strip_prefix("Bearer "))
+                    .and_then(|token| policy.verify(token).ok())
+                    .map(|claims| Arc::from(format!("iss:{}", ClientId::from(claims.iss))));
+                Ok((issuer.or_else(|| client_ip(&request)), Ok(request)))
+            }
+            ClientKey::Relay(state) if state.validate_signatures() => {
+                let (mut parts, body) = request.into_parts();
+                let bytes = read_body(&parts, body, state.config().max_body_size).await?;
+                let verified = if parts.uri.path() == WATCH_WEBHOOK_PATH {
+                    verify_watch_events(state, &bytes).await.map(|events| {
+                        parts.extensions.insert(VerifiedWatchEvents(events));
+                    })
+                } else {
+                    verify_signature(state, &parts, &bytes).await.map(|()| {
+                        parts.extensions.insert(VerifiedSignature);
+                    })
+                };
+
+                let request = Request::from_parts(parts, bytes.into());
+                Ok(match verified {
+                    Ok(()) => (None, Ok(request)),
+                    Err(e) => (client_ip(&request), Err(e)),
+                })
+            }
+            ClientKey::Relay(_) => Ok((client_ip(&request), Ok(request))),
+        }
+    }
+}
+
+fn client_ip<B>(request: &Request<B>) -> Option<Arc<str>> {
+    request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| Arc::from(format!("ip:{}", addr.ip())))
+}
+
+/// Limits the rate of the requests of each client with a token bucket.
+///
+/// Clients are identified as set by the layer's [`ClientKey`], and by their
+/// IP address otherwise. The server must be served with its
+/// `ConnectInfo<SocketAddr>` for the latter. Routes sharing a layer share its
+/// buckets, and requests aren't limited if the layer has no limit.
+#[derive(Clone)]
+pub struct RateLimitLayer {
+    limiter: Arc<RwLock<Option<Arc<Limiter>>>>,
+    client_key: ClientKey,
+    metrics: Option<Metrics>,
+}
+
+impl RateLimitLayer {
+    pub fn new(limit: Option<RateLimit>, client_key: ClientKey, metrics: Option<Metrics>) -> Self {
+        let layer = Self {
+            limiter: Arc::new(RwLock::new(None)),
+            client_key,
+            metrics,
+        };
+        layer.set_limit(limit);
+        layer
+    }
+
+    /// Changes the limit of the routes sharing the layer. The buckets are
+    /// full again if the limit changed.
+    pub fn set_limit(&self, limit: Option<RateLimit>) {
+        let mut limiter = self.limiter.write().unwrap();
+        if limiter.as_ref().map(|limiter| limiter.limit) == limit {
+            return;
+        }
+
+        *limiter = limit.map(|limit| {
+            Arc::new(Limiter {
+                limit,
+                buckets: Cache::builder()
+                    .max_capacity(BUCKETS_CAPACITY)
+                    .time_to_idle(limit.period)
+                    .build(),
+                metrics: self.metrics.clone(),
+            })
+        });
+    }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimitService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimitService {
+            inner,
+            limiter: self.limiter.clone(),
+            client_key: self.client_key.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RateLimitService<S> {
+    inner: S,
+    limiter: Arc<RwLock<Option<Arc<Limiter>>>>,
+    client_key: ClientKey,
+}
+
+impl<S, B> Service<Request<B>> for RateLimitService<S>
+where
+    S: Service<Request<B>, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    B: HttpBody + From<Bytes> + Send + 'static,
+    B::Data: Send,
+{
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+    type Response = Response;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let limiter = self.limiter.read().unwrap().clone();
+        let Some(limiter) = limiter else {
+            return Box::pin(self.inner.call(request));
+        };
+
+        // The clone may not be ready, the ready service is taken instead
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let client_key = self.client_key.clone();
+
+        Box::pin(async move {
+            let path = request.uri().path().to_string();
+            let (key, request) = match client_key.identify(request).await {
+                Ok(identified) => identified,
+                Err(e) => return Ok(e.into_response()),
+            };
+
+            if let Some(key) = key {
+                if let Err(retry_after) = limiter.take(key).await {
+                    debug!("rate limited request to {path}");
+                    increment_counter!(limiter.metrics, rate_limited_requests);
+                    return Ok(
+                        Error::RateLimited(retry_after.as_secs_f64().ceil() as u64).into_response()
+                    );
+                }
+            }
+
+            match request {
+                Ok(request) => inner.call(request).await,
+                Err(e) => Ok(e.into_response()),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            "20/60".parse(),
+            Ok(RateLimit {
+                requests: 20,
+                period: Duration::from_secs(60),
+            })
+        );
+        assert_eq!(" 5 / 1 ".parse::<RateLimit>().unwrap().requests, 5);
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        for limit in ["", "20", "20/", "/60", "0/60", "20/0", "-1/60", "20/1.5"] {
+            assert!(limit.parse::<RateLimit>().is_err(), "{limit}");
+        }
+    }
+
+    #[test]
+    fn test_bucket() {
+        let limit: RateLimit = "2/10".parse().unwrap();
+        let start = Instant::now();
+        let mut bucket = Bucket::full(&limit, start);
+
+        assert!(bucket.take(&limit, start).is_ok());
+        assert!(bucket.take(&limit, start).is_ok());
+        assert_eq!(bucket.take(&limit, start), Err(Duration::from_secs(5)));
+
+        // A token is refilled every 5 seconds, up to the burst size
+        assert!(bucket.take(&limit, start + Duration::from_secs(5)).is_ok());
+        assert!(bucket.take(&limit, start + Duration::from_secs(5)).is_err());
+        let later = start + Duration::from_secs(60);
+        assert!(bucket.take(&limit, later).is_ok());
+        assert!(bucket.take(&limit, later).is_ok());
+        assert!(bucket.take(&limit, later).is_err());
+    }
+}
@@ -1,48 +1,181 @@
 use {
+    crate::{error::Error, increment_counter, log::prelude::*, metrics::Metrics},
     chrono::{DateTime, Duration, Utc},
     ed25519_dalek::PublicKey,
-    std::ops::Add,
+    serde_json::{json, Value},
+    std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+        RwLock,
+    },
+    tokio::time::{interval, MissedTickBehavior},
 };
 
 pub mod signature;
+pub mod watch;
 
+/// How long the fetched keys are fresh, stale keys are still served while
+/// being refreshed in the background.
 const PUBLIC_KEY_TTL_HOURS: i64 = 6;
+/// The interval between two background refreshes of the keys.
+const PUBLIC_KEY_REFRESH_MINUTES: u64 = 60;
+/// How long a key is still accepted after the relay rotated it.
+const PUBLIC_KEY_ROTATION_GRACE_HOURS: i64 = 24;
+/// The max number of keys accepted at once.
+const MAX_PUBLIC_KEYS: usize = 3;
 
+struct CachedKey {
+    key: PublicKey,
+    /// When the relay stopped serving the key, if it has.
+    rotated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Default)]
+struct KeyCache {
+    /// The relay's keys, the current one first.
+    keys: Vec<CachedKey>,
+    last_fetched: Option<DateTime<Utc>>,
+}
+
+impl KeyCache {
+    fn is_stale(&self) -> bool {
+        self.last_fetched.map_or(true, |last_fetched| {
+            last_fetched + Duration::hours(PUBLIC_KEY_TTL_HOURS) < Utc::now()
+        })
+    }
+
+    fn keys(&self) -> Vec<PublicKey> {
+        self.keys.iter().map(|cached| cached.key).collect()
+    }
+
+    fn update(&mut self, key: PublicKey) {
+        let now = Utc::now();
+        self.last_fetched = Some(now);
+
+        if self.keys.first().map(|cached| cached.key) == Some(key) {
+            return;
+        }
+
+        if let Some(current) = self.keys.first_mut() {
+            info!("the relay's public key was rotated");
+            current.rotated_at = Some(now);
+        }
+        self.keys.retain(|cached| cached.key != key);
+        self.keys.insert(0, CachedKey {
+            key,
+            rotated_at: None,
+        });
+        self.keys.retain(|cached| {
+            cached.rotated_at.map_or(true, |rotated_at| {
+                rotated_at + Duration::hours(PUBLIC_KEY_ROTATION_GRACE_HOURS) > now
+            })
+        });
+        self.keys.truncate(MAX_PUBLIC_KEYS);
+    }
+}
+
+/// A client of the relay's HTTP API, its clones share the relay's URL and the
+/// cache of its public keys.
 #[derive(Clone)]
 pub struct RelayClient {
     http_client: reqwest::Client,
-    base_url: String,
-    public_key: Option<PublicKey>,
-    public_key_last_fetched: DateTime<Utc>,
+    base_url: Arc<RwLock<String>>,
+    keys: Arc<RwLock<KeyCache>>,
+    refreshing: Arc<AtomicBool>,
+    metrics: Option<Metrics>,
 }
 
 impl RelayClient {
     pub fn new(base_url: String) -> RelayClient {
         RelayClient {
             http_client: reqwest::Client::new(),
-            base_url,
-            public_key: None,
-            public_key_last_fetched: DateTime::<Utc>::MIN_UTC,
-        }
-    }
-
-    /// Fetches the public key with a TTL
-    pub async fn public_key(&mut self) -> crate::error::Result<PublicKey> {
-        if let Some(public_key) = self.public_key {
-            // TTL Not exceeded
-            if self
-                .public_key_last_fetched
-                .add(Duration::hours(PUBLIC_KEY_TTL_HOURS))
-                < Utc::now()
-            {
-                return Ok(public_key);
+            base_url: Arc::new(RwLock::new(base_url)),
+            keys: Arc::new(RwLock::new(KeyCache::default())),
+            refreshing: Arc::new(AtomicBool::new(false)),
+            metrics: None,
+        }
+    }
+
+    pub fn set_metrics(&mut self, metrics: Metrics) {
+        metrics.set_relay_key_fetched_at(self.keys.read().unwrap().last_fetched);
+        self.metrics = Some(metrics);
+    }
+
+    /// Points the client to another relay, whose public keys are fetched on
+    /// the next verification.
+    pub fn set_base_url(&self, base_url: String) {
+        let mut current = self.base_url.write().unwrap();
+        if *current != base_url {
+            *current = base_url;
+            *self.keys.write().unwrap() = KeyCache::default();
+            if let Some(metrics) = &self.metrics {
+                metrics.set_relay_key_fetched_at(None);
             }
         }
+    }
 
-        let public_key = self.fetch_public_key().await?;
-        self.public_key = Some(public_key);
-        self.public_key_last_fetched = Utc::now();
-        Ok(public_key)
+    /// The current public key of the relay.
+    pub async fn public_key(&self) -> crate::error::Result<PublicKey> {
+        self.public_keys()
+            .await?
+            .first()
+            .copied()
+            .ok_or(Error::InternalServerError)
+    }
+
+    /// The public keys of the relay, the current one first followed by the
+    /// recently rotated ones.
+    ///
+    /// The keys are only fetched when none is cached yet, stale keys are
+    /// served while being refreshed in the background.
+    pub async fn public_keys(&self) -> crate::error::Result<Vec<PublicKey>> {
+        let (keys, is_stale) = {
+            let cache = self.keys.read().unwrap();
+            (cache.keys(), cache.is_stale())
+        };
+
+        if keys.is_empty() {
+            self.refresh_public_key().await?;
+            return Ok(self.keys.read().unwrap().keys());
+        }
+
+        if is_stale {
+            self.spawn_refresh();
+        }
+
+        Ok(keys)
+    }
+
+    /// Fetches the relay's current public key, and caches it.
+    pub async fn refresh_public_key(&self) -> crate::error::Result<()> {
+        match self.fetch_public_key().await {
+            Ok(key) => {
+                let mut keys = self.keys.write().unwrap();
+                keys.update(key);
+                if let Some(metrics) = &self.metrics {
+                    metrics.set_relay_key_fetched_at(keys.last_fetched);
+                }
+                Ok(())
+            }
+            Err(e) => {
+                increment_counter!(self.metrics, relay_key_fetch_failures);
+                Err(e)
+            }
+        }
+    }
+
+    fn spawn_refresh(&self) {
+        if self.refreshing.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        let client = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) = client.refresh_public_key().await {
+                warn!("Failed to refresh the relay's public key: {e}");
+            }
+            client.refreshing.store(false, Ordering::Release);
+        });
     }
 
     async fn fetch_public_key(&self) -> crate::error::Result<PublicKey> {
@@ -57,7 +190,59 @@ impl RelayClient {
         Ok(public_key)
     }
 
+    /// Registers a watch with the relay's `irn_watchRegister` RPC method, the
+    /// relay then delivers the watched messages to the registered webhook.
+    pub async fn watch_register(
+        &self,
+        auth: &str,
+        register_auth: &str,
+    ) -> crate::error::Result<()> {
+        let request = json!({
+            "id": Utc::now().timestamp_micros(),
+            "jsonrpc": "2.0",
+            "method": watch::WATCH_REGISTER_METHOD,
+            "params": {
+                "registerAuth": register_auth,
+            },
+        });
+
+        let response: Value = self
+            .http_client
+            .post(self.get_url("rpc"))
+            .bearer_auth(auth)
+            .json(&request)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        match response.get("error") {
+            Some(error) => Err(Error::RelayRpc(error.to_string())),
+            None => Ok(()),
+        }
+    }
+
     fn get_url(&self, path: &str) -> String {
-        format!("{}/{}", self.base_url, path)
+        format!("{}/{}", self.base_url.read().unwrap(), path)
+    }
+}
+
+/// Periodically refreshes the relay's public keys, so they're rarely stale
+/// when requests are verified.
+pub async fn refresh_public_keys(relay_client: RelayClient) {
+    let mut interval = interval(std::time::Duration::from_secs(
+        PUBLIC_KEY_REFRESH_MINUTES * 60,
+    ));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        interval.tick().await;
+
+        if let Err(e) = relay_client.refresh_public_key().await {
+            warn!(
+                "Failed to refresh the relay's public key, this may prevent items validation: {e}"
+            );
+        }
     }
 }
@@ -1,26 +1,48 @@
 use {
     crate::{
         error::Error::{
+            self,
             FromRequestError,
+            InvalidAuthentication,
             MissingAllSignatureHeader,
             MissingSignatureHeader,
             MissingTimestampHeader,
+            PayloadTooLarge,
+            ReplayedSignature,
+            StaleSignature,
             ToBytesError,
         },
+        increment_counter,
         log::prelude::*,
         state::State,
     },
     async_trait::async_trait,
-    axum::{body, extract::FromRequest, http::Request},
+    axum::{
+        body::{self, Bytes, HttpBody},
+        extract::FromRequest,
+        http::{header::CONTENT_LENGTH, request::Parts, Request},
+    },
+    chrono::Utc,
     ed25519_dalek::{PublicKey, Signature, Verifier},
+    hyper::body::Buf,
+    std::sync::Arc,
     tracing::span,
 };
 
 pub const SIGNATURE_HEADER_NAME: &str = "X-Ed25519-Signature";
 pub const TIMESTAMP_HEADER_NAME: &str = "X-Ed25519-Timestamp";
 
+/// Extracts `T` from a request signed by the relay.
+///
+/// The body is buffered up to `MAX_BODY_SIZE` bytes and its exact bytes are
+/// verified, so `T` can be any extractor consuming the body, e.g. `Json<_>`
+/// or `Bytes`. The signature isn't verified again if the rate limit layer
+/// already did.
 pub struct RequireValidSignature<T>(pub T);
 
+/// Marks a request whose signature was verified by [`verify_signature`].
+pub(crate) struct VerifiedSignature;
+
 #[async_trait]
 impl<S, B, T> FromRequest<S, B> for RequireValidSignature<T>
 where
@@ -34,68 +56,170 @@ where
     type Rejection = crate::error::Error;
 
     async fn from_request(req: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
-        if !state.validate_signatures() {
-            // Skip signature validation
-            return T::from_request(req, state)
-                .await
-                .map(Self)
-                .map_err(|_| FromRequestError);
+        let (parts, body_raw) = req.into_parts();
+        let bytes = read_body(&parts, body_raw, state.config().max_body_size).await?;
+
+        if state.validate_signatures() && parts.extensions.get::<VerifiedSignature>().is_none() {
+            verify_signature(state, &parts, &bytes).await?;
         }
 
-        let s = span!(tracing::Level::DEBUG, "validate_signature");
-        let _ = s.enter();
+        let req = Request::<B>::from_parts(parts, bytes.into());
+        T::from_request(req, state)
+            .await
+            .map(Self)
+            .map_err(|_| FromRequestError)
+    }
+}
 
-        let public_key = state.relay_client().public_key().await?;
+/// Verifies that a request is signed by the relay, with any of its keys, and
+/// that its signature is fresh and not replayed. The signature is then marked
+/// as seen.
+pub async fn verify_signature<S: State>(
+    state: &S,
+    parts: &Parts,
+    bytes: &[u8],
+) -> Result<(), Error> {
+    let s = span!(tracing::Level::DEBUG, "validate_signature");
+    let _ = s.enter();
 
-        let (parts, body_raw) = req.into_parts();
-        let bytes = hyper::body::to_bytes(body_raw)
-            .await
-            .map_err(|_| ToBytesError)?;
-        let body = String::from_utf8_lossy(&bytes);
-
-        let signature_header = parts
-            .headers
-            .get(SIGNATURE_HEADER_NAME)
-            .and_then(|header| header.to_str().ok());
-
-        let timestamp_header = parts
-            .headers
-            .get(TIMESTAMP_HEADER_NAME)
-            .and_then(|header| header.to_str().ok());
-
-        match (signature_header, timestamp_header) {
-            (Some(signature), Some(timestamp)) => {
-                match signature_is_valid(signature, timestamp, &body, &public_key).await {
-                    Ok(_) => {
-                        let req = Request::<B>::from_parts(parts, bytes.into());
-                        Ok(T::from_request(req, state)
-                            .await
-                            .map(Self)
-                            .map_err(|_| FromRequestError)?)
-                    }
-                    Err(err) => {
-                        warn!("relay signature is not valid: {err:?}");
-                        Err(err)
+    let public_keys = state.relay_client().public_keys().await?;
+    let metrics = state.metrics();
+
+    let signature_header = parts
+        .headers
+        .get(SIGNATURE_HEADER_NAME)
+        .and_then(|header| header.to_str().ok());
+
+    let timestamp_header = parts
+        .headers
+        .get(TIMESTAMP_HEADER_NAME)
+        .and_then(|header| header.to_str().ok());
+
+    match (signature_header, timestamp_header) {
+        (Some(signature), Some(timestamp)) => {
+            // Any of the relay's keys is accepted, as the previous key keeps
+            // signing in-flight requests while the relay rotates it
+            let mut is_valid = Ok(false);
+            for public_key in &public_keys {
+                is_valid = signature_is_valid(signature, timestamp, bytes, public_key).await;
+                if !matches!(is_valid, Ok(false)) {
+                    break;
+                }
+            }
+
+            match is_valid {
+                Ok(true) => {
+                    if let Err(err) = check_not_replayed(state, signature, timestamp).await {
+                        warn!("relay signature is replayed: {err:?}");
+                        increment_counter!(metrics, rejected_replays);
+                        return Err(err);
                     }
+
+                    Ok(())
+                }
+                Ok(false) => {
+                    warn!("relay signature is not valid");
+                    Err(InvalidAuthentication)
+                }
+                Err(err) => {
+                    warn!("relay signature is not valid: {err:?}");
+                    Err(err)
                 }
             }
-            (Some(_), None) => Err(MissingTimestampHeader),
-            (None, Some(_)) => Err(MissingSignatureHeader),
-            (None, None) => Err(MissingAllSignatureHeader),
         }
+        (Some(_), None) => Err(MissingTimestampHeader),
+        (None, Some(_)) => Err(MissingSignatureHeader),
+        (None, None) => Err(MissingAllSignatureHeader),
+    }
+}
+
+/// Rejects signatures whose timestamp is outside the freshness window, and
+/// those already seen within it.
+async fn check_not_replayed<S: State>(
+    state: &S,
+    signature: &str,
+    timestamp: &str,
+) -> Result<(), Error> {
+    let max_age = state.config().signature_max_age;
+    if !is_fresh(timestamp, max_age) {
+        return Err(StaleSignature(max_age));
+    }
+
+    if !mark_seen(state, signature_key(signature, timestamp)).await {
+        return Err(ReplayedSignature);
     }
+
+    Ok(())
+}
+
+/// Remembers a signature as seen, returning whether it wasn't already.
+pub(crate) async fn mark_seen<S: State>(state: &S, key: Arc<str>) -> bool {
+    // The cache only keeps the first of concurrent insertions, so the
+    // signature was already seen if another token was kept
+    let token = Arc::new(());
+    let seen = state
+        .seen_signatures()
+        .get_with(key, async { token.clone() })
+        .await;
+
+    Arc::ptr_eq(&seen, &token)
+}
+
+fn is_fresh(timestamp: &str, max_age: u64) -> bool {
+    timestamp.parse::<i64>().map_or(false, |timestamp| {
+        (Utc::now().timestamp() - timestamp).unsigned_abs() <= max_age
+    })
+}
+
+fn signature_key(signature: &str, timestamp: &str) -> Arc<str> {
+    Arc::from(format!("{signature}.{timestamp}"))
 }
 
+/// Checks the signature of a body, over its exact bytes.
 pub async fn signature_is_valid(
     signature: &str,
     timestamp: &str,
-    body: &str,
+    body: &[u8],
     public_key: &PublicKey,
 ) -> Result<bool, crate::error::Error> {
-    let sig_body = format!("{}.{}.{}", timestamp, body.len(), body);
+    let mut sig_body = format!("{}.{}.", timestamp, body.len()).into_bytes();
+    sig_body.extend_from_slice(body);
 
     let sig_bytes = hex::decode(signature)?;
     let sig = Signature::from_bytes(&sig_bytes)?;
 
-    Ok(public_key.verify(sig_body.as_bytes(), &sig).is_ok())
+    Ok(public_key.verify(&sig_body, &sig).is_ok())
+}
+
+/// Buffers a request body, rejecting it as soon as it's known to be larger
+/// than `max_size` bytes.
+pub async fn read_body<B>(parts: &Parts, body: B, max_size: usize) -> Result<Bytes, Error>
+where
+    B: HttpBody,
+{
+    let content_length = parts
+        .headers
+        .get(CONTENT_LENGTH)
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.parse::<usize>().ok());
+    if content_length.map_or(false, |length| length > max_size) {
+        return Err(PayloadTooLarge(max_size));
+    }
+
+    tokio::pin!(body);
+    let mut bytes = Vec::with_capacity(content_length.unwrap_or_default());
+    while let Some(chunk) = body.as_mut().data().await {
+        let mut chunk = chunk.map_err(|_| ToBytesError)?;
+        if bytes.len() + chunk.remaining() > max_size {
+            return Err(PayloadTooLarge(max_size));
+        }
+        while chunk.has_remaining() {
+            let slice = chunk.chunk();
+            bytes.extend_from_slice(slice);
+            let length = slice.len();
+            chunk.advance(length);
+        }
+    }
+
+    Ok(bytes.into())
 }
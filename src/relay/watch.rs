@@ -0,0 +1,312 @@
+use {
+    crate::{
+        error::{
+            self,
+            Error::{
+                self,
+                FromRequestError,
+                InternalServerError,
+                InvalidAuthentication,
+                ReplayedWatchEvent,
+                StaleWatchEvent,
+            },
+        },
+        increment_counter,
+        log::prelude::*,
+        relay::signature::{mark_seen, read_body},
+        state::{AppState, State},
+    },
+    async_trait::async_trait,
+    axum::{body, extract::FromRequest, http::Request},
+    chrono::{Duration, Utc},
+    data_encoding::BASE64URL_NOPAD,
+    relay_rpc::{
+        auth::{
+            ed25519_dalek::{Keypair, Signer},
+            AuthToken,
+        },
+        domain::DecodedClientId,
+        jwt::{JwtBasicClaims, VerifyableClaims},
+    },
+    serde::{Deserialize, Serialize},
+    std::{collections::HashSet, sync::Arc},
+    tokio::time::sleep,
+};
+
+pub const WATCH_REGISTER_METHOD: &str = "irn_watchRegister";
+pub const WATCH_EVENT_ACTION: &str = "irn_watchEvent";
+pub const WATCH_WEBHOOK_PATH: &str = "/messages/watch";
+
+/// How long a watch registration lasts, it's renewed halfway through.
+const WATCH_TTL_DAYS: i64 = 30;
+/// How long to wait before retrying a failed watch registration.
+const WATCH_RETRY_SECONDS: u64 = 60;
+
+const JWT_HEADER: &str = r#"{"alg":"EdDSA","typ":"JWT"}"#;
+
+/// The messages a watch is notified of, those published by the watched
+/// clients or those delivered to them.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum WatchType {
+    Publisher,
+    Subscriber,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum WatchStatus {
+    Accepted,
+    Queued,
+    Delivered,
+}
+
+/// The claims of the JWT registering a watch with the relay.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WatchRegisterClaims {
+    #[serde(flatten)]
+    pub basic: JwtBasicClaims,
+    /// The action, always `irn_watchRegister`.
+    pub act: String,
+    pub typ: WatchType,
+    /// The webhook URL the events are delivered to.
+    pub whu: String,
+    /// The topics to watch, all of them if empty.
+    pub tft: Vec<String>,
+    /// The statuses of the messages to be notified of.
+    pub sts: Vec<WatchStatus>,
+}
+
+impl VerifyableClaims for WatchRegisterClaims {
+    fn basic(&self) -> &JwtBasicClaims {
+        &self.basic
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchEventPayload {
+    pub message_id: u64,
+    pub status: WatchStatus,
+    pub topic: Arc<str>,
+    pub message: Arc<str>,
+    pub published_at: i64,
+    pub tag: u32,
+}
+
+/// The claims of a JWT delivered by the relay to the watch's webhook.
+///
+/// The JWT is issued by the relay for the watch's client (`aud`), its `sub` is
+/// the DID of the client the message was published by or delivered to.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WatchEventClaims {
+    #[serde(flatten)]
+    pub basic: JwtBasicClaims,
+    /// The action, always `irn_watchEvent`.
+    pub act: String,
+    pub typ: WatchType,
+    /// The webhook URL the event was delivered to.
+    pub whu: String,
+    pub evt: WatchEventPayload,
+}
+
+impl VerifyableClaims for WatchEventClaims {
+    fn basic(&self) -> &JwtBasicClaims {
+        &self.basic
+    }
+}
+
+/// The body of the requests delivering watch events.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchWebhookPayload {
+    pub event_auth: Vec<String>,
+}
+
+/// Extracts the watch events of a webhook request, only accepting JWTs
+/// signed by the relay and addressed to this server's watch. The events
+/// aren't verified again if the rate limit layer already did.
+pub struct RequireValidWatchEvents(pub Vec<WatchEventClaims>);
+
+/// The watch events of a request verified by [`verify_watch_events`].
+pub(crate) struct VerifiedWatchEvents(pub Vec<WatchEventClaims>);
+
+#[async_trait]
+impl<S, B> FromRequest<S, B> for RequireValidWatchEvents
+where
+    // these bounds are required by
+    // `async_trait`
+    B: Send + 'static + body::HttpBody,
+    B::Data: Send,
+    S: Send + Sync + State,
+{
+    type Rejection = Error;
+
+    async fn from_request(req: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
+        let (mut parts, body) = req.into_parts();
+        if let Some(VerifiedWatchEvents(events)) = parts.extensions.remove() {
+            return Ok(Self(events));
+        }
+
+        let bytes = read_body(&parts, body, state.config().max_body_size).await?;
+        verify_watch_events(state, &bytes).await.map(Self)
+    }
+}
+
+/// Decodes the watch events of a webhook request's body, only accepting JWTs
+/// signed by the relay and addressed to this server's watch. The JWTs must
+/// have been issued within `SIGNATURE_MAX_AGE`, and are rejected once seen.
+pub async fn verify_watch_events<S: State>(
+    state: &S,
+    body: &[u8],
+) -> Result<Vec<WatchEventClaims>, Error> {
+    let events = decode_watch_events(state, body).await?;
+    for (_, signature) in &events {
+        if !mark_seen(state, watch_event_key(signature)).await {
+            warn!("watch event is replayed");
+            let metrics = state.metrics();
+            increment_counter!(metrics, rejected_replays);
+            return Err(ReplayedWatchEvent);
+        }
+    }
+
+    Ok(events.into_iter().map(|(claims, _)| claims).collect())
+}
+
+/// Decodes and verifies the watch events of a webhook request's body, along
+/// with the signatures of their JWTs.
+async fn decode_watch_events<S: State>(
+    state: &S,
+    body: &[u8],
+) -> Result<Vec<(WatchEventClaims, Arc<str>)>, Error> {
+    let keypair = state.relay_keypair().ok_or(InternalServerError)?;
+    let payload: WatchWebhookPayload =
+        serde_json::from_slice(body).map_err(|_| FromRequestError)?;
+
+    let aud: HashSet<String> = [watch_client_id(&keypair).to_did_key()].into();
+    let clock_skew = state.config().auth_clock_skew as i64;
+    let max_age = state.config().signature_max_age;
+    let webhook_url = webhook_url(&state.config().public_url);
+
+    // Without signature validation the events may be issued by anyone, as
+    // the relay's own keys are only checked when the validation is enabled
+    let relay_keys = if state.validate_signatures() {
+        Some(state.relay_client().public_keys().await?)
+    } else {
+        None
+    };
+
+    let mut events = Vec::with_capacity(payload.event_auth.len());
+    for event_auth in &payload.event_auth {
+        let claims = WatchEventClaims::try_from_str(event_auth)?;
+        claims.verify_basic(&aud, clock_skew)?;
+        // The `exp` claim is optional, so the events would be accepted forever
+        if Utc::now().timestamp() - claims.basic.iat > max_age as i64 {
+            return Err(StaleWatchEvent(max_age));
+        }
+
+        if let Some(relay_keys) = &relay_keys {
+            if !relay_keys
+                .iter()
+                .any(|relay_key| claims.basic.iss.0 == *relay_key.as_bytes())
+            {
+                warn!("watch event not issued by the relay");
+                return Err(InvalidAuthentication);
+            }
+        }
+        if claims.act != WATCH_EVENT_ACTION || claims.whu != webhook_url {
+            return Err(Error::InvalidWatchEvent(
+                "not an event of this server's watch".to_string(),
+            ));
+        }
+
+        let signature = event_auth.rsplit('.').next().unwrap_or_default();
+        events.push((claims, Arc::from(signature)));
+    }
+
+    Ok(events)
+}
+
+fn watch_event_key(signature: &str) -> Arc<str> {
+    Arc::from(format!("watch.{signature}"))
+}
+
+/// The client ID this server registers its watch with.
+pub fn watch_client_id(keypair: &Keypair) -> DecodedClientId {
+    DecodedClientId(*keypair.public_key().as_bytes())
+}
+
+pub fn webhook_url(public_url: &str) -> String {
+    format!("{}{WATCH_WEBHOOK_PATH}", public_url.trim_end_matches('/'))
+}
+
+/// Encodes and signs a JWT with the given claims.
+pub fn encode_jwt<T: Serialize>(claims: &T, keypair: &Keypair) -> error::Result<String> {
+    let header = BASE64URL_NOPAD.encode(JWT_HEADER.as_bytes());
+    let claims = BASE64URL_NOPAD.encode(serde_json::to_string(claims)?.as_bytes());
+    let message = format!("{header}.{claims}");
+    let signature = BASE64URL_NOPAD.encode(&keypair.sign(message.as_bytes()).to_bytes());
+
+    Ok(format!("{message}.{signature}"))
+}
+
+/// Registers this server's watch with the relay, and keeps renewing it before
+/// it expires or as `watch_renewal` is notified, e.g. as the relay's URL
+/// is reloaded.
+pub async fn register_watch(state: Arc<AppState>) {
+    let Some(keypair) = state.relay_keypair.clone() else {
+        return;
+    };
+
+    loop {
+        let delay = match register(&state, &keypair).await {
+            Ok(()) => {
+                info!("registered the watch with the relay");
+                Duration::days(WATCH_TTL_DAYS) / 2
+            }
+            Err(e) => {
+                warn!("Failed to register the watch with the relay: {e}");
+                Duration::seconds(WATCH_RETRY_SECONDS as i64)
+            }
+        };
+
+        tokio::select! {
+            _ = sleep(delay.to_std().expect("the delay should be positive")) => {}
+            _ = state.watch_renewal.notified() => {
+                info!("registering the watch with the relay again");
+            }
+        }
+    }
+}
+
+async fn register(state: &AppState, keypair: &Keypair) -> error::Result<()> {
+    let config = state.config();
+    let relay_url = &config.relay_url;
+    let now = Utc::now();
+
+    let auth = AuthToken::new(config.public_url.clone())
+        .aud(relay_url.clone())
+        .as_jwt(keypair)?
+        .to_string();
+
+    let claims = WatchRegisterClaims {
+        basic: JwtBasicClaims {
+            iss: watch_client_id(keypair),
+            sub: config.public_url.clone(),
+            aud: relay_url.clone(),
+            iat: now.timestamp(),
+            exp: Some((now + Duration::days(WATCH_TTL_DAYS)).timestamp()),
+        },
+        act: WATCH_REGISTER_METHOD.to_string(),
+        typ: WatchType::Subscriber,
+        whu: webhook_url(&config.public_url),
+        tft: vec![],
+        sts: vec![WatchStatus::Accepted],
+    };
+    let register_auth = encode_jwt(&claims, keypair)?;
+
+    state
+        .relay_client
+        .watch_register(&auth, &register_auth)
+        .await
+}
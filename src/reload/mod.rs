@@ -0,0 +1,193 @@
+use {
+    crate::{
+        config::{self, Configuration},
+        error,
+        log::{self, prelude::*, LogFilterHandle},
+        rate_limit::RateLimitLayer,
+        state::AppState,
+    },
+    serde::Serialize,
+    std::sync::Arc,
+    tokio::{
+        signal::unix::{signal, SignalKind},
+        sync::Mutex,
+    },
+};
+
+/// Loads the configuration to reload.
+pub type ConfigSource = Arc<dyn Fn() -> error::Result<Configuration> + Send + Sync>;
+
+/// The names of the listed fields which differ between `$current` and
+/// `$next`.
+macro_rules! changed_fields {
+    ($current:expr, $next:expr, [$($field:ident),* $(,)?]) => {{
+        let mut changed = vec![];
+        $(
+            if $current.$field != $next.$field {
+                changed.push(stringify!($field));
+            }
+        )*
+        changed
+    }};
+}
+
+/// Moves the listed fields of `$next` to `$config`.
+macro_rules! move_fields {
+    ($config:ident, $next:ident, [$($field:ident),* $(,)?]) => {
+        $($config.$field = $next.$field;)*
+    };
+}
+
+/// The fields which changed in a reload.
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ReloadReport {
+    /// The fields applied to the running server.
+    pub applied: Vec<&'static str>,
+    /// The fields ignored until the server is restarted.
+    pub restart_required: Vec<&'static str>,
+}
+
+/// The rate limits of the route groups, as layered on the routes.
+#[derive(Clone)]
+pub struct RateLimitLayers {
+    pub register: RateLimitLayer,
+    pub messages: RateLimitLayer,
+    pub relay: RateLimitLayer,
+}
+
+/// Applies a new configuration to the running server.
+///
+/// Only the logging filter, the relay's URL, the retention, the limits and the
+/// quotas are reloaded, the other fields keep their value until a restart.
+/// The watch is registered again with a reloaded relay.
+pub struct Reloader {
+    state: Arc<AppState>,
+    source: ConfigSource,
+    log_filter: Option<LogFilterHandle>,
+    rate_limits: RateLimitLayers,
+    /// Held while reloading, so that concurrent reloads don't interleave.
+    reloading: Mutex<()>,
+}
+
+impl Reloader {
+    pub fn new(
+        state: Arc<AppState>,
+        source: Option<ConfigSource>,
+        log_filter: Option<LogFilterHandle>,
+        rate_limits: RateLimitLayers,
+    ) -> Self {
+        Self {
+            state,
+            source: source.unwrap_or_else(|| Arc::new(config::get_config)),
+            log_filter,
+            rate_limits,
+            reloading: Mutex::new(()),
+        }
+    }
+
+    /// Loads the configuration again and applies its reloadable fields.
+    ///
+    /// Nothing is applied if the new configuration is invalid.
+    pub async fn reload(&self) -> error::Result<ReloadReport> {
+        let _reloading = self.reloading.lock().await;
+
+        let next = (self.source)()?;
+        next.is_valid()?;
+
+        let current = self.state.config();
+        let report = ReloadReport {
+            applied: changed_fields!(current, next, [
+                log_level,
+                relay_url,
+                max_body_size,
+                message_max_age,
+                max_message_size,
+                client_max_messages,
+                client_max_bytes,
+                quota_policy,
+                register_rate_limit,
+                messages_rate_limit,
+                relay_rate_limit,
+            ]),
+            restart_required: changed_fields!(current, next, [
+                port,
+                public_url,
+                relay_keypair_seed,
+                validate_signatures,
+                signature_max_age,
+                storage_backend,
+                mongo_address,
+                postgres_address,
+                sqlite_path,
+                retention_prune_interval,
+                auth_audiences,
+                auth_clock_skew,
+                auth_require_exp,
+                otel_exporter_otlp_endpoint,
+                telemetry_prometheus_port,
+            ]),
+        };
+
+        let mut config = (*current).clone();
+        move_fields!(config, next, [
+            log_level,
+            relay_url,
+            max_body_size,
+            message_max_age,
+            max_message_size,
+            client_max_messages,
+            client_max_bytes,
+            quota_policy,
+            register_rate_limit,
+            messages_rate_limit,
+            relay_rate_limit,
+        ]);
+
+        if let Some(log_filter) = &self.log_filter {
+            log::set_filter(log_filter, &config.log_level)?;
+        }
+        self.state
+            .relay_client
+            .set_base_url(config.relay_url.clone());
+        self.rate_limits
+            .register
+            .set_limit(config.register_rate_limit);
+        self.rate_limits
+            .messages
+            .set_limit(config.messages_rate_limit);
+        self.rate_limits.relay.set_limit(config.relay_rate_limit);
+        self.state.set_config(config);
+        if report.applied.contains(&"relay_url") {
+            // The watch is only registered with the previous relay
+            self.state.watch_renewal.notify_one();
+        }
+
+        if !report.restart_required.is_empty() {
+            warn!(
+                "the configuration of {} requires a restart to be applied",
+                report.restart_required.join(", ")
+            );
+        }
+        info!("configuration reloaded, applied {:?}", report.applied);
+
+        Ok(report)
+    }
+}
+
+/// Reloads the configuration whenever the process receives a `SIGHUP`.
+pub async fn reload_on_sighup(reloader: Arc<Reloader>) {
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(e) => {
+            warn!("Failed to listen for SIGHUP, the configuration won't be reloaded: {e}");
+            return;
+        }
+    };
+
+    while hangups.recv().await.is_some() {
+        if let Err(e) = reloader.reload().await {
+            warn!("Failed to reload the configuration: {e}");
+        }
+    }
+}
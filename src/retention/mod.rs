@@ -0,0 +1,34 @@
+use {
+    crate::{increment_counter_with, log::prelude::*, state::AppState},
+    chrono::Utc,
+    std::{sync::Arc, time::Duration},
+    tokio::time::{interval, MissedTickBehavior},
+};
+
+/// Periodically delete the messages past their expiry date, as set from the
+/// registration's `maxAge`, or older than the server's current
+/// `MESSAGE_MAX_AGE` for the others.
+pub async fn prune_expired_messages(state: Arc<AppState>) {
+    let mut interval = interval(Duration::from_secs(state.config().retention_prune_interval));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        interval.tick().await;
+
+        let stored_before = state
+            .config()
+            .message_max_age
+            .map(|max_age| Utc::now() - chrono::Duration::seconds(max_age as i64));
+        match state
+            .messages_store
+            .delete_expired_messages(stored_before)
+            .await
+        {
+            Ok(count) => {
+                debug!("pruned {count} expired messages");
+                increment_counter_with!(state.metrics, pruned_items, count);
+            }
+            Err(e) => warn!("Failed to prune expired messages: {e}"),
+        }
+    }
+}
@@ -1,43 +1,107 @@
 use {
     crate::{
+        auth::AuthPolicy,
         error,
+        hub::MessageHub,
         metrics::Metrics,
         relay::RelayClient,
-        store::{messages::MessagesStore, registrations::RegistrationStore},
+        store::{
+            messages::MessagesStore,
+            registrations::{Registration, RegistrationStore},
+        },
+        tags::TagPattern,
         Configuration,
     },
     build_info::BuildInfo,
     moka::future::Cache,
-    std::{collections::HashSet, sync::Arc, time::Duration},
+    relay_rpc::auth::{
+        ed25519_dalek::Keypair,
+        rand::{rngs::StdRng, SeedableRng},
+    },
+    std::{
+        sync::{Arc, RwLock},
+        time::Duration,
+    },
+    tokio::sync::Notify,
 };
 
+/// The max number of signatures remembered to reject replayed requests.
+const SEEN_SIGNATURES_CAPACITY: u64 = 1_000_000;
+
 pub type MessagesStorageArc = Arc<dyn MessagesStore + Send + Sync + 'static>;
 pub type RegistrationStorageArc = Arc<dyn RegistrationStore + Send + Sync + 'static>;
 
 #[derive(Clone)]
 pub struct CachedRegistration {
     pub tags: Vec<Arc<str>>,
+    /// The parsed `tags`, parsed once as the registration is cached. Invalid
+    /// patterns are left out as they don't match any tag.
+    tag_patterns: Arc<[TagPattern]>,
     pub relay_url: Arc<str>,
+    pub max_age: Option<u64>,
+}
+
+impl CachedRegistration {
+    pub fn new(tags: Vec<Arc<str>>, relay_url: Arc<str>, max_age: Option<u64>) -> Self {
+        let tag_patterns = tags
+            .iter()
+            .filter_map(|tag| TagPattern::parse(tag).ok())
+            .collect();
+
+        Self {
+            tags,
+            tag_patterns,
+            relay_url,
+            max_age,
+        }
+    }
+
+    /// Whether `tag` matches any of the registered tag patterns.
+    pub fn matches_tag(&self, tag: u32) -> bool {
+        self.tag_patterns.iter().any(|pattern| pattern.matches(tag))
+    }
+}
+
+impl From<Registration> for CachedRegistration {
+    fn from(registration: Registration) -> Self {
+        Self::new(
+            registration.tags,
+            registration.relay_url,
+            registration.max_age,
+        )
+    }
 }
 
 pub trait State {
-    fn config(&self) -> Configuration;
+    fn config(&self) -> Arc<Configuration>;
     fn build_info(&self) -> BuildInfo;
     fn messages_store(&self) -> MessagesStorageArc;
     fn relay_client(&self) -> RelayClient;
     fn validate_signatures(&self) -> bool;
+    fn relay_keypair(&self) -> Option<Arc<Keypair>>;
+    fn seen_signatures(&self) -> Cache<Arc<str>, Arc<()>>;
+    fn metrics(&self) -> Option<Metrics>;
 }
 
 #[derive(Clone)]
 pub struct AppState {
-    pub config: Configuration,
+    /// The current configuration, swapped when reloaded.
+    config: Arc<RwLock<Arc<Configuration>>>,
     pub build_info: BuildInfo,
     pub metrics: Option<Metrics>,
     pub messages_store: MessagesStorageArc,
     pub registration_store: RegistrationStorageArc,
     pub registration_cache: Cache<Arc<str>, CachedRegistration>,
+    pub message_hub: Arc<MessageHub>,
     pub relay_client: RelayClient,
-    pub auth_aud: HashSet<String>,
+    /// The keypair identifying the server's watch to the relay, if any.
+    pub relay_keypair: Option<Arc<Keypair>>,
+    /// The recently received signatures, to reject replayed requests.
+    pub seen_signatures: Cache<Arc<str>, Arc<()>>,
+    /// How the clients' JWTs are verified.
+    pub auth_policy: AuthPolicy,
+    /// Wakes up the watch registration, to register again at once.
+    pub watch_renewal: Arc<Notify>,
 }
 
 build_info::build_info!(fn build_info);
@@ -65,30 +129,56 @@ impl AppState {
             .time_to_idle(Duration::from_secs(5 * 60))
             .build();
 
+        // A signature is fresh up to `signature_max_age` away from now, either
+        // way, so it must be remembered for twice as long
+        let seen_signatures = Cache::builder()
+            .max_capacity(SEEN_SIGNATURES_CAPACITY)
+            .time_to_live(Duration::from_secs(2 * config.signature_max_age))
+            .build();
+
+        let relay_keypair = config
+            .relay_keypair_seed()?
+            .map(|seed| Arc::new(Keypair::generate(&mut StdRng::from_seed(seed))));
+
+        let auth_policy = AuthPolicy::new(&config);
+
         Ok(AppState {
-            config,
+            config: Arc::new(RwLock::new(Arc::new(config))),
             build_info: build_info.clone(),
             metrics: None,
             messages_store,
             registration_store,
             registration_cache,
+            message_hub: Arc::new(MessageHub::new()),
             relay_client: RelayClient::new(relay_url),
-            auth_aud: [
-                "wss://relay.walletconnect.com".to_owned(),
-                "https://history.walletconnect.com".to_owned(),
-            ]
-            .into(),
+            relay_keypair,
+            seen_signatures,
+            auth_policy,
+            watch_renewal: Arc::new(Notify::new()),
         })
     }
 
-    pub fn set_metrics(&mut self, metrics: Metrics) {
+    /// The current configuration.
+    pub fn config(&self) -> Arc<Configuration> {
+        self.config.read().unwrap().clone()
+    }
+
+    /// Replaces the configuration, for the requests and tasks reading it
+    /// from now on.
+    pub fn set_config(&self, config: Configuration) {
+        *self.config.write().unwrap() = Arc::new(config);
+    }
+
+    pub fn set_metrics(&mut self, metrics: Metrics) -> error::Result<()> {
+        self.relay_client.set_metrics(metrics.clone());
         self.metrics = Some(metrics);
+        Ok(())
     }
 }
 
 impl State for Arc<AppState> {
-    fn config(&self) -> Configuration {
-        self.config.clone()
+    fn config(&self) -> Arc<Configuration> {
+        AppState::config(self)
     }
 
     fn build_info(&self) -> BuildInfo {
@@ -104,6 +194,18 @@ impl State for Arc<AppState> {
     }
 
     fn validate_signatures(&self) -> bool {
-        self.config.validate_signatures
+        AppState::config(self).validate_signatures
+    }
+
+    fn relay_keypair(&self) -> Option<Arc<Keypair>> {
+        self.relay_keypair.clone()
+    }
+
+    fn seen_signatures(&self) -> Cache<Arc<str>, Arc<()>> {
+        self.seen_signatures.clone()
+    }
+
+    fn metrics(&self) -> Option<Metrics> {
+        self.metrics.clone()
     }
 }
@@ -0,0 +1,573 @@
+use {
+    crate::store::{
+        messages::{
+            ClientTopic,
+            ClientUsage,
+            Message,
+            MessageCursor,
+            MessageFilter,
+            MessagesStore,
+            Origin,
+            StoreMessages,
+        },
+        registrations::{Registration, RegistrationStore},
+        StoreError,
+    },
+    async_trait::async_trait,
+    chrono::{DateTime, Utc},
+    std::{
+        cmp,
+        collections::{BTreeMap, HashMap},
+        ops::Bound,
+        sync::{Arc, RwLock},
+    },
+    wither::bson,
+};
+
+/// Messages are ordered by timestamp, the insertion sequence breaks ties.
+type MessageKey = (i64, u64);
+
+#[derive(Default)]
+struct TopicMessages {
+    keys: HashMap<Arc<str>, MessageKey>,
+    messages: BTreeMap<MessageKey, Message>,
+}
+
+#[derive(Default)]
+struct Inner {
+    sequence: u64,
+    messages: HashMap<(Arc<str>, Arc<str>), TopicMessages>,
+    topics: HashMap<(Arc<str>, Arc<str>), ClientTopic>,
+    usage: HashMap<Arc<str>, ClientUsage>,
+    registrations: HashMap<Arc<str>, Registration>,
+}
+
+/// A store keeping everything in memory, for tests and ephemeral
+/// deployments. Nothing survives a restart.
+#[derive(Clone, Default)]
+pub struct MemoryStore {
+    inner: Arc<RwLock<Inner>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn get_messages<'a>(
+        messages: impl Iterator<Item = (&'a MessageKey, &'a Message)>,
+        origin: Origin<'_>,
+        filter: &MessageFilter,
+        message_count: usize,
+    ) -> StoreMessages {
+        let page = messages
+            .filter(|(_, message)| filter.matches(message))
+            .take(message_count + 1)
+            .map(|((ts, sequence), message)| {
+                (
+                    MessageCursor::new(*ts, sequence.to_string()),
+                    message.clone(),
+                )
+            })
+            .collect();
+
+        StoreMessages::from_page(page, origin, message_count)
+    }
+
+    fn topics_messages<'a>(
+        inner: &'a Inner,
+        client_id: &'a str,
+        topics: &'a [&str],
+    ) -> impl Iterator<Item = &'a TopicMessages> {
+        topics.iter().filter_map(move |topic| {
+            inner
+                .messages
+                .get(&(Arc::from(client_id), Arc::from(*topic)))
+        })
+    }
+
+    /// Resolves `origin` to a key in the topic's messages, and whether the
+    /// message at that key is part of the page.
+    fn origin_key(
+        topic_messages: Option<&TopicMessages>,
+        topic: &str,
+        origin: Origin<'_>,
+    ) -> Result<Option<(MessageKey, bool)>, StoreError> {
+        match origin {
+            Origin::Start => Ok(None),
+            Origin::MessageId(message_id) => topic_messages
+                .and_then(|topic_messages| topic_messages.keys.get(message_id))
+                .map(|key| Some((*key, true)))
+                .ok_or(StoreError::NotFound(
+                    topic.to_string(),
+                    message_id.to_string(),
+                )),
+            Origin::Cursor(cursor) => cursor
+                .id
+                .parse()
+                .map(|sequence| Some(((cursor.timestamp, sequence), false)))
+                .map_err(|_| StoreError::InvalidCursor(cursor.encode())),
+        }
+    }
+}
+
+#[async_trait]
+impl MessagesStore for MemoryStore {
+    async fn upsert_message(
+        &self,
+        method: &str,
+        tag: u32,
+        client_id: &str,
+        topic: &str,
+        message_id: &str,
+        message: &str,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<(), StoreError> {
+        let mut inner = self.inner.write().unwrap();
+        inner.sequence += 1;
+        let sequence = inner.sequence;
+
+        let timestamp = bson::DateTime::now();
+        let Inner {
+            messages, usage, ..
+        } = &mut *inner;
+        let topic_messages = messages
+            .entry((Arc::from(client_id), Arc::from(topic)))
+            .or_default();
+        let usage = usage.entry(Arc::from(client_id)).or_default();
+
+        if let Some(key) = topic_messages.keys.remove(message_id) {
+            if let Some(replaced) = topic_messages.messages.remove(&key) {
+                usage.remove(&replaced.message);
+            }
+        }
+        usage.add(message);
+
+        let key = (timestamp.timestamp_millis(), sequence);
+        topic_messages.keys.insert(Arc::from(message_id), key);
+        topic_messages.messages.insert(key, Message {
+            id: None,
+            timestamp,
+            method: Arc::from(method),
+            tag: Some(tag),
+            client_id: Arc::from(client_id),
+            topic: Arc::from(topic),
+            message_id: Arc::from(message_id),
+            message: Arc::from(message),
+            expires_at: expires_at.map(Into::into),
+        });
+
+        Ok(())
+    }
+
+    async fn get_messages_after(
+        &self,
+        client_id: &str,
+        topic: &str,
+        origin: Origin<'_>,
+        filter: &MessageFilter,
+        message_count: usize,
+    ) -> Result<StoreMessages, StoreError> {
+        let inner = self.inner.read().unwrap();
+        let topic_messages = inner
+            .messages
+            .get(&(Arc::from(client_id), Arc::from(topic)));
+
+        let from = match Self::origin_key(topic_messages, topic, origin)? {
+            None => Bound::Unbounded,
+            Some((key, true)) => Bound::Included(key),
+            Some((key, false)) => Bound::Excluded(key),
+        };
+
+        Ok(Self::get_messages(
+            topic_messages
+                .into_iter()
+                .flat_map(|topic_messages| topic_messages.messages.range((from, Bound::Unbounded))),
+            origin,
+            filter,
+            message_count,
+        ))
+    }
+
+    async fn get_messages_before(
+        &self,
+        client_id: &str,
+        topic: &str,
+        origin: Origin<'_>,
+        filter: &MessageFilter,
+        message_count: usize,
+    ) -> Result<StoreMessages, StoreError> {
+        let inner = self.inner.read().unwrap();
+        let topic_messages = inner
+            .messages
+            .get(&(Arc::from(client_id), Arc::from(topic)));
+
+        let to = match Self::origin_key(topic_messages, topic, origin)? {
+            None => Bound::Unbounded,
+            Some((key, true)) => Bound::Included(key),
+            Some((key, false)) => Bound::Excluded(key),
+        };
+
+        Ok(Self::get_messages(
+            topic_messages.into_iter().flat_map(|topic_messages| {
+                topic_messages.messages.range((Bound::Unbounded, to)).rev()
+            }),
+            origin,
+            filter,
+            message_count,
+        ))
+    }
+
+    async fn get_topics_messages_after(
+        &self,
+        client_id: &str,
+        topics: &[&str],
+        cursor: Option<&MessageCursor>,
+        filter: &MessageFilter,
+        message_count: usize,
+    ) -> Result<StoreMessages, StoreError> {
+        let inner = self.inner.read().unwrap();
+        let from = match Self::origin_key(None, "", cursor.into())? {
+            Some((key, _)) => Bound::Excluded(key),
+            None => Bound::Unbounded,
+        };
+
+        // The sequence is shared by all the topics, so the keys order the
+        // merged messages too
+        let mut page: Vec<_> = Self::topics_messages(&inner, client_id, topics)
+            .flat_map(|topic_messages| {
+                topic_messages
+                    .messages
+                    .range((from, Bound::Unbounded))
+                    .filter(|(_, message)| filter.matches(message))
+                    .take(message_count + 1)
+            })
+            .collect();
+        page.sort_unstable_by_key(|(key, _)| **key);
+
+        Ok(Self::get_messages(
+            page.into_iter(),
+            cursor.into(),
+            filter,
+            message_count,
+        ))
+    }
+
+    async fn get_topics_messages_before(
+        &self,
+        client_id: &str,
+        topics: &[&str],
+        cursor: Option<&MessageCursor>,
+        filter: &MessageFilter,
+        message_count: usize,
+    ) -> Result<StoreMessages, StoreError> {
+        let inner = self.inner.read().unwrap();
+        let to = match Self::origin_key(None, "", cursor.into())? {
+            Some((key, _)) => Bound::Excluded(key),
+            None => Bound::Unbounded,
+        };
+
+        let mut page: Vec<_> = Self::topics_messages(&inner, client_id, topics)
+            .flat_map(|topic_messages| {
+                topic_messages
+                    .messages
+                    .range((Bound::Unbounded, to))
+                    .rev()
+                    .filter(|(_, message)| filter.matches(message))
+                    .take(message_count + 1)
+            })
+            .collect();
+        page.sort_unstable_by_key(|(key, _)| cmp::Reverse(**key));
+
+        Ok(Self::get_messages(
+            page.into_iter(),
+            cursor.into(),
+            filter,
+            message_count,
+        ))
+    }
+
+    async fn upsert_topic(&self, client_id: &str, topic: &str) -> Result<(), StoreError> {
+        let client_id: Arc<str> = Arc::from(client_id);
+        let topic: Arc<str> = Arc::from(topic);
+
+        self.inner.write().unwrap().topics.insert(
+            (client_id.clone(), topic.clone()),
+            ClientTopic {
+                id: None,
+                timestamp: bson::DateTime::now(),
+                client_id,
+                topic,
+            },
+        );
+
+        Ok(())
+    }
+
+    async fn has_topic(&self, client_id: &str, topic: &str) -> Result<bool, StoreError> {
+        Ok(self
+            .inner
+            .read()
+            .unwrap()
+            .topics
+            .contains_key(&(Arc::from(client_id), Arc::from(topic))))
+    }
+
+    async fn get_topics(&self, client_id: &str) -> Result<Vec<ClientTopic>, StoreError> {
+        let mut topics: Vec<ClientTopic> = self
+            .inner
+            .read()
+            .unwrap()
+            .topics
+            .values()
+            .filter(|topic| topic.client_id.as_ref() == client_id)
+            .cloned()
+            .collect();
+        topics.sort_by_key(|topic| std::cmp::Reverse(topic.timestamp.timestamp_millis()));
+
+        Ok(topics)
+    }
+
+    async fn delete_expired_messages(
+        &self,
+        stored_before: Option<DateTime<Utc>>,
+    ) -> Result<u64, StoreError> {
+        let now = Utc::now().timestamp_millis();
+        let stored_before = stored_before.map(|stored_before| stored_before.timestamp_millis());
+        let mut deleted = 0;
+
+        let mut inner = self.inner.write().unwrap();
+        let Inner {
+            messages, usage, ..
+        } = &mut *inner;
+        for topic_messages in messages.values_mut() {
+            let expired: Vec<(Arc<str>, MessageKey)> = topic_messages
+                .messages
+                .iter()
+                .filter(|(_, message)| match message.expires_at {
+                    Some(expires_at) => expires_at.timestamp_millis() <= now,
+                    None => stored_before.map_or(false, |stored_before| {
+                        message.timestamp.timestamp_millis() <= stored_before
+                    }),
+                })
+                .map(|(key, message)| (message.message_id.clone(), *key))
+                .collect();
+
+            for (message_id, key) in expired {
+                topic_messages.keys.remove(&message_id);
+                if let Some(message) = topic_messages.messages.remove(&key) {
+                    release(usage, &message);
+                }
+                deleted += 1;
+            }
+        }
+
+        Ok(deleted)
+    }
+
+    async fn delete_messages(
+        &self,
+        client_id: &str,
+        topic: Option<&str>,
+        message_ids: &[&str],
+    ) -> Result<u64, StoreError> {
+        let mut deleted = 0;
+
+        let mut inner = self.inner.write().unwrap();
+        let Inner {
+            messages, usage, ..
+        } = &mut *inner;
+        for ((owner, message_topic), topic_messages) in messages.iter_mut() {
+            if owner.as_ref() != client_id
+                || topic.map_or(false, |topic| message_topic.as_ref() != topic)
+            {
+                continue;
+            }
+
+            for message_id in message_ids {
+                if let Some(key) = topic_messages.keys.remove(*message_id) {
+                    if let Some(message) = topic_messages.messages.remove(&key) {
+                        release(usage, &message);
+                    }
+                    deleted += 1;
+                }
+            }
+        }
+
+        Ok(deleted)
+    }
+
+    async fn delete_topic(&self, client_id: &str, topic: &str) -> Result<u64, StoreError> {
+        let key = (Arc::from(client_id), Arc::from(topic));
+        let mut inner = self.inner.write().unwrap();
+
+        inner.topics.remove(&key);
+        let Some(topic_messages) = inner.messages.remove(&key) else {
+            return Ok(0);
+        };
+        for message in topic_messages.messages.values() {
+            release(&mut inner.usage, message);
+        }
+
+        Ok(topic_messages.messages.len() as u64)
+    }
+
+    async fn delete_all_messages(&self, client_id: &str) -> Result<u64, StoreError> {
+        let mut inner = self.inner.write().unwrap();
+        let mut deleted = 0;
+
+        inner
+            .topics
+            .retain(|(owner, _), _| owner.as_ref() != client_id);
+        inner.messages.retain(|(owner, _), topic_messages| {
+            if owner.as_ref() != client_id {
+                return true;
+            }
+            deleted += topic_messages.messages.len() as u64;
+            false
+        });
+        inner.usage.remove(client_id);
+
+        Ok(deleted)
+    }
+
+    async fn get_usage(&self, client_id: &str) -> Result<ClientUsage, StoreError> {
+        Ok(self
+            .inner
+            .read()
+            .unwrap()
+            .usage
+            .get(client_id)
+            .copied()
+            .unwrap_or_default())
+    }
+
+    async fn get_message_size(
+        &self,
+        client_id: &str,
+        topic: &str,
+        message_id: &str,
+    ) -> Result<Option<u64>, StoreError> {
+        let inner = self.inner.read().unwrap();
+        Ok(inner
+            .messages
+            .get(&(Arc::from(client_id), Arc::from(topic)))
+            .and_then(|topic_messages| {
+                let key = topic_messages.keys.get(message_id)?;
+                topic_messages.messages.get(key)
+            })
+            .map(|message| message.message.len() as u64))
+    }
+
+    async fn evict_messages(
+        &self,
+        client_id: &str,
+        max_messages: Option<u64>,
+        max_bytes: Option<u64>,
+    ) -> Result<u64, StoreError> {
+        let mut inner = self.inner.write().unwrap();
+        let Inner {
+            messages, usage, ..
+        } = &mut *inner;
+
+        let mut keys: Vec<(MessageKey, Arc<str>, Arc<str>)> = messages
+            .iter()
+            .filter(|((owner, _), _)| owner.as_ref() == client_id)
+            .flat_map(|((_, topic), topic_messages)| {
+                topic_messages
+                    .messages
+                    .iter()
+                    .map(|(key, message)| (*key, topic.clone(), message.message_id.clone()))
+            })
+            .collect();
+        keys.sort_unstable_by_key(|(key, ..)| cmp::Reverse(*key));
+
+        // Keeps the newest messages while they fit, the keys ordering the
+        // messages of all the topics
+        let mut kept = ClientUsage::default();
+        let mut deleted = 0;
+        for (key, topic, message_id) in keys {
+            let Some(topic_messages) = messages.get_mut(&(Arc::from(client_id), topic)) else {
+                continue;
+            };
+            let Some(message) = topic_messages.messages.get(&key) else {
+                continue;
+            };
+
+            kept.add(&message.message);
+            if max_messages.map_or(true, |max| kept.messages <= max)
+                && max_bytes.map_or(true, |max| kept.bytes <= max)
+            {
+                continue;
+            }
+
+            topic_messages.keys.remove(&message_id);
+            if let Some(message) = topic_messages.messages.remove(&key) {
+                release(usage, &message);
+            }
+            deleted += 1;
+        }
+
+        Ok(deleted)
+    }
+}
+
+/// Accounts for the deletion of `message` in its client's usage.
+fn release(usage: &mut HashMap<Arc<str>, ClientUsage>, message: &Message) {
+    if let Some(usage) = usage.get_mut(message.client_id.as_ref()) {
+        usage.remove(&message.message);
+    }
+}
+
+#[async_trait]
+impl RegistrationStore for MemoryStore {
+    async fn upsert_registration(
+        &self,
+        client_id: &str,
+        tags: Vec<&str>,
+        relay_url: &str,
+        max_age: Option<u64>,
+    ) -> Result<(), StoreError> {
+        let client_id: Arc<str> = Arc::from(client_id);
+
+        self.inner
+            .write()
+            .unwrap()
+            .registrations
+            .insert(client_id.clone(), Registration {
+                id: None,
+                client_id,
+                tags: tags.into_iter().map(Arc::from).collect(),
+                relay_url: Arc::from(relay_url),
+                max_age,
+            });
+
+        Ok(())
+    }
+
+    async fn get_registration(&self, client_id: &str) -> Result<Registration, StoreError> {
+        self.inner
+            .read()
+            .unwrap()
+            .registrations
+            .get(client_id)
+            .cloned()
+            .ok_or(StoreError::NotFound(
+                "registration".to_string(),
+                client_id.to_string(),
+            ))
+    }
+
+    async fn delete_registration(&self, client_id: &str) -> Result<(), StoreError> {
+        self.inner
+            .write()
+            .unwrap()
+            .registrations
+            .remove(client_id)
+            .map(|_| ())
+            .ok_or(StoreError::NotFound(
+                "registration".to_string(),
+                client_id.to_string(),
+            ))
+    }
+}
@@ -1,6 +1,9 @@
 use {
     super::StoreError,
+    crate::tags::TagPattern,
     async_trait::async_trait,
+    chrono::{DateTime, Utc},
+    data_encoding::BASE64URL_NOPAD,
     serde::{Deserialize, Serialize},
     std::{fmt::Debug, sync::Arc},
     wither::{
@@ -15,6 +18,10 @@ use {
     index(keys = r#"doc!{"ts": 1}"#),
     index(keys = r#"doc!{"ts": -1}"#),
     index(keys = r#"doc!{"topic": 1}"#),
+    index(keys = r#"doc!{"client_id": 1, "topic": 1, "ts": 1, "_id": 1}"#),
+    index(keys = r#"doc!{"client_id": 1, "topic": 1, "tag": 1}"#),
+    index(keys = r#"doc!{"client_id": 1, "ts": 1, "_id": 1}"#),
+    index(keys = r#"doc!{"expires_at": 1}"#, options = r#"doc!{"sparse": true}"#),
     index(
         keys = r#"doc!{"client_id": 1, "topic": 1, "message_id": 1}"#,
         options = r#"doc!{"unique": true}"#
@@ -29,6 +36,10 @@ pub struct Message {
     pub timestamp: bson::DateTime,
     /// The messages method (`publish`/`subscription`).
     pub method: Arc<str>,
+    /// The relay tag the message was published with, unset for the messages
+    /// stored before tags were.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<u32>,
     /// The message's client ID.
     pub client_id: Arc<str>,
     /// The message's topic ID.
@@ -37,34 +48,348 @@ pub struct Message {
     pub message_id: Arc<str>,
     /// The actual message.
     pub message: Arc<str>,
+    /// When the message is pruned, kept forever if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<bson::DateTime>,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+/// A topic a client has been delivered messages on.
+#[derive(Clone, Debug, Model, Serialize, Deserialize, PartialEq, Eq)]
+#[model(
+    collection_name = "Topics",
+    index(keys = r#"doc!{"client_id": 1, "ts": -1}"#),
+    index(
+        keys = r#"doc!{"client_id": 1, "topic": 1}"#,
+        options = r#"doc!{"unique": true}"#
+    )
+)]
+pub struct ClientTopic {
+    /// MongoDB's default `_id` field.
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    /// The number of milliseconds since Epoch of the last delivery.
+    #[serde(rename = "ts")]
+    pub timestamp: bson::DateTime,
+    /// The client ID the topic's messages were delivered to.
+    pub client_id: Arc<str>,
+    /// The topic ID.
+    pub topic: Arc<str>,
+}
+
+/// A position in a topic's messages, between two messages.
+///
+/// Messages are ordered by timestamp then by a store specific identifier, so
+/// that messages sharing a timestamp are still paged through without gaps or
+/// duplicates. Clients only ever see the encoded form.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageCursor {
+    /// The message's timestamp, in the store's own precision.
+    pub timestamp: i64,
+    /// The store's identifier of the message.
+    pub id: Arc<str>,
+}
+
+impl MessageCursor {
+    pub fn new(timestamp: i64, id: impl Into<Arc<str>>) -> Self {
+        Self {
+            timestamp,
+            id: id.into(),
+        }
+    }
+
+    /// Encodes the cursor into the opaque string handed out to clients.
+    pub fn encode(&self) -> String {
+        BASE64URL_NOPAD.encode(format!("{}:{}", self.timestamp, self.id).as_bytes())
+    }
+
+    /// Decodes a cursor previously returned by [`MessageCursor::encode`].
+    pub fn decode(cursor: &str) -> Result<Self, StoreError> {
+        let invalid = || StoreError::InvalidCursor(cursor.to_string());
+
+        let decoded = BASE64URL_NOPAD
+            .decode(cursor.as_bytes())
+            .map_err(|_| invalid())?;
+        let decoded = String::from_utf8(decoded).map_err(|_| invalid())?;
+        let (timestamp, id) = decoded.split_once(':').ok_or_else(invalid)?;
+
+        Ok(Self {
+            timestamp: timestamp.parse().map_err(|_| invalid())?,
+            id: Arc::from(id),
+        })
+    }
+}
+
+/// Where a page of messages starts.
+#[derive(Debug, Clone, Copy)]
+pub enum Origin<'a> {
+    /// The topic's oldest message going forward, its newest going backward.
+    Start,
+    /// The message with the given `message_id`, included in the page.
+    MessageId(&'a str),
+    /// The position of a cursor, the message it was taken from is excluded.
+    Cursor(&'a MessageCursor),
+}
+
+impl<'a> From<Option<&'a MessageCursor>> for Origin<'a> {
+    fn from(cursor: Option<&'a MessageCursor>) -> Self {
+        cursor.map_or(Origin::Start, Origin::Cursor)
+    }
+}
+
+/// Restricts the messages of a page, on top of where it starts.
+#[derive(Debug, Clone, Default)]
+pub struct MessageFilter {
+    /// Only the messages stored at or after this time.
+    pub since: Option<DateTime<Utc>>,
+    /// Only the messages stored before this time.
+    pub until: Option<DateTime<Utc>>,
+    /// Only the messages with this method.
+    pub method: Option<Arc<str>>,
+    /// Only the messages whose tag matches this pattern, never those stored
+    /// without a tag.
+    pub tag: Option<TagPattern>,
+}
+
+impl MessageFilter {
+    /// Whether `message` passes the filter, for the stores that can't filter
+    /// natively.
+    pub fn matches(&self, message: &Message) -> bool {
+        let timestamp = message.timestamp.timestamp_millis();
+        self.since
+            .map_or(true, |since| timestamp >= since.timestamp_millis())
+            && self
+                .until
+                .map_or(true, |until| timestamp < until.timestamp_millis())
+            && self
+                .method
+                .as_ref()
+                .map_or(true, |method| message.method == *method)
+            && self.tag.as_ref().map_or(true, |pattern| {
+                message.tag.map_or(false, |tag| pattern.matches(tag))
+            })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
 pub struct StoreMessages {
     pub messages: Vec<Message>,
+    /// The position of each of `messages`, continuing right after it.
+    pub cursors: Vec<MessageCursor>,
+    /// The `message_id` of the first message of the next page.
     pub next_id: Option<Arc<str>>,
+    /// Continues after the page's last message, set if there are more
+    /// messages.
+    pub next_cursor: Option<MessageCursor>,
+    /// Continues before the page's first message in the opposite direction,
+    /// set if the page didn't start at the topic's first message.
+    pub prev_cursor: Option<MessageCursor>,
+}
+
+impl StoreMessages {
+    /// Builds a page out of up to `message_count + 1` messages fetched from
+    /// `origin`, the extra message only signals that there are more.
+    pub fn from_page(
+        mut page: Vec<(MessageCursor, Message)>,
+        origin: Origin<'_>,
+        message_count: usize,
+    ) -> Self {
+        let next_id = if page.len() > message_count {
+            page.pop().map(|(_, message)| message.message_id)
+        } else {
+            None
+        };
+
+        let next_cursor = next_id
+            .as_ref()
+            .and_then(|_| page.last())
+            .map(|(cursor, _)| cursor.clone());
+        let prev_cursor = match origin {
+            Origin::Start => None,
+            Origin::MessageId(_) | Origin::Cursor(_) => {
+                page.first().map(|(cursor, _)| cursor.clone())
+            }
+        };
+
+        let (cursors, messages) = page.into_iter().unzip();
+
+        StoreMessages {
+            messages,
+            cursors,
+            next_id,
+            next_cursor,
+            prev_cursor,
+        }
+    }
+}
+
+/// How much of the store the messages of a client take.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ClientUsage {
+    /// The number of stored messages.
+    pub messages: u64,
+    /// The total size of the stored messages' `message`, in bytes.
+    pub bytes: u64,
+}
+
+impl ClientUsage {
+    /// Accounts for `message` being stored.
+    pub fn add(&mut self, message: &str) {
+        self.messages += 1;
+        self.bytes += message.len() as u64;
+    }
+
+    /// Accounts for a stored message of `replaced` bytes being replaced by
+    /// `message`.
+    pub fn replace(&mut self, replaced: u64, message: &str) {
+        self.bytes = self.bytes.saturating_sub(replaced) + message.len() as u64;
+    }
+
+    /// Accounts for `message` being deleted.
+    pub fn remove(&mut self, message: &str) {
+        self.messages = self.messages.saturating_sub(1);
+        self.bytes = self.bytes.saturating_sub(message.len() as u64);
+    }
+}
+
+/// A message to store with [`MessagesStore::upsert_messages`].
+#[derive(Debug, Clone, Copy)]
+pub struct NewMessage<'a> {
+    pub method: &'a str,
+    pub tag: u32,
+    pub client_id: &'a str,
+    pub topic: &'a str,
+    pub message_id: &'a str,
+    pub message: &'a str,
+    pub expires_at: Option<DateTime<Utc>>,
 }
 
 #[async_trait]
 pub trait MessagesStore: 'static + Send + Sync {
+    #[allow(clippy::too_many_arguments)]
     async fn upsert_message(
         &self,
         method: &str,
+        tag: u32,
         client_id: &str,
         topic: &str,
         message_id: &str,
         message: &str,
+        expires_at: Option<DateTime<Utc>>,
     ) -> Result<(), StoreError>;
+    /// Upserts many messages at once, returning the outcome of each message in
+    /// order. An error fails the whole batch.
+    async fn upsert_messages(
+        &self,
+        messages: &[NewMessage<'_>],
+    ) -> Result<Vec<Result<(), String>>, StoreError> {
+        let mut results = Vec::with_capacity(messages.len());
+        for message in messages {
+            self.upsert_message(
+                message.method,
+                message.tag,
+                message.client_id,
+                message.topic,
+                message.message_id,
+                message.message,
+                message.expires_at,
+            )
+            .await?;
+            results.push(Ok(()));
+        }
+
+        Ok(results)
+    }
+    /// Pages through the messages from `origin` onward, oldest first. Only the
+    /// messages passing `filter` are returned.
     async fn get_messages_after(
         &self,
+        client_id: &str,
         topic: &str,
-        origin: Option<&str>,
+        origin: Origin<'_>,
+        filter: &MessageFilter,
         message_count: usize,
     ) -> Result<StoreMessages, StoreError>;
+    /// Pages through the messages from `origin` backward, newest first.
     async fn get_messages_before(
         &self,
+        client_id: &str,
         topic: &str,
-        origin: Option<&str>,
+        origin: Origin<'_>,
+        filter: &MessageFilter,
         message_count: usize,
     ) -> Result<StoreMessages, StoreError>;
+    /// Pages through the messages of many topics at once from `cursor`
+    /// onward, merged oldest first. The page's cursors span all of `topics`.
+    async fn get_topics_messages_after(
+        &self,
+        client_id: &str,
+        topics: &[&str],
+        cursor: Option<&MessageCursor>,
+        filter: &MessageFilter,
+        message_count: usize,
+    ) -> Result<StoreMessages, StoreError>;
+    /// Pages through the messages of many topics at once from `cursor`
+    /// backward, merged newest first.
+    async fn get_topics_messages_before(
+        &self,
+        client_id: &str,
+        topics: &[&str],
+        cursor: Option<&MessageCursor>,
+        filter: &MessageFilter,
+        message_count: usize,
+    ) -> Result<StoreMessages, StoreError>;
+    /// Records that `client_id` has been delivered messages on `topic`.
+    async fn upsert_topic(&self, client_id: &str, topic: &str) -> Result<(), StoreError>;
+    /// Records many `(client_id, topic)` memberships at once.
+    async fn upsert_topics(&self, topics: &[(&str, &str)]) -> Result<(), StoreError> {
+        for (client_id, topic) in topics {
+            self.upsert_topic(client_id, topic).await?;
+        }
+
+        Ok(())
+    }
+    /// Checks whether `client_id` has ever been delivered messages on `topic`.
+    async fn has_topic(&self, client_id: &str, topic: &str) -> Result<bool, StoreError>;
+    /// Lists the topics `client_id` has been delivered messages on, most
+    /// recent first.
+    async fn get_topics(&self, client_id: &str) -> Result<Vec<ClientTopic>, StoreError>;
+    /// Deletes the messages whose `expires_at` has passed, and those without
+    /// one stored before `stored_before`, returning how many were deleted.
+    async fn delete_expired_messages(
+        &self,
+        stored_before: Option<DateTime<Utc>>,
+    ) -> Result<u64, StoreError>;
+    /// Deletes the given messages of `client_id`, optionally restricted to
+    /// `topic`, returning how many were deleted.
+    async fn delete_messages(
+        &self,
+        client_id: &str,
+        topic: Option<&str>,
+        message_ids: &[&str],
+    ) -> Result<u64, StoreError>;
+    /// Deletes all the messages of `client_id` on `topic` along with the topic
+    /// membership, returning how many messages were deleted.
+    async fn delete_topic(&self, client_id: &str, topic: &str) -> Result<u64, StoreError>;
+    /// Deletes all the messages and topic memberships of `client_id`,
+    /// returning how many messages were deleted.
+    async fn delete_all_messages(&self, client_id: &str) -> Result<u64, StoreError>;
+    /// The number and size of the messages of `client_id`, read from counters
+    /// the store updates on every write rather than from the messages.
+    async fn get_usage(&self, client_id: &str) -> Result<ClientUsage, StoreError>;
+    /// The size of the stored `message` of a message, if it's stored.
+    async fn get_message_size(
+        &self,
+        client_id: &str,
+        topic: &str,
+        message_id: &str,
+    ) -> Result<Option<u64>, StoreError>;
+    /// Deletes the oldest messages of `client_id` until at most
+    /// `max_messages` messages and `max_bytes` bytes are left, returning how
+    /// many were deleted.
+    async fn evict_messages(
+        &self,
+        client_id: &str,
+        max_messages: Option<u64>,
+        max_bytes: Option<u64>,
+    ) -> Result<u64, StoreError>;
 }
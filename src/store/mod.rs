@@ -1,6 +1,23 @@
+use {
+    crate::{
+        config::{Configuration, StorageBackend},
+        state::{MessagesStorageArc, RegistrationStorageArc},
+    },
+    std::sync::Arc,
+};
+
+#[cfg(any(feature = "postgres-store", feature = "sqlite-store"))]
+use crate::tags::{Matcher, TagPattern, TAG_WILDCARD};
+
+#[cfg(feature = "memory-store")]
+pub mod memory;
 pub mod messages;
 pub mod mongo;
+#[cfg(feature = "postgres-store")]
+pub mod postgres;
 pub mod registrations;
+#[cfg(feature = "sqlite-store")]
+pub mod sqlite;
 
 #[derive(Debug, thiserror::Error)]
 pub enum StoreError {
@@ -10,4 +27,90 @@ pub enum StoreError {
 
     #[error(transparent)]
     Database(#[from] wither::WitherError),
+
+    #[cfg(any(feature = "postgres-store", feature = "sqlite-store"))]
+    #[error(transparent)]
+    Sql(#[from] sqlx::Error),
+
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    #[error("invalid cursor {0}")]
+    InvalidCursor(String),
+
+    #[error("bulk write failed: {0}")]
+    BulkWrite(String),
+}
+
+/// Connects to the storage backend selected in the configuration.
+pub async fn connect(
+    config: &Configuration,
+) -> anyhow::Result<(MessagesStorageArc, RegistrationStorageArc)> {
+    match config.storage_backend {
+        StorageBackend::Mongo => {
+            let store = Arc::new(mongo::MongoStore::new(config).await?);
+            Ok((
+                store.clone() as MessagesStorageArc,
+                store as RegistrationStorageArc,
+            ))
+        }
+        #[cfg(feature = "postgres-store")]
+        StorageBackend::Postgres => {
+            let store = Arc::new(postgres::PostgresStore::new(config).await?);
+            Ok((
+                store.clone() as MessagesStorageArc,
+                store as RegistrationStorageArc,
+            ))
+        }
+        #[cfg(feature = "memory-store")]
+        StorageBackend::Memory => {
+            let store = Arc::new(memory::MemoryStore::new());
+            Ok((
+                store.clone() as MessagesStorageArc,
+                store as RegistrationStorageArc,
+            ))
+        }
+        #[cfg(feature = "sqlite-store")]
+        StorageBackend::Sqlite => {
+            let store = Arc::new(sqlite::SqliteStore::new(config).await?);
+            Ok((
+                store.clone() as MessagesStorageArc,
+                store as RegistrationStorageArc,
+            ))
+        }
+    }
+}
+
+/// Translates a tag pattern into a SQL condition on the `tag` column, the
+/// digit items becoming `LIKE` patterns over the tag's decimal string. The
+/// items are validated to only hold digits and wildcards, so they are inlined.
+#[cfg(any(feature = "postgres-store", feature = "sqlite-store"))]
+fn sql_tag_condition(pattern: &TagPattern) -> String {
+    let condition = |matcher: &Matcher| match matcher {
+        Matcher::Range(start, end) => format!("tag BETWEEN {start} AND {end}"),
+        Matcher::Digits { digits, any_suffix } => {
+            let suffix = if *any_suffix { "%" } else { "" };
+            format!(
+                "CAST(tag AS TEXT) LIKE '{}{suffix}'",
+                digits.replace(TAG_WILDCARD, "_")
+            )
+        }
+    };
+    let any = |matchers: &[Matcher]| {
+        matchers
+            .iter()
+            .map(condition)
+            .collect::<Vec<_>>()
+            .join(" OR ")
+    };
+
+    let mut sql = "tag IS NOT NULL".to_string();
+    if !pattern.included().is_empty() {
+        sql.push_str(&format!(" AND ({})", any(pattern.included())));
+    }
+    if !pattern.excluded().is_empty() {
+        sql.push_str(&format!(" AND NOT ({})", any(pattern.excluded())));
+    }
+
+    sql
 }
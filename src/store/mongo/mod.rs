@@ -2,25 +2,53 @@ use {
     crate::{
         config::Configuration,
         store::{
-            messages::{Message, MessagesStore, StoreMessages},
+            messages::{
+                ClientTopic,
+                ClientUsage,
+                Message,
+                MessageCursor,
+                MessageFilter,
+                MessagesStore,
+                NewMessage,
+                Origin,
+                StoreMessages,
+            },
             registrations::{Registration, RegistrationStore},
             StoreError,
         },
+        tags::{Matcher, TagPattern, TAG_WILDCARD},
     },
     async_trait::async_trait,
-    chrono::Utc,
+    chrono::{DateTime, Utc},
     futures::TryStreamExt,
+    serde::{Deserialize, Serialize},
+    std::collections::{HashMap, HashSet},
     wither::{
-        bson::{self, doc, Document},
+        bson::{self, doc, oid::ObjectId, Bson, Document},
         mongodb::{
             options::{ClientOptions, FindOneAndUpdateOptions, FindOptions},
             Client,
             Database,
         },
         Model,
+        WitherError,
     },
 };
 
+/// The usage counters of a client, updated along with its messages.
+#[derive(Clone, Debug, Model, Serialize, Deserialize)]
+#[model(
+    collection_name = "Usage",
+    index(keys = r#"doc!{"client_id": 1}"#, options = r#"doc!{"unique": true}"#)
+)]
+struct UsageCounters {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    client_id: String,
+    messages: i64,
+    bytes: i64,
+}
+
 #[derive(Clone)]
 pub struct MongoStore {
     db: Database,
@@ -37,74 +65,418 @@ impl MongoStore {
         })?;
 
         Message::sync(&db).await?;
+        ClientTopic::sync(&db).await?;
+        UsageCounters::sync(&db).await?;
         Registration::sync(&db).await?;
 
-        Ok(Self { db })
+        let store = Self { db };
+        store
+            .backfill(
+                ClientTopic::COLLECTION_NAME,
+                vec!["client_id", "topic"].into(),
+                vec![
+                    doc! {
+                        "$group": {
+                            "_id": { "client_id": "$client_id", "topic": "$topic" },
+                            "ts": { "$max": "$ts" },
+                        }
+                    },
+                    doc! {
+                        "$project": {
+                            "_id": 0,
+                            "client_id": "$_id.client_id",
+                            "topic": "$_id.topic",
+                            "ts": 1,
+                        }
+                    },
+                ],
+            )
+            .await?;
+        store
+            .backfill(UsageCounters::COLLECTION_NAME, "client_id".into(), vec![
+                doc! {
+                    "$group": {
+                        "_id": "$client_id",
+                        "messages": { "$sum": 1_i64 },
+                        "bytes": { "$sum": { "$toLong": { "$strLenBytes": "$message" } } },
+                    }
+                },
+                doc! {
+                    "$project": {
+                        "_id": 0,
+                        "client_id": "$_id",
+                        "messages": 1,
+                        "bytes": 1,
+                    }
+                },
+            ])
+            .await?;
+
+        Ok(store)
+    }
+
+    /// Fills the `into` collection, maintained along with the messages, with
+    /// the output of `pipeline` over the messages stored before it was. Only
+    /// runs while the collection is empty, the documents written meanwhile by
+    /// other instances being kept.
+    async fn backfill(
+        &self,
+        into: &str,
+        on: Bson,
+        mut pipeline: Vec<Document>,
+    ) -> Result<(), StoreError> {
+        let count = self
+            .db
+            .collection::<Document>(into)
+            .estimated_document_count(None)
+            .await
+            .map_err(WitherError::from)?;
+        if count > 0 {
+            return Ok(());
+        }
+
+        pipeline.push(doc! {
+            "$merge": {
+                "into": into,
+                "on": on,
+                "whenMatched": "keepExisting",
+                "whenNotMatched": "insert",
+            }
+        });
+        let _: Vec<Document> = self
+            .db
+            .collection::<Document>(Message::COLLECTION_NAME)
+            .aggregate(pipeline, None)
+            .await
+            .map_err(WitherError::from)?
+            .try_collect()
+            .await
+            .map_err(WitherError::from)?;
+
+        Ok(())
     }
 
-    async fn get_message_timestamp(
+    async fn get_message_cursor(
         &self,
+        client_id: &str,
         topic: &str,
         message_id: &str,
-    ) -> Result<bson::DateTime, StoreError> {
+    ) -> Result<MessageCursor, StoreError> {
         let filter = doc! {
+            "client_id": &client_id,
             "topic": &topic,
             "message_id": message_id,
         };
 
-        let cursor = Message::find(&self.db, filter, None).await?;
-        let origin: Vec<Message> = cursor.try_collect().await?;
-        let origin = origin.first().ok_or(StoreError::NotFound(
+        let origin = Message::find_one(&self.db, filter, None).await?;
+        let origin = origin.ok_or(StoreError::NotFound(
             topic.to_string(),
             message_id.to_string(),
         ))?;
 
-        Ok(origin.timestamp)
+        Ok(message_cursor(&origin))
     }
 
     async fn get_messages(
         &self,
+        client_id: &str,
         topic: &str,
-        origin: Option<&str>,
+        origin: Origin<'_>,
+        filter: &MessageFilter,
         message_count: usize,
-        comparator: &str,
         sort_order: i32,
     ) -> Result<StoreMessages, StoreError> {
-        let filter: Result<Document, StoreError> = match origin {
-            None => Ok(doc! {
-                "topic": &topic,
-            }),
-            Some(origin) => {
-                let ts = self.get_message_timestamp(topic, origin).await?;
-                Ok(doc! {
-                    "topic": &topic,
-                    "ts": { comparator: ts }
-                })
-            }
+        let position = match origin {
+            Origin::Start => None,
+            Origin::MessageId(message_id) => Some(
+                self.get_message_cursor(client_id, topic, message_id)
+                    .await?,
+            ),
+            Origin::Cursor(cursor) => Some(cursor.clone()),
+        };
+
+        let query = doc! {
+            "client_id": &client_id,
+            "topic": &topic,
+        };
+
+        self.find_messages(query, origin, position, filter, message_count, sort_order)
+            .await
+    }
+
+    /// Finds the page of messages matching `query` and `filter` from the
+    /// `position` `origin` was resolved to.
+    async fn find_messages(
+        &self,
+        mut query: Document,
+        origin: Origin<'_>,
+        position: Option<MessageCursor>,
+        filter: &MessageFilter,
+        message_count: usize,
+        sort_order: i32,
+    ) -> Result<StoreMessages, StoreError> {
+        let (comparator, inclusive_comparator) = if sort_order > 0 {
+            ("$gt", "$gte")
+        } else {
+            ("$lt", "$lte")
         };
-        let filter = filter?;
+        let id_comparator = match origin {
+            Origin::MessageId(_) => inclusive_comparator,
+            Origin::Start | Origin::Cursor(_) => comparator,
+        };
+
+        if let Some(cursor) = position {
+            let ts = bson::DateTime::from_millis(cursor.timestamp);
+            let id = ObjectId::parse_str(cursor.id.as_ref())
+                .map_err(|_| StoreError::InvalidCursor(cursor.encode()))?;
+            // Messages sharing the cursor's timestamp are ordered by `_id`.
+            query.insert("$or", vec![
+                doc! { "ts": { comparator: ts } },
+                doc! { "ts": ts, "_id": { id_comparator: id } },
+            ]);
+        }
+
+        // The bounds are served by the same `ts` index as the position
+        let mut ts_bounds = Document::new();
+        if let Some(since) = filter.since {
+            ts_bounds.insert("$gte", since);
+        }
+        if let Some(until) = filter.until {
+            ts_bounds.insert("$lt", until);
+        }
+        if !ts_bounds.is_empty() {
+            query.insert("ts", ts_bounds);
+        }
+        if let Some(method) = &filter.method {
+            query.insert("method", method.as_ref());
+        }
+        if let Some(pattern) = &filter.tag {
+            query.insert("$and", tag_conditions(pattern));
+        }
 
         let message_count: i64 = message_count as i64;
         let limit = -(message_count + 1);
         let options = FindOptions::builder()
-            .sort(doc! {"ts": sort_order})
+            .sort(doc! {"ts": sort_order, "_id": sort_order})
             .limit(limit)
             .build();
 
-        let cursor = Message::find(&self.db, filter, options).await?;
+        let cursor = Message::find(&self.db, query, options).await?;
+
+        let messages: Vec<Message> = cursor.try_collect().await?;
+        let page = messages
+            .into_iter()
+            .map(|message| (message_cursor(&message), message))
+            .collect();
+
+        Ok(StoreMessages::from_page(
+            page,
+            origin,
+            message_count as usize,
+        ))
+    }
+
+    async fn get_topics_messages(
+        &self,
+        client_id: &str,
+        topics: &[&str],
+        cursor: Option<&MessageCursor>,
+        filter: &MessageFilter,
+        message_count: usize,
+        sort_order: i32,
+    ) -> Result<StoreMessages, StoreError> {
+        // A single query over the `client_id, topic, ts, _id` index, the
+        // topics' messages are merged by the sort.
+        let query = doc! {
+            "client_id": &client_id,
+            "topic": { "$in": topics },
+        };
+
+        self.find_messages(
+            query,
+            cursor.into(),
+            cursor.cloned(),
+            filter,
+            message_count,
+            sort_order,
+        )
+        .await
+    }
+
+    /// Runs many upserts in a single round trip, the driver has no
+    /// `bulk_write` yet. The updates are unordered so that a failing one
+    /// doesn't prevent the others.
+    async fn update_many(
+        &self,
+        collection: &str,
+        updates: Vec<Document>,
+    ) -> Result<Document, StoreError> {
+        let command = doc! {
+            "update": collection,
+            "updates": updates,
+            "ordered": false,
+        };
+
+        Ok(self
+            .db
+            .run_command(command, None)
+            .await
+            .map_err(WitherError::from)?)
+    }
+
+    async fn delete_many(&self, collection: &str, filter: Document) -> Result<u64, StoreError> {
+        let result = self
+            .db
+            .collection::<Document>(collection)
+            .delete_many(filter, None)
+            .await
+            .map_err(WitherError::from)?;
+
+        Ok(result.deleted_count)
+    }
+
+    /// Deletes the messages matching `filter`, releasing them from their
+    /// clients' usage counters.
+    ///
+    /// The counters are updated after the messages are deleted, so messages
+    /// written to in between may leave them slightly off.
+    async fn delete_messages_where(&self, filter: Document) -> Result<u64, StoreError> {
+        let pipeline = vec![doc! { "$match": filter.clone() }, doc! {
+            "$group": {
+                "_id": "$client_id",
+                "messages": { "$sum": 1_i64 },
+                "bytes": { "$sum": { "$toLong": { "$strLenBytes": "$message" } } },
+            }
+        }];
+        let released: Vec<Document> = self
+            .db
+            .collection::<Document>(Message::COLLECTION_NAME)
+            .aggregate(pipeline, None)
+            .await
+            .map_err(WitherError::from)?
+            .try_collect()
+            .await
+            .map_err(WitherError::from)?;
+
+        let deleted = self.delete_many(Message::COLLECTION_NAME, filter).await?;
+
+        self.add_usage(released.iter().filter_map(|group| {
+            Some((
+                group.get_str("_id").ok()?,
+                -group.get_i64("messages").ok()?,
+                -group.get_i64("bytes").ok()?,
+            ))
+        }))
+        .await?;
 
-        let mut messages: Vec<Message> = cursor.try_collect().await?;
+        Ok(deleted)
+    }
 
-        if messages.len() > message_count as usize {
-            let next_id = messages.pop().map(|message| message.message_id);
-            return Ok(StoreMessages { messages, next_id });
+    /// Adds the `(client_id, messages, bytes)` deltas to the clients' usage
+    /// counters. The counters are kept from going below zero, which releasing
+    /// messages written to meanwhile could otherwise make them.
+    async fn add_usage<'a>(
+        &self,
+        deltas: impl IntoIterator<Item = (&'a str, i64, i64)>,
+    ) -> Result<(), StoreError> {
+        let updates: Vec<Document> = deltas
+            .into_iter()
+            .filter(|&(_, messages, bytes)| messages != 0 || bytes != 0)
+            .map(|(client_id, messages, bytes)| {
+                doc! {
+                    "q": { "client_id": client_id },
+                    "u": [{
+                        "$set": {
+                            "messages": {
+                                "$max": [0_i64, { "$add": [{ "$ifNull": ["$messages", 0_i64] }, messages] }],
+                            },
+                            "bytes": {
+                                "$max": [0_i64, { "$add": [{ "$ifNull": ["$bytes", 0_i64] }, bytes] }],
+                            },
+                        },
+                    }],
+                    "upsert": true,
+                }
+            })
+            .collect();
+        if updates.is_empty() {
+            return Ok(());
         }
 
-        Ok(StoreMessages {
-            messages,
-            next_id: None,
+        self.update_many(UsageCounters::COLLECTION_NAME, updates)
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Lists the `(index, message)` of the failed statements of an `update`
+/// command's response.
+fn write_errors(response: &Document) -> impl Iterator<Item = (usize, String)> + '_ {
+    response
+        .get_array("writeErrors")
+        .into_iter()
+        .flatten()
+        .filter_map(Bson::as_document)
+        .filter_map(|error| {
+            Some((
+                error.get_i32("index").ok()? as usize,
+                error.get_str("errmsg").unwrap_or_default().to_string(),
+            ))
+        })
+}
+
+/// Lists the indexes of the statements of an `update` command's response which
+/// inserted a document.
+fn upserted_indexes(response: &Document) -> impl Iterator<Item = usize> + '_ {
+    response
+        .get_array("upserted")
+        .into_iter()
+        .flatten()
+        .filter_map(Bson::as_document)
+        .filter_map(|upserted| match upserted.get("index")? {
+            Bson::Int32(index) => Some(*index as usize),
+            Bson::Int64(index) => Some(*index as usize),
+            _ => None,
         })
+}
+
+/// Translates a tag pattern into conditions on the `tag` field. The items
+/// matching a range of tags become range conditions which can use the index,
+/// the other digit items being matched against the tag's decimal string.
+fn tag_conditions(pattern: &TagPattern) -> Vec<Document> {
+    let condition = |matcher: &Matcher| match (matcher, matcher.as_range()) {
+        (_, Some((start, end))) => doc! { "tag": { "$gte": start, "$lte": end } },
+        (Matcher::Range(start, end), None) => doc! { "tag": { "$gte": *start, "$lte": *end } },
+        (Matcher::Digits { digits, any_suffix }, None) => {
+            let suffix = if *any_suffix { "\\d*" } else { "" };
+            let regex = format!("^{}{suffix}$", digits.replace(TAG_WILDCARD, "\\d"));
+            doc! {
+                "$expr": {
+                    "$regexMatch": { "input": { "$toString": "$tag" }, "regex": regex },
+                },
+            }
+        }
+    };
+
+    let mut conditions = vec![doc! { "tag": { "$ne": null } }];
+    if !pattern.included().is_empty() {
+        conditions
+            .push(doc! { "$or": pattern.included().iter().map(condition).collect::<Vec<_>>() });
+    }
+    if !pattern.excluded().is_empty() {
+        conditions
+            .push(doc! { "$nor": pattern.excluded().iter().map(condition).collect::<Vec<_>>() });
     }
+
+    conditions
+}
+
+fn message_cursor(message: &Message) -> MessageCursor {
+    MessageCursor::new(
+        message.timestamp.timestamp_millis(),
+        message.id.map(|id| id.to_hex()).unwrap_or_default(),
+    )
 }
 
 #[async_trait]
@@ -112,10 +484,12 @@ impl MessagesStore for MongoStore {
     async fn upsert_message(
         &self,
         method: &str,
+        tag: u32,
         client_id: &str,
         topic: &str,
         message_id: &str,
         message: &str,
+        expires_at: Option<DateTime<Utc>>,
     ) -> Result<(), StoreError> {
         let filter = doc! {
             "client_id": &client_id,
@@ -127,38 +501,408 @@ impl MessagesStore for MongoStore {
             "$set": {
                 "ts": Utc::now(),
                 "method": &method,
+                "tag": tag,
                 "client_id": &client_id,
                 "topic": &topic,
                 "message_id": &message_id,
                 "message": &message,
+                "expires_at": expires_at,
             }
         };
 
         let option = FindOneAndUpdateOptions::builder().upsert(true).build();
 
-        match Message::find_one_and_update(&self.db, filter, update, option).await? {
-            Some(_) => Ok(()),
-            None => Ok(()),
+        // The update returns the replaced message, if any
+        let size = message.len() as i64;
+        let delta = match Message::find_one_and_update(&self.db, filter, update, option).await? {
+            Some(replaced) => (client_id, 0, size - replaced.message.len() as i64),
+            None => (client_id, 1, size),
+        };
+
+        self.add_usage([delta]).await
+    }
+
+    async fn upsert_messages(
+        &self,
+        messages: &[NewMessage<'_>],
+    ) -> Result<Vec<Result<(), String>>, StoreError> {
+        if messages.is_empty() {
+            return Ok(vec![]);
+        }
+
+        // The sizes of the messages about to be replaced, the `update` command
+        // doesn't return them
+        let keys: Vec<Document> = messages
+            .iter()
+            .map(|message| {
+                doc! {
+                    "client_id": message.client_id,
+                    "topic": message.topic,
+                    "message_id": message.message_id,
+                }
+            })
+            .collect();
+        let pipeline = vec![doc! { "$match": { "$or": keys } }, doc! {
+            "$project": {
+                "client_id": 1,
+                "topic": 1,
+                "message_id": 1,
+                "size": { "$toLong": { "$strLenBytes": "$message" } },
+            }
+        }];
+        let replaced: Vec<Document> = self
+            .db
+            .collection::<Document>(Message::COLLECTION_NAME)
+            .aggregate(pipeline, None)
+            .await
+            .map_err(WitherError::from)?
+            .try_collect()
+            .await
+            .map_err(WitherError::from)?;
+        let replaced: HashMap<(&str, &str, &str), i64> = replaced
+            .iter()
+            .filter_map(|message| {
+                Some((
+                    (
+                        message.get_str("client_id").ok()?,
+                        message.get_str("topic").ok()?,
+                        message.get_str("message_id").ok()?,
+                    ),
+                    message.get_i64("size").ok()?,
+                ))
+            })
+            .collect();
+
+        let now = Utc::now();
+        let updates: Vec<Document> = messages
+            .iter()
+            .map(|message| {
+                doc! {
+                    "q": {
+                        "client_id": message.client_id,
+                        "topic": message.topic,
+                        "message_id": message.message_id,
+                    },
+                    "u": {
+                        "$set": {
+                            "ts": now,
+                            "method": message.method,
+                            "tag": message.tag,
+                            "client_id": message.client_id,
+                            "topic": message.topic,
+                            "message_id": message.message_id,
+                            "message": message.message,
+                            "expires_at": message.expires_at,
+                        }
+                    },
+                    "upsert": true,
+                }
+            })
+            .collect();
+
+        let response = self.update_many(Message::COLLECTION_NAME, updates).await?;
+
+        let mut results = vec![Ok(()); messages.len()];
+        for (index, error) in write_errors(&response) {
+            if let Some(result) = results.get_mut(index) {
+                *result = Err(error);
+            }
         }
+
+        // Only the upserted statements stored a new message, the others
+        // replaced one stored before or earlier in the batch
+        let upserted: HashSet<usize> = upserted_indexes(&response).collect();
+        let mut sizes = replaced;
+        let mut usage: HashMap<&str, (i64, i64)> = HashMap::new();
+        for (index, message) in messages.iter().enumerate() {
+            if results[index].is_err() {
+                continue;
+            }
+
+            let size = message.message.len() as i64;
+            let replaced =
+                sizes.insert((message.client_id, message.topic, message.message_id), size);
+            let delta = usage.entry(message.client_id).or_default();
+            if upserted.contains(&index) {
+                delta.0 += 1;
+                delta.1 += size;
+            } else {
+                delta.1 += size - replaced.unwrap_or_default();
+            }
+        }
+        self.add_usage(
+            usage
+                .into_iter()
+                .map(|(client_id, (messages, bytes))| (client_id, messages, bytes)),
+        )
+        .await?;
+
+        Ok(results)
     }
 
     async fn get_messages_after(
         &self,
+        client_id: &str,
         topic: &str,
-        origin: Option<&str>,
+        origin: Origin<'_>,
+        filter: &MessageFilter,
         message_count: usize,
     ) -> Result<StoreMessages, StoreError> {
-        self.get_messages(topic, origin, message_count, "$gte", 1)
+        self.get_messages(client_id, topic, origin, filter, message_count, 1)
             .await
     }
 
     async fn get_messages_before(
         &self,
+        client_id: &str,
         topic: &str,
-        origin: Option<&str>,
+        origin: Origin<'_>,
+        filter: &MessageFilter,
+        message_count: usize,
+    ) -> Result<StoreMessages, StoreError> {
+        self.get_messages(client_id, topic, origin, filter, message_count, -1)
+            .await
+    }
+
+    async fn get_topics_messages_after(
+        &self,
+        client_id: &str,
+        topics: &[&str],
+        cursor: Option<&MessageCursor>,
+        filter: &MessageFilter,
+        message_count: usize,
+    ) -> Result<StoreMessages, StoreError> {
+        self.get_topics_messages(client_id, topics, cursor, filter, message_count, 1)
+            .await
+    }
+
+    async fn get_topics_messages_before(
+        &self,
+        client_id: &str,
+        topics: &[&str],
+        cursor: Option<&MessageCursor>,
+        filter: &MessageFilter,
         message_count: usize,
     ) -> Result<StoreMessages, StoreError> {
-        self.get_messages(topic, origin, message_count, "$lte", -1)
+        self.get_topics_messages(client_id, topics, cursor, filter, message_count, -1)
+            .await
+    }
+
+    async fn upsert_topic(&self, client_id: &str, topic: &str) -> Result<(), StoreError> {
+        let filter = doc! {
+            "client_id": &client_id,
+            "topic": &topic,
+        };
+
+        let update = doc! {
+            "$set": {
+                "ts": Utc::now(),
+                "client_id": &client_id,
+                "topic": &topic,
+            }
+        };
+
+        let option = FindOneAndUpdateOptions::builder().upsert(true).build();
+
+        match ClientTopic::find_one_and_update(&self.db, filter, update, option).await? {
+            Some(_) => Ok(()),
+            None => Ok(()),
+        }
+    }
+
+    async fn upsert_topics(&self, topics: &[(&str, &str)]) -> Result<(), StoreError> {
+        if topics.is_empty() {
+            return Ok(());
+        }
+
+        let now = Utc::now();
+        let updates: Vec<Document> = topics
+            .iter()
+            .map(|&(client_id, topic)| {
+                doc! {
+                    "q": {
+                        "client_id": client_id,
+                        "topic": topic,
+                    },
+                    "u": {
+                        "$set": {
+                            "ts": now,
+                            "client_id": client_id,
+                            "topic": topic,
+                        }
+                    },
+                    "upsert": true,
+                }
+            })
+            .collect();
+
+        let response = self
+            .update_many(ClientTopic::COLLECTION_NAME, updates)
+            .await?;
+
+        match write_errors(&response).next() {
+            Some((_, error)) => Err(StoreError::BulkWrite(error)),
+            None => Ok(()),
+        }
+    }
+
+    async fn has_topic(&self, client_id: &str, topic: &str) -> Result<bool, StoreError> {
+        let filter = doc! {
+            "client_id": &client_id,
+            "topic": &topic,
+        };
+
+        if ClientTopic::find_one(&self.db, filter.clone(), None)
+            .await?
+            .is_some()
+        {
+            return Ok(true);
+        }
+
+        // Messages stored before topics were tracked have no `Topics` entry, so
+        // fall back to the messages themselves and backfill the membership.
+        if Message::find_one(&self.db, filter, None).await?.is_none() {
+            return Ok(false);
+        }
+
+        self.upsert_topic(client_id, topic).await?;
+        Ok(true)
+    }
+
+    async fn get_topics(&self, client_id: &str) -> Result<Vec<ClientTopic>, StoreError> {
+        let filter = doc! {
+            "client_id": &client_id,
+        };
+
+        let options = FindOptions::builder().sort(doc! {"ts": -1}).build();
+
+        let cursor = ClientTopic::find(&self.db, filter, options).await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    async fn delete_expired_messages(
+        &self,
+        stored_before: Option<DateTime<Utc>>,
+    ) -> Result<u64, StoreError> {
+        let mut expired = vec![doc! { "expires_at": { "$lte": Utc::now() } }];
+        if let Some(stored_before) = stored_before {
+            // Also matches the messages stored before `expires_at` was
+            expired.push(doc! { "expires_at": null, "ts": { "$lte": stored_before } });
+        }
+
+        self.delete_messages_where(doc! { "$or": expired }).await
+    }
+
+    async fn delete_messages(
+        &self,
+        client_id: &str,
+        topic: Option<&str>,
+        message_ids: &[&str],
+    ) -> Result<u64, StoreError> {
+        let mut filter = doc! {
+            "client_id": &client_id,
+            "message_id": { "$in": message_ids },
+        };
+        if let Some(topic) = topic {
+            filter.insert("topic", topic);
+        }
+
+        self.delete_messages_where(filter).await
+    }
+
+    async fn delete_topic(&self, client_id: &str, topic: &str) -> Result<u64, StoreError> {
+        let filter = doc! {
+            "client_id": &client_id,
+            "topic": &topic,
+        };
+
+        let deleted = self.delete_messages_where(filter.clone()).await?;
+        self.delete_many(ClientTopic::COLLECTION_NAME, filter)
+            .await?;
+
+        Ok(deleted)
+    }
+
+    async fn delete_all_messages(&self, client_id: &str) -> Result<u64, StoreError> {
+        let filter = doc! {
+            "client_id": &client_id,
+        };
+
+        let deleted = self.delete_messages_where(filter.clone()).await?;
+        self.delete_many(ClientTopic::COLLECTION_NAME, filter)
+            .await?;
+
+        Ok(deleted)
+    }
+
+    async fn get_usage(&self, client_id: &str) -> Result<ClientUsage, StoreError> {
+        let filter = doc! {
+            "client_id": &client_id,
+        };
+
+        let counters = UsageCounters::find_one(&self.db, filter, None).await?;
+
+        Ok(
+            counters.map_or_else(ClientUsage::default, |counters| ClientUsage {
+                messages: counters.messages as u64,
+                bytes: counters.bytes as u64,
+            }),
+        )
+    }
+
+    async fn get_message_size(
+        &self,
+        client_id: &str,
+        topic: &str,
+        message_id: &str,
+    ) -> Result<Option<u64>, StoreError> {
+        let filter = doc! {
+            "client_id": &client_id,
+            "topic": &topic,
+            "message_id": &message_id,
+        };
+
+        Ok(Message::find_one(&self.db, filter, None)
+            .await?
+            .map(|message| message.message.len() as u64))
+    }
+
+    async fn evict_messages(
+        &self,
+        client_id: &str,
+        max_messages: Option<u64>,
+        max_bytes: Option<u64>,
+    ) -> Result<u64, StoreError> {
+        let pipeline = vec![
+            doc! { "$match": { "client_id": &client_id } },
+            doc! { "$sort": { "ts": -1, "_id": -1 } },
+            doc! { "$project": { "size": { "$toLong": { "$strLenBytes": "$message" } } } },
+        ];
+        let mut newest = self
+            .db
+            .collection::<Document>(Message::COLLECTION_NAME)
+            .aggregate(pipeline, None)
+            .await
+            .map_err(WitherError::from)?;
+
+        // Keeps the newest messages while their running count and size fit
+        let (mut kept_messages, mut kept_bytes) = (0, 0);
+        let mut evicted = vec![];
+        while let Some(message) = newest.try_next().await.map_err(WitherError::from)? {
+            kept_messages += 1;
+            kept_bytes += message.get_i64("size").unwrap_or_default() as u64;
+            if max_messages.map_or(false, |max| kept_messages > max)
+                || max_bytes.map_or(false, |max| kept_bytes > max)
+            {
+                evicted.extend(message.get_object_id("_id").ok());
+            }
+        }
+
+        if evicted.is_empty() {
+            return Ok(0);
+        }
+
+        self.delete_messages_where(doc! { "_id": { "$in": evicted } })
             .await
     }
 }
@@ -170,6 +914,7 @@ impl RegistrationStore for MongoStore {
         client_id: &str,
         tags: Vec<&str>,
         relay_url: &str,
+        max_age: Option<u64>,
     ) -> Result<(), StoreError> {
         let filter = doc! {
             "client_id": &client_id,
@@ -180,6 +925,7 @@ impl RegistrationStore for MongoStore {
                 "client_id": &client_id,
                 "tags": tags,
                 "relay_url": &relay_url,
+                "max_age": max_age.map(|max_age| max_age as i64),
             }
         };
 
@@ -202,4 +948,33 @@ impl RegistrationStore for MongoStore {
             client_id.to_string(),
         ))
     }
+
+    async fn get_registrations(
+        &self,
+        client_ids: &[&str],
+    ) -> Result<Vec<Registration>, StoreError> {
+        let filter = doc! {
+            "client_id": { "$in": client_ids },
+        };
+
+        let cursor = Registration::find(&self.db, filter, None).await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    async fn delete_registration(&self, client_id: &str) -> Result<(), StoreError> {
+        let filter = doc! {
+            "client_id": &client_id,
+        };
+
+        match self
+            .delete_many(Registration::COLLECTION_NAME, filter)
+            .await?
+        {
+            0 => Err(StoreError::NotFound(
+                "registration".to_string(),
+                client_id.to_string(),
+            )),
+            _ => Ok(()),
+        }
+    }
 }
@@ -0,0 +1,596 @@
+use {
+    crate::{
+        config::Configuration,
+        store::{
+            messages::{
+                ClientTopic,
+                ClientUsage,
+                Message,
+                MessageCursor,
+                MessageFilter,
+                MessagesStore,
+                Origin,
+                StoreMessages,
+            },
+            registrations::{Registration, RegistrationStore},
+            sql_tag_condition,
+            StoreError,
+        },
+    },
+    async_trait::async_trait,
+    chrono::{DateTime, Utc},
+    sqlx::{
+        postgres::{PgPool, PgPoolOptions, Postgres},
+        FromRow,
+    },
+    std::sync::Arc,
+};
+
+#[derive(FromRow)]
+struct MessageRow {
+    id: i64,
+    ts: DateTime<Utc>,
+    method: String,
+    tag: Option<i64>,
+    client_id: String,
+    topic: String,
+    message_id: String,
+    message: String,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+impl From<MessageRow> for Message {
+    fn from(row: MessageRow) -> Self {
+        Message {
+            id: None,
+            timestamp: row.ts.into(),
+            method: Arc::from(row.method),
+            tag: row.tag.map(|tag| tag as u32),
+            client_id: Arc::from(row.client_id),
+            topic: Arc::from(row.topic),
+            message_id: Arc::from(row.message_id),
+            message: Arc::from(row.message),
+            expires_at: row.expires_at.map(Into::into),
+        }
+    }
+}
+
+#[derive(FromRow)]
+struct UsageRow {
+    messages: i64,
+    bytes: i64,
+}
+
+#[derive(FromRow)]
+struct TopicRow {
+    ts: DateTime<Utc>,
+    client_id: String,
+    topic: String,
+}
+
+impl From<TopicRow> for ClientTopic {
+    fn from(row: TopicRow) -> Self {
+        ClientTopic {
+            id: None,
+            timestamp: row.ts.into(),
+            client_id: Arc::from(row.client_id),
+            topic: Arc::from(row.topic),
+        }
+    }
+}
+
+#[derive(FromRow)]
+struct RegistrationRow {
+    client_id: String,
+    tags: Vec<String>,
+    relay_url: String,
+    max_age: Option<i64>,
+}
+
+impl From<RegistrationRow> for Registration {
+    fn from(row: RegistrationRow) -> Self {
+        Registration {
+            id: None,
+            client_id: Arc::from(row.client_id),
+            tags: row.tags.into_iter().map(Arc::from).collect(),
+            relay_url: Arc::from(row.relay_url),
+            max_age: row.max_age.map(|max_age| max_age as u64),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct PostgresStore {
+    pool: PgPool,
+}
+
+impl PostgresStore {
+    pub async fn new(config: &Configuration) -> anyhow::Result<Self> {
+        let url = config
+            .postgres_address
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("no PostgreSQL address specified"))?;
+
+        let pool = PgPoolOptions::new().connect(url).await?;
+
+        sqlx::migrate!("./migrations/postgres").run(&pool).await?;
+
+        Ok(Self { pool })
+    }
+
+    async fn get_message_cursor(
+        &self,
+        client_id: &str,
+        topic: &str,
+        message_id: &str,
+    ) -> Result<MessageCursor, StoreError> {
+        let position = sqlx::query_as::<_, (DateTime<Utc>, i64)>(
+            "SELECT ts, id FROM messages WHERE client_id = $1 AND topic = $2 AND message_id = $3",
+        )
+        .bind(client_id)
+        .bind(topic)
+        .bind(message_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let (ts, id) = position.ok_or(StoreError::NotFound(
+            topic.to_string(),
+            message_id.to_string(),
+        ))?;
+
+        Ok(MessageCursor::new(ts.timestamp_micros(), id.to_string()))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn get_messages(
+        &self,
+        client_id: &str,
+        topic: &str,
+        origin: Origin<'_>,
+        filter: &MessageFilter,
+        message_count: usize,
+        comparator: &str,
+        sort_order: &str,
+    ) -> Result<StoreMessages, StoreError> {
+        let position = match origin {
+            Origin::Start => None,
+            Origin::MessageId(message_id) => Some(
+                self.get_message_cursor(client_id, topic, message_id)
+                    .await?,
+            ),
+            Origin::Cursor(cursor) => Some(cursor.clone()),
+        };
+
+        self.find_messages(
+            client_id,
+            "topic = $2",
+            topic,
+            origin,
+            position,
+            filter,
+            message_count,
+            comparator,
+            sort_order,
+        )
+        .await
+    }
+
+    /// Finds the page of messages of `client_id` matching `topic_condition`,
+    /// which binds `topics` as `$2`, from the `position` `origin` was
+    /// resolved to.
+    #[allow(clippy::too_many_arguments)]
+    async fn find_messages<T>(
+        &self,
+        client_id: &str,
+        topic_condition: &str,
+        topics: T,
+        origin: Origin<'_>,
+        position: Option<MessageCursor>,
+        filter: &MessageFilter,
+        message_count: usize,
+        comparator: &str,
+        sort_order: &str,
+    ) -> Result<StoreMessages, StoreError>
+    where
+        T: Send + for<'q> sqlx::Encode<'q, Postgres> + sqlx::Type<Postgres>,
+    {
+        let comparator = match origin {
+            Origin::MessageId(_) => format!("{comparator}="),
+            Origin::Start | Origin::Cursor(_) => comparator.to_string(),
+        };
+
+        let position = position
+            .map(|cursor| {
+                cursor
+                    .id
+                    .parse::<i64>()
+                    .map(|id| (cursor.timestamp, id))
+                    .map_err(|_| StoreError::InvalidCursor(cursor.encode()))
+            })
+            .transpose()?;
+
+        let tag_condition = filter
+            .tag
+            .as_ref()
+            .map_or_else(|| "TRUE".to_string(), sql_tag_condition);
+
+        // Timestamps are compared in microseconds since Epoch, the precision
+        // of `TIMESTAMPTZ`, so that cursors round-trip exactly.
+        let query = format!(
+            "SELECT id, ts, method, tag, client_id, topic, message_id, message, expires_at FROM \
+             messages WHERE client_id = $1 AND {topic_condition} AND ($3::bigint IS NULL OR (ts, \
+             id) {comparator} (TIMESTAMPTZ 'epoch' + $3 * INTERVAL '1 microsecond', $4)) AND \
+             ($6::timestamptz IS NULL OR ts >= $6) AND ($7::timestamptz IS NULL OR ts < $7) AND \
+             ($8::text IS NULL OR method = $8) AND {tag_condition} ORDER BY ts {sort_order}, id \
+             {sort_order} LIMIT $5"
+        );
+
+        let page = sqlx::query_as::<_, MessageRow>(&query)
+            .bind(client_id)
+            .bind(topics)
+            .bind(position.map(|(timestamp, _)| timestamp))
+            .bind(position.map(|(_, id)| id))
+            .bind(message_count as i64 + 1)
+            .bind(filter.since)
+            .bind(filter.until)
+            .bind(filter.method.as_deref())
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(|row| {
+                (
+                    MessageCursor::new(row.ts.timestamp_micros(), row.id.to_string()),
+                    Message::from(row),
+                )
+            })
+            .collect();
+
+        Ok(StoreMessages::from_page(page, origin, message_count))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn get_topics_messages(
+        &self,
+        client_id: &str,
+        topics: &[&str],
+        cursor: Option<&MessageCursor>,
+        filter: &MessageFilter,
+        message_count: usize,
+        comparator: &str,
+        sort_order: &str,
+    ) -> Result<StoreMessages, StoreError> {
+        self.find_messages(
+            client_id,
+            "topic = ANY($2)",
+            topics,
+            cursor.into(),
+            cursor.cloned(),
+            filter,
+            message_count,
+            comparator,
+            sort_order,
+        )
+        .await
+    }
+}
+
+#[async_trait]
+impl MessagesStore for PostgresStore {
+    async fn upsert_message(
+        &self,
+        method: &str,
+        tag: u32,
+        client_id: &str,
+        topic: &str,
+        message_id: &str,
+        message: &str,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<(), StoreError> {
+        sqlx::query(
+            "INSERT INTO messages (ts, method, tag, client_id, topic, message_id, message, \
+             expires_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8) ON CONFLICT (client_id, topic, \
+             message_id) DO UPDATE SET ts = EXCLUDED.ts, method = EXCLUDED.method, tag = \
+             EXCLUDED.tag, message = EXCLUDED.message, expires_at = EXCLUDED.expires_at",
+        )
+        .bind(Utc::now())
+        .bind(method)
+        .bind(i64::from(tag))
+        .bind(client_id)
+        .bind(topic)
+        .bind(message_id)
+        .bind(message)
+        .bind(expires_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_messages_after(
+        &self,
+        client_id: &str,
+        topic: &str,
+        origin: Origin<'_>,
+        filter: &MessageFilter,
+        message_count: usize,
+    ) -> Result<StoreMessages, StoreError> {
+        self.get_messages(client_id, topic, origin, filter, message_count, ">", "ASC")
+            .await
+    }
+
+    async fn get_messages_before(
+        &self,
+        client_id: &str,
+        topic: &str,
+        origin: Origin<'_>,
+        filter: &MessageFilter,
+        message_count: usize,
+    ) -> Result<StoreMessages, StoreError> {
+        self.get_messages(client_id, topic, origin, filter, message_count, "<", "DESC")
+            .await
+    }
+
+    async fn get_topics_messages_after(
+        &self,
+        client_id: &str,
+        topics: &[&str],
+        cursor: Option<&MessageCursor>,
+        filter: &MessageFilter,
+        message_count: usize,
+    ) -> Result<StoreMessages, StoreError> {
+        self.get_topics_messages(client_id, topics, cursor, filter, message_count, ">", "ASC")
+            .await
+    }
+
+    async fn get_topics_messages_before(
+        &self,
+        client_id: &str,
+        topics: &[&str],
+        cursor: Option<&MessageCursor>,
+        filter: &MessageFilter,
+        message_count: usize,
+    ) -> Result<StoreMessages, StoreError> {
+        self.get_topics_messages(
+            client_id,
+            topics,
+            cursor,
+            filter,
+            message_count,
+            "<",
+            "DESC",
+        )
+        .await
+    }
+
+    async fn upsert_topic(&self, client_id: &str, topic: &str) -> Result<(), StoreError> {
+        sqlx::query(
+            "INSERT INTO topics (client_id, topic, ts) VALUES ($1, $2, $3) ON CONFLICT \
+             (client_id, topic) DO UPDATE SET ts = EXCLUDED.ts",
+        )
+        .bind(client_id)
+        .bind(topic)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn has_topic(&self, client_id: &str, topic: &str) -> Result<bool, StoreError> {
+        let exists = sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS (SELECT 1 FROM topics WHERE client_id = $1 AND topic = $2)",
+        )
+        .bind(client_id)
+        .bind(topic)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(exists)
+    }
+
+    async fn get_topics(&self, client_id: &str) -> Result<Vec<ClientTopic>, StoreError> {
+        let topics = sqlx::query_as::<_, TopicRow>(
+            "SELECT ts, client_id, topic FROM topics WHERE client_id = $1 ORDER BY ts DESC",
+        )
+        .bind(client_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(topics.into_iter().map(ClientTopic::from).collect())
+    }
+
+    async fn delete_expired_messages(
+        &self,
+        stored_before: Option<DateTime<Utc>>,
+    ) -> Result<u64, StoreError> {
+        let result = sqlx::query(
+            "DELETE FROM messages WHERE expires_at <= $1 OR (expires_at IS NULL AND ts <= $2)",
+        )
+        .bind(Utc::now())
+        .bind(stored_before)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn delete_messages(
+        &self,
+        client_id: &str,
+        topic: Option<&str>,
+        message_ids: &[&str],
+    ) -> Result<u64, StoreError> {
+        let result = sqlx::query(
+            "DELETE FROM messages WHERE client_id = $1 AND ($2::text IS NULL OR topic = $2) AND \
+             message_id = ANY($3)",
+        )
+        .bind(client_id)
+        .bind(topic)
+        .bind(message_ids)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn delete_topic(&self, client_id: &str, topic: &str) -> Result<u64, StoreError> {
+        let mut tx = self.pool.begin().await?;
+
+        let result = sqlx::query("DELETE FROM messages WHERE client_id = $1 AND topic = $2")
+            .bind(client_id)
+            .bind(topic)
+            .execute(&mut tx)
+            .await?;
+        sqlx::query("DELETE FROM topics WHERE client_id = $1 AND topic = $2")
+            .bind(client_id)
+            .bind(topic)
+            .execute(&mut tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn delete_all_messages(&self, client_id: &str) -> Result<u64, StoreError> {
+        let mut tx = self.pool.begin().await?;
+
+        let result = sqlx::query("DELETE FROM messages WHERE client_id = $1")
+            .bind(client_id)
+            .execute(&mut tx)
+            .await?;
+        sqlx::query("DELETE FROM topics WHERE client_id = $1")
+            .bind(client_id)
+            .execute(&mut tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn get_usage(&self, client_id: &str) -> Result<ClientUsage, StoreError> {
+        let row: Option<UsageRow> =
+            sqlx::query_as("SELECT messages, bytes FROM client_usage WHERE client_id = $1")
+                .bind(client_id)
+                .fetch_optional(&self.pool)
+                .await?;
+
+        Ok(row.map_or_else(ClientUsage::default, |row| ClientUsage {
+            messages: row.messages.max(0) as u64,
+            bytes: row.bytes.max(0) as u64,
+        }))
+    }
+
+    async fn get_message_size(
+        &self,
+        client_id: &str,
+        topic: &str,
+        message_id: &str,
+    ) -> Result<Option<u64>, StoreError> {
+        let size = sqlx::query_scalar::<_, i32>(
+            "SELECT octet_length(message) FROM messages WHERE client_id = $1 AND topic = $2 AND \
+             message_id = $3",
+        )
+        .bind(client_id)
+        .bind(topic)
+        .bind(message_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(size.map(|size| size.max(0) as u64))
+    }
+
+    async fn evict_messages(
+        &self,
+        client_id: &str,
+        max_messages: Option<u64>,
+        max_bytes: Option<u64>,
+    ) -> Result<u64, StoreError> {
+        // Keeps the newest messages while their running count and size fit
+        let result = sqlx::query(
+            "DELETE FROM messages WHERE id IN (SELECT id FROM (SELECT id, ROW_NUMBER() OVER w AS \
+             kept_messages, SUM(octet_length(message)) OVER w AS kept_bytes FROM messages WHERE \
+             client_id = $1 WINDOW w AS (ORDER BY ts DESC, id DESC)) newest WHERE kept_messages > \
+             COALESCE($2::bigint, kept_messages) OR kept_bytes > COALESCE($3::bigint, kept_bytes))",
+        )
+        .bind(client_id)
+        .bind(max_messages.map(|max| max as i64))
+        .bind(max_bytes.map(|max| max as i64))
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+#[async_trait]
+impl RegistrationStore for PostgresStore {
+    async fn upsert_registration(
+        &self,
+        client_id: &str,
+        tags: Vec<&str>,
+        relay_url: &str,
+        max_age: Option<u64>,
+    ) -> Result<(), StoreError> {
+        sqlx::query(
+            "INSERT INTO registrations (client_id, tags, relay_url, max_age) VALUES ($1, $2, $3, \
+             $4) ON CONFLICT (client_id) DO UPDATE SET tags = EXCLUDED.tags, relay_url = \
+             EXCLUDED.relay_url, max_age = EXCLUDED.max_age",
+        )
+        .bind(client_id)
+        .bind(tags)
+        .bind(relay_url)
+        .bind(max_age.map(|max_age| max_age as i64))
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_registration(&self, client_id: &str) -> Result<Registration, StoreError> {
+        let registration = sqlx::query_as::<_, RegistrationRow>(
+            "SELECT client_id, tags, relay_url, max_age FROM registrations WHERE client_id = $1",
+        )
+        .bind(client_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        registration
+            .map(Registration::from)
+            .ok_or(StoreError::NotFound(
+                "registration".to_string(),
+                client_id.to_string(),
+            ))
+    }
+
+    async fn get_registrations(
+        &self,
+        client_ids: &[&str],
+    ) -> Result<Vec<Registration>, StoreError> {
+        let registrations = sqlx::query_as::<_, RegistrationRow>(
+            "SELECT client_id, tags, relay_url, max_age FROM registrations WHERE client_id = \
+             ANY($1)",
+        )
+        .bind(client_ids)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(registrations.into_iter().map(Registration::from).collect())
+    }
+
+    async fn delete_registration(&self, client_id: &str) -> Result<(), StoreError> {
+        let result = sqlx::query("DELETE FROM registrations WHERE client_id = $1")
+            .bind(client_id)
+            .execute(&self.pool)
+            .await?;
+
+        match result.rows_affected() {
+            0 => Err(StoreError::NotFound(
+                "registration".to_string(),
+                client_id.to_string(),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
@@ -24,6 +24,10 @@ pub struct Registration {
     pub tags: Vec<Arc<str>>,
     /// The registered relay_url
     pub relay_url: Arc<str>,
+    /// The max age of the stored messages in seconds, overrides the configured
+    /// default when set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_age: Option<u64>,
 }
 
 #[async_trait]
@@ -33,6 +37,26 @@ pub trait RegistrationStore: 'static + Send + Sync {
         client_id: &str,
         tags: Vec<&str>,
         relay_url: &str,
+        max_age: Option<u64>,
     ) -> Result<(), StoreError>;
     async fn get_registration(&self, client_id: &str) -> Result<Registration, StoreError>;
+    /// Gets the registrations of many clients at once, unregistered clients
+    /// are left out.
+    async fn get_registrations(
+        &self,
+        client_ids: &[&str],
+    ) -> Result<Vec<Registration>, StoreError> {
+        let mut registrations = Vec::with_capacity(client_ids.len());
+        for client_id in client_ids {
+            match self.get_registration(client_id).await {
+                Ok(registration) => registrations.push(registration),
+                Err(StoreError::NotFound(_, _)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(registrations)
+    }
+    /// Deletes the registration of `client_id`, its messages are kept.
+    async fn delete_registration(&self, client_id: &str) -> Result<(), StoreError>;
 }
@@ -0,0 +1,611 @@
+use {
+    crate::{
+        config::Configuration,
+        store::{
+            messages::{
+                ClientTopic,
+                ClientUsage,
+                Message,
+                MessageCursor,
+                MessageFilter,
+                MessagesStore,
+                Origin,
+                StoreMessages,
+            },
+            registrations::{Registration, RegistrationStore},
+            sql_tag_condition,
+            StoreError,
+        },
+    },
+    async_trait::async_trait,
+    chrono::{DateTime, Utc},
+    sqlx::{
+        sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqlitePoolOptions},
+        FromRow,
+    },
+    std::sync::Arc,
+    wither::bson,
+};
+
+#[derive(FromRow)]
+struct MessageRow {
+    id: i64,
+    ts: i64,
+    method: String,
+    tag: Option<i64>,
+    client_id: String,
+    topic: String,
+    message_id: String,
+    message: String,
+    expires_at: Option<i64>,
+}
+
+impl From<MessageRow> for Message {
+    fn from(row: MessageRow) -> Self {
+        Message {
+            id: None,
+            timestamp: bson::DateTime::from_millis(row.ts),
+            method: Arc::from(row.method),
+            tag: row.tag.map(|tag| tag as u32),
+            client_id: Arc::from(row.client_id),
+            topic: Arc::from(row.topic),
+            message_id: Arc::from(row.message_id),
+            message: Arc::from(row.message),
+            expires_at: row.expires_at.map(bson::DateTime::from_millis),
+        }
+    }
+}
+
+#[derive(FromRow)]
+struct UsageRow {
+    messages: i64,
+    bytes: i64,
+}
+
+#[derive(FromRow)]
+struct TopicRow {
+    ts: i64,
+    client_id: String,
+    topic: String,
+}
+
+impl From<TopicRow> for ClientTopic {
+    fn from(row: TopicRow) -> Self {
+        ClientTopic {
+            id: None,
+            timestamp: bson::DateTime::from_millis(row.ts),
+            client_id: Arc::from(row.client_id),
+            topic: Arc::from(row.topic),
+        }
+    }
+}
+
+#[derive(FromRow)]
+struct RegistrationRow {
+    client_id: String,
+    tags: String,
+    relay_url: String,
+    max_age: Option<i64>,
+}
+
+impl TryFrom<RegistrationRow> for Registration {
+    type Error = serde_json::Error;
+
+    fn try_from(row: RegistrationRow) -> Result<Self, Self::Error> {
+        let tags: Vec<Arc<str>> = serde_json::from_str(&row.tags)?;
+
+        Ok(Registration {
+            id: None,
+            client_id: Arc::from(row.client_id),
+            tags,
+            relay_url: Arc::from(row.relay_url),
+            max_age: row.max_age.map(|max_age| max_age as u64),
+        })
+    }
+}
+
+/// A single-file store, timestamps are stored as milliseconds since Epoch
+/// and registration tags as a JSON array.
+#[derive(Clone)]
+pub struct SqliteStore {
+    pool: SqlitePool,
+}
+
+impl SqliteStore {
+    pub async fn new(config: &Configuration) -> anyhow::Result<Self> {
+        let path = config
+            .sqlite_path
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("no SQLite database path specified"))?;
+
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal);
+
+        let pool = SqlitePoolOptions::new().connect_with(options).await?;
+
+        sqlx::migrate!("./migrations/sqlite").run(&pool).await?;
+
+        Ok(Self { pool })
+    }
+
+    async fn get_message_cursor(
+        &self,
+        client_id: &str,
+        topic: &str,
+        message_id: &str,
+    ) -> Result<MessageCursor, StoreError> {
+        let position = sqlx::query_as::<_, (i64, i64)>(
+            "SELECT ts, id FROM messages WHERE client_id = ? AND topic = ? AND message_id = ?",
+        )
+        .bind(client_id)
+        .bind(topic)
+        .bind(message_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let (ts, id) = position.ok_or(StoreError::NotFound(
+            topic.to_string(),
+            message_id.to_string(),
+        ))?;
+
+        Ok(MessageCursor::new(ts, id.to_string()))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn get_messages(
+        &self,
+        client_id: &str,
+        topic: &str,
+        origin: Origin<'_>,
+        filter: &MessageFilter,
+        message_count: usize,
+        comparator: &str,
+        sort_order: &str,
+    ) -> Result<StoreMessages, StoreError> {
+        let position = match origin {
+            Origin::Start => None,
+            Origin::MessageId(message_id) => Some(
+                self.get_message_cursor(client_id, topic, message_id)
+                    .await?,
+            ),
+            Origin::Cursor(cursor) => Some(cursor.clone()),
+        };
+
+        self.find_messages(
+            client_id,
+            "topic = ?2",
+            topic.to_string(),
+            origin,
+            position,
+            filter,
+            message_count,
+            comparator,
+            sort_order,
+        )
+        .await
+    }
+
+    /// Finds the page of messages of `client_id` matching `topic_condition`,
+    /// which binds `topics` as `?2`, from the `position` `origin` was
+    /// resolved to.
+    #[allow(clippy::too_many_arguments)]
+    async fn find_messages(
+        &self,
+        client_id: &str,
+        topic_condition: &str,
+        topics: String,
+        origin: Origin<'_>,
+        position: Option<MessageCursor>,
+        filter: &MessageFilter,
+        message_count: usize,
+        comparator: &str,
+        sort_order: &str,
+    ) -> Result<StoreMessages, StoreError> {
+        let comparator = match origin {
+            Origin::MessageId(_) => format!("{comparator}="),
+            Origin::Start | Origin::Cursor(_) => comparator.to_string(),
+        };
+
+        let position = position
+            .map(|cursor| {
+                cursor
+                    .id
+                    .parse::<i64>()
+                    .map(|id| (cursor.timestamp, id))
+                    .map_err(|_| StoreError::InvalidCursor(cursor.encode()))
+            })
+            .transpose()?;
+
+        let tag_condition = filter
+            .tag
+            .as_ref()
+            .map_or_else(|| "TRUE".to_string(), sql_tag_condition);
+
+        let query = format!(
+            "SELECT id, ts, method, tag, client_id, topic, message_id, message, expires_at FROM \
+             messages WHERE client_id = ?1 AND {topic_condition} AND (?3 IS NULL OR (ts, id) \
+             {comparator} (?3, ?4)) AND (?6 IS NULL OR ts >= ?6) AND (?7 IS NULL OR ts < ?7) AND \
+             (?8 IS NULL OR method = ?8) AND {tag_condition} ORDER BY ts {sort_order}, id \
+             {sort_order} LIMIT ?5"
+        );
+
+        let page = sqlx::query_as::<_, MessageRow>(&query)
+            .bind(client_id)
+            .bind(topics)
+            .bind(position.map(|(timestamp, _)| timestamp))
+            .bind(position.map(|(_, id)| id))
+            .bind(message_count as i64 + 1)
+            .bind(filter.since.map(|since| since.timestamp_millis()))
+            .bind(filter.until.map(|until| until.timestamp_millis()))
+            .bind(filter.method.as_deref())
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(|row| {
+                (
+                    MessageCursor::new(row.ts, row.id.to_string()),
+                    Message::from(row),
+                )
+            })
+            .collect();
+
+        Ok(StoreMessages::from_page(page, origin, message_count))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn get_topics_messages(
+        &self,
+        client_id: &str,
+        topics: &[&str],
+        cursor: Option<&MessageCursor>,
+        filter: &MessageFilter,
+        message_count: usize,
+        comparator: &str,
+        sort_order: &str,
+    ) -> Result<StoreMessages, StoreError> {
+        self.find_messages(
+            client_id,
+            "topic IN (SELECT value FROM json_each(?2))",
+            serde_json::to_string(topics)?,
+            cursor.into(),
+            cursor.cloned(),
+            filter,
+            message_count,
+            comparator,
+            sort_order,
+        )
+        .await
+    }
+}
+
+#[async_trait]
+impl MessagesStore for SqliteStore {
+    async fn upsert_message(
+        &self,
+        method: &str,
+        tag: u32,
+        client_id: &str,
+        topic: &str,
+        message_id: &str,
+        message: &str,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<(), StoreError> {
+        sqlx::query(
+            "INSERT INTO messages (ts, method, tag, client_id, topic, message_id, message, \
+             expires_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?) ON CONFLICT (client_id, topic, \
+             message_id) DO UPDATE SET ts = excluded.ts, method = excluded.method, tag = \
+             excluded.tag, message = excluded.message, expires_at = excluded.expires_at",
+        )
+        .bind(Utc::now().timestamp_millis())
+        .bind(method)
+        .bind(i64::from(tag))
+        .bind(client_id)
+        .bind(topic)
+        .bind(message_id)
+        .bind(message)
+        .bind(expires_at.map(|expires_at| expires_at.timestamp_millis()))
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_messages_after(
+        &self,
+        client_id: &str,
+        topic: &str,
+        origin: Origin<'_>,
+        filter: &MessageFilter,
+        message_count: usize,
+    ) -> Result<StoreMessages, StoreError> {
+        self.get_messages(client_id, topic, origin, filter, message_count, ">", "ASC")
+            .await
+    }
+
+    async fn get_messages_before(
+        &self,
+        client_id: &str,
+        topic: &str,
+        origin: Origin<'_>,
+        filter: &MessageFilter,
+        message_count: usize,
+    ) -> Result<StoreMessages, StoreError> {
+        self.get_messages(client_id, topic, origin, filter, message_count, "<", "DESC")
+            .await
+    }
+
+    async fn get_topics_messages_after(
+        &self,
+        client_id: &str,
+        topics: &[&str],
+        cursor: Option<&MessageCursor>,
+        filter: &MessageFilter,
+        message_count: usize,
+    ) -> Result<StoreMessages, StoreError> {
+        self.get_topics_messages(client_id, topics, cursor, filter, message_count, ">", "ASC")
+            .await
+    }
+
+    async fn get_topics_messages_before(
+        &self,
+        client_id: &str,
+        topics: &[&str],
+        cursor: Option<&MessageCursor>,
+        filter: &MessageFilter,
+        message_count: usize,
+    ) -> Result<StoreMessages, StoreError> {
+        self.get_topics_messages(
+            client_id,
+            topics,
+            cursor,
+            filter,
+            message_count,
+            "<",
+            "DESC",
+        )
+        .await
+    }
+
+    async fn upsert_topic(&self, client_id: &str, topic: &str) -> Result<(), StoreError> {
+        sqlx::query(
+            "INSERT INTO topics (client_id, topic, ts) VALUES (?, ?, ?) ON CONFLICT (client_id, \
+             topic) DO UPDATE SET ts = excluded.ts",
+        )
+        .bind(client_id)
+        .bind(topic)
+        .bind(Utc::now().timestamp_millis())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn has_topic(&self, client_id: &str, topic: &str) -> Result<bool, StoreError> {
+        let count = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(1) FROM topics WHERE client_id = ? AND topic = ?",
+        )
+        .bind(client_id)
+        .bind(topic)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(count > 0)
+    }
+
+    async fn get_topics(&self, client_id: &str) -> Result<Vec<ClientTopic>, StoreError> {
+        let topics = sqlx::query_as::<_, TopicRow>(
+            "SELECT ts, client_id, topic FROM topics WHERE client_id = ? ORDER BY ts DESC",
+        )
+        .bind(client_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(topics.into_iter().map(ClientTopic::from).collect())
+    }
+
+    async fn delete_expired_messages(
+        &self,
+        stored_before: Option<DateTime<Utc>>,
+    ) -> Result<u64, StoreError> {
+        let result = sqlx::query(
+            "DELETE FROM messages WHERE expires_at <= ? OR (expires_at IS NULL AND ts <= ?)",
+        )
+        .bind(Utc::now().timestamp_millis())
+        .bind(stored_before.map(|stored_before| stored_before.timestamp_millis()))
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn delete_messages(
+        &self,
+        client_id: &str,
+        topic: Option<&str>,
+        message_ids: &[&str],
+    ) -> Result<u64, StoreError> {
+        let message_ids = serde_json::to_string(message_ids)?;
+
+        let result = sqlx::query(
+            "DELETE FROM messages WHERE client_id = ?1 AND (?2 IS NULL OR topic = ?2) AND \
+             message_id IN (SELECT value FROM json_each(?3))",
+        )
+        .bind(client_id)
+        .bind(topic)
+        .bind(message_ids)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn delete_topic(&self, client_id: &str, topic: &str) -> Result<u64, StoreError> {
+        let mut tx = self.pool.begin().await?;
+
+        let result = sqlx::query("DELETE FROM messages WHERE client_id = ? AND topic = ?")
+            .bind(client_id)
+            .bind(topic)
+            .execute(&mut tx)
+            .await?;
+        sqlx::query("DELETE FROM topics WHERE client_id = ? AND topic = ?")
+            .bind(client_id)
+            .bind(topic)
+            .execute(&mut tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn delete_all_messages(&self, client_id: &str) -> Result<u64, StoreError> {
+        let mut tx = self.pool.begin().await?;
+
+        let result = sqlx::query("DELETE FROM messages WHERE client_id = ?")
+            .bind(client_id)
+            .execute(&mut tx)
+            .await?;
+        sqlx::query("DELETE FROM topics WHERE client_id = ?")
+            .bind(client_id)
+            .execute(&mut tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn get_usage(&self, client_id: &str) -> Result<ClientUsage, StoreError> {
+        let row: Option<UsageRow> =
+            sqlx::query_as("SELECT messages, bytes FROM client_usage WHERE client_id = ?1")
+                .bind(client_id)
+                .fetch_optional(&self.pool)
+                .await?;
+
+        Ok(row.map_or_else(ClientUsage::default, |row| ClientUsage {
+            messages: row.messages.max(0) as u64,
+            bytes: row.bytes.max(0) as u64,
+        }))
+    }
+
+    async fn get_message_size(
+        &self,
+        client_id: &str,
+        topic: &str,
+        message_id: &str,
+    ) -> Result<Option<u64>, StoreError> {
+        let size = sqlx::query_scalar::<_, i64>(
+            "SELECT length(CAST(message AS BLOB)) FROM messages WHERE client_id = ? AND topic = ? \
+             AND message_id = ?",
+        )
+        .bind(client_id)
+        .bind(topic)
+        .bind(message_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(size.map(|size| size.max(0) as u64))
+    }
+
+    async fn evict_messages(
+        &self,
+        client_id: &str,
+        max_messages: Option<u64>,
+        max_bytes: Option<u64>,
+    ) -> Result<u64, StoreError> {
+        // Keeps the newest messages while their running count and size fit
+        let result = sqlx::query(
+            "DELETE FROM messages WHERE id IN (SELECT id FROM (SELECT id, ROW_NUMBER() OVER w AS \
+             kept_messages, SUM(length(CAST(message AS BLOB))) OVER w AS kept_bytes FROM messages \
+             WHERE client_id = ?1 WINDOW w AS (ORDER BY ts DESC, id DESC)) newest WHERE \
+             kept_messages > COALESCE(?2, kept_messages) OR kept_bytes > COALESCE(?3, kept_bytes))",
+        )
+        .bind(client_id)
+        .bind(max_messages.map(|max| max as i64))
+        .bind(max_bytes.map(|max| max as i64))
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+#[async_trait]
+impl RegistrationStore for SqliteStore {
+    async fn upsert_registration(
+        &self,
+        client_id: &str,
+        tags: Vec<&str>,
+        relay_url: &str,
+        max_age: Option<u64>,
+    ) -> Result<(), StoreError> {
+        let tags = serde_json::to_string(&tags)?;
+
+        sqlx::query(
+            "INSERT INTO registrations (client_id, tags, relay_url, max_age) VALUES (?, ?, ?, ?) \
+             ON CONFLICT (client_id) DO UPDATE SET tags = excluded.tags, relay_url = \
+             excluded.relay_url, max_age = excluded.max_age",
+        )
+        .bind(client_id)
+        .bind(tags)
+        .bind(relay_url)
+        .bind(max_age.map(|max_age| max_age as i64))
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_registration(&self, client_id: &str) -> Result<Registration, StoreError> {
+        let registration = sqlx::query_as::<_, RegistrationRow>(
+            "SELECT client_id, tags, relay_url, max_age FROM registrations WHERE client_id = ?",
+        )
+        .bind(client_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(registration
+            .ok_or(StoreError::NotFound(
+                "registration".to_string(),
+                client_id.to_string(),
+            ))?
+            .try_into()?)
+    }
+
+    async fn get_registrations(
+        &self,
+        client_ids: &[&str],
+    ) -> Result<Vec<Registration>, StoreError> {
+        let client_ids = serde_json::to_string(client_ids)?;
+
+        let registrations = sqlx::query_as::<_, RegistrationRow>(
+            "SELECT client_id, tags, relay_url, max_age FROM registrations WHERE client_id IN \
+             (SELECT value FROM json_each(?))",
+        )
+        .bind(client_ids)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(registrations
+            .into_iter()
+            .map(Registration::try_from)
+            .collect::<Result<_, _>>()?)
+    }
+
+    async fn delete_registration(&self, client_id: &str) -> Result<(), StoreError> {
+        let result = sqlx::query("DELETE FROM registrations WHERE client_id = ?")
+            .bind(client_id)
+            .execute(&self.pool)
+            .await?;
+
+        match result.rows_affected() {
+            0 => Err(StoreError::NotFound(
+                "registration".to_string(),
+                client_id.to_string(),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
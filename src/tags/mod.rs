@@ -1,26 +1,204 @@
-const TAG_WILDCARD: char = '*';
+use std::str::FromStr;
 
-/// Matches a tag against a pattern.
-/// The pattern can contain '*' as a wildcard to match any number.
-pub fn match_tag(tag: u32, pattern: &str) -> bool {
-    let tag = tag.to_string();
-    let mut tag_chars = tag.chars();
-    let mut pattern_chars = pattern.chars();
-
-    loop {
-        match (tag_chars.next(), pattern_chars.next()) {
-            (Some(tc), Some(pc)) => {
-                if tc != pc && pc != TAG_WILDCARD {
-                    return false;
-                }
+/// Matches any single digit of a tag.
+pub const TAG_WILDCARD: char = '*';
+const TAG_SUFFIX_WILDCARD: char = '%';
+const TAG_RANGE_SEPARATOR: char = '-';
+const TAG_LIST_SEPARATOR: char = ',';
+const TAG_NEGATION: char = '!';
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum TagPatternError {
+    #[error("the pattern is empty")]
+    Empty,
+
+    #[error(
+        "`{0}` is not valid, expected digits with `*` wildcards and an optional trailing `%`, or \
+         a `start-end` range"
+    )]
+    InvalidItem(String),
+
+    #[error("the range `{0}` ends before it starts")]
+    InvalidRange(String),
+}
+
+/// An item of a [`TagPattern`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Matcher {
+    /// Digits or `*` wildcards matching exactly one digit each, followed by
+    /// any number of digits if `any_suffix` is set.
+    Digits { digits: String, any_suffix: bool },
+    /// An inclusive range.
+    Range(u32, u32),
+}
+
+impl Matcher {
+    fn parse(item: &str) -> Result<Self, TagPatternError> {
+        let invalid = || TagPatternError::InvalidItem(item.to_string());
+
+        if let Some((start, end)) = item.split_once(TAG_RANGE_SEPARATOR) {
+            let start = parse_number(start).ok_or_else(invalid)?;
+            let end = parse_number(end).ok_or_else(invalid)?;
+            if start > end {
+                return Err(TagPatternError::InvalidRange(item.to_string()));
             }
-            (Some(_), None) => return false,
-            (None, Some(_)) => return false,
-            (None, None) => return true,
+            return Ok(Matcher::Range(start, end));
         }
+
+        let (digits, any_suffix) = match item.strip_suffix(TAG_SUFFIX_WILDCARD) {
+            Some(digits) => (digits, true),
+            None => (item, false),
+        };
+        if !digits
+            .chars()
+            .all(|c| c.is_ascii_digit() || c == TAG_WILDCARD)
+            || (digits.is_empty() && !any_suffix)
+        {
+            return Err(invalid());
+        }
+
+        Ok(Matcher::Digits {
+            digits: digits.to_string(),
+            any_suffix,
+        })
+    }
+
+    /// The inclusive range of the tags matched, if they form one. Digits only
+    /// followed by wildcards match a range of the tags of their length, e.g.
+    /// `11**` matches `1100-1199`.
+    pub fn as_range(&self) -> Option<(u32, u32)> {
+        let digits = match self {
+            Matcher::Range(start, end) => return Some((*start, *end)),
+            Matcher::Digits {
+                any_suffix: true, ..
+            } => return None,
+            Matcher::Digits { digits, .. } => digits,
+        };
+
+        let prefix = digits.trim_end_matches(TAG_WILDCARD);
+        // Tags are written without leading zeros, `0` aside
+        if prefix.contains(TAG_WILDCARD) || (prefix.starts_with('0') && digits != "0") {
+            return None;
+        }
+
+        let wildcards = u32::try_from(digits.len() - prefix.len()).ok()?;
+        let span = 10_u64.checked_pow(wildcards)?;
+        let start = match prefix {
+            "" if wildcards == 1 => 0,
+            "" => span / 10,
+            prefix => prefix.parse::<u64>().ok()?.checked_mul(span)?,
+        };
+        let end = (if prefix.is_empty() {
+            span
+        } else {
+            start + span
+        }) - 1;
+
+        Some((
+            u32::try_from(start).ok()?,
+            u32::try_from(end).unwrap_or(u32::MAX),
+        ))
+    }
+
+    fn matches(&self, tag: u32, tag_digits: &str) -> bool {
+        match self {
+            Matcher::Range(start, end) => (*start..=*end).contains(&tag),
+            Matcher::Digits { digits, any_suffix } => {
+                let length_matches = if *any_suffix {
+                    tag_digits.len() >= digits.len()
+                } else {
+                    tag_digits.len() == digits.len()
+                };
+
+                length_matches
+                    && tag_digits
+                        .chars()
+                        .zip(digits.chars())
+                        .all(|(tc, pc)| tc == pc || pc == TAG_WILDCARD)
+            }
+        }
+    }
+}
+
+fn parse_number(number: &str) -> Option<u32> {
+    if number.is_empty() || !number.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    number.parse().ok()
+}
+
+/// A tag pattern, as registered by clients.
+///
+/// A pattern is a comma separated list of items, each of which is either:
+/// - digits where `*` matches any single digit, e.g. `1*34`,
+/// - digits followed by `%` matching any number of digits, e.g. `11%`,
+/// - an inclusive range, e.g. `1100-1199`.
+///
+/// Items prefixed with `!` exclude the tags they match. A tag matches the
+/// pattern if it matches any of the other items, or if there are none.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagPattern {
+    included: Vec<Matcher>,
+    excluded: Vec<Matcher>,
+}
+
+impl TagPattern {
+    pub fn parse(pattern: &str) -> Result<Self, TagPatternError> {
+        if pattern.trim().is_empty() {
+            return Err(TagPatternError::Empty);
+        }
+
+        let mut included = vec![];
+        let mut excluded = vec![];
+        for item in pattern.split(TAG_LIST_SEPARATOR).map(str::trim) {
+            match item.strip_prefix(TAG_NEGATION) {
+                Some(item) => excluded.push(Matcher::parse(item.trim())?),
+                None => included.push(Matcher::parse(item)?),
+            }
+        }
+
+        Ok(TagPattern { included, excluded })
+    }
+
+    /// The items including the tags they match.
+    pub fn included(&self) -> &[Matcher] {
+        &self.included
+    }
+
+    /// The `!` prefixed items excluding the tags they match.
+    pub fn excluded(&self) -> &[Matcher] {
+        &self.excluded
+    }
+
+    pub fn matches(&self, tag: u32) -> bool {
+        let tag_digits = tag.to_string();
+
+        (self.included.is_empty()
+            || self
+                .included
+                .iter()
+                .any(|matcher| matcher.matches(tag, &tag_digits)))
+            && !self
+                .excluded
+                .iter()
+                .any(|matcher| matcher.matches(tag, &tag_digits))
     }
 }
 
+impl FromStr for TagPattern {
+    type Err = TagPatternError;
+
+    fn from_str(pattern: &str) -> Result<Self, Self::Err> {
+        TagPattern::parse(pattern)
+    }
+}
+
+/// Matches a tag against a pattern, see [`TagPattern`] for the syntax.
+/// Invalid patterns don't match any tag.
+pub fn match_tag(tag: u32, pattern: &str) -> bool {
+    TagPattern::parse(pattern).map_or(false, |pattern| pattern.matches(tag))
+}
+
 #[cfg(test)]
 mod test_match_tag {
     use super::*;
@@ -60,4 +238,113 @@ mod test_match_tag {
         assert!(!match_tag(1234, "12*"));
         assert!(!match_tag(1234, "*23"));
     }
+
+    #[test]
+    fn test_suffix_wildcard() {
+        assert!(match_tag(1100, "11%"));
+        assert!(match_tag(11, "11%"));
+        assert!(match_tag(112345, "11%"));
+        assert!(match_tag(1234, "1*3%"));
+        assert!(match_tag(1234, "%"));
+        assert!(!match_tag(1, "11%"));
+        assert!(!match_tag(1200, "11%"));
+    }
+
+    #[test]
+    fn test_range() {
+        assert!(match_tag(1100, "1100-1199"));
+        assert!(match_tag(1150, "1100-1199"));
+        assert!(match_tag(1199, "1100-1199"));
+        assert!(!match_tag(1099, "1100-1199"));
+        assert!(!match_tag(1200, "1100-1199"));
+        assert!(match_tag(5, "5-5"));
+    }
+
+    #[test]
+    fn test_list() {
+        assert!(match_tag(4000, "4000, 5***"));
+        assert!(match_tag(5123, "4000,5***"));
+        assert!(match_tag(1150, "4000,1100-1199"));
+        assert!(!match_tag(6000, "4000,5***"));
+    }
+
+    #[test]
+    fn test_negation() {
+        assert!(match_tag(1000, "1000-1999,!1100-1199"));
+        assert!(!match_tag(1150, "1000-1999,!1100-1199"));
+        assert!(match_tag(4000, "!5%"));
+        assert!(!match_tag(5000, "!5%"));
+        assert!(!match_tag(2000, "1000-1999,!1100-1199"));
+    }
+
+    #[test]
+    fn test_as_range() {
+        for (item, range) in [
+            ("11**", Some((1100, 1199))),
+            ("1234", Some((1234, 1234))),
+            ("0", Some((0, 0))),
+            ("*", Some((0, 9))),
+            ("***", Some((100, 999))),
+            ("4***", Some((4000, 4999))),
+            ("4*********", Some((4000000000, u32::MAX))),
+            ("1100-1199", Some((1100, 1199))),
+            ("1*34", None),
+            ("11%", None),
+            ("0*", None),
+            ("5*********", None),
+            ("***********", None),
+        ] {
+            let matcher = Matcher::parse(item).unwrap();
+            assert_eq!(matcher.as_range(), range, "{item}");
+
+            // The range matches the same tags as the item
+            if let Some((start, end)) = range {
+                for tag in [
+                    0,
+                    9,
+                    10,
+                    99,
+                    100,
+                    1099,
+                    1100,
+                    1199,
+                    1200,
+                    4000000000,
+                    u32::MAX,
+                ] {
+                    assert_eq!(
+                        (start..=end).contains(&tag),
+                        matcher.matches(tag, &tag.to_string()),
+                        "{item} {tag}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid() {
+        for (pattern, error) in [
+            ("", TagPatternError::Empty),
+            ("  ", TagPatternError::Empty),
+            ("12a4", TagPatternError::InvalidItem("12a4".to_string())),
+            ("1%2", TagPatternError::InvalidItem("1%2".to_string())),
+            ("1000,", TagPatternError::InvalidItem("".to_string())),
+            ("!", TagPatternError::InvalidItem("".to_string())),
+            ("1*-2", TagPatternError::InvalidItem("1*-2".to_string())),
+            ("-2", TagPatternError::InvalidItem("-2".to_string())),
+            ("1-2-3", TagPatternError::InvalidItem("1-2-3".to_string())),
+            (
+                "99999999999-1",
+                TagPatternError::InvalidItem("99999999999-1".to_string()),
+            ),
+            (
+                "1199-1100",
+                TagPatternError::InvalidRange("1199-1100".to_string()),
+            ),
+        ] {
+            assert_eq!(TagPattern::parse(pattern), Err(error), "{pattern}");
+            assert!(!match_tag(1, pattern), "{pattern}");
+        }
+    }
 }
@@ -0,0 +1,122 @@
+use {
+    crate::{context::MemoryServerContext, get_client_jwt, TEST_RELAY_URL},
+    axum::http,
+    gilgamesh::{
+        handlers::{
+            get_messages::GetMessagesResponse,
+            query_messages::{QueryMessagesBody, QueryMessagesResponse},
+            save_message::HistoryPayload,
+        },
+        store::registrations::Registration,
+    },
+    relay_rpc::domain::ClientId,
+    std::sync::Arc,
+    test_context::test_context,
+};
+
+const TEST_METHOD: &str = "publish";
+const TEST_TOPIC: &str = "test-topic";
+const TEST_MESSAGE: &str = "test-message";
+
+async fn register(ctx: &MemoryServerContext, client_id: &ClientId) {
+    ctx.server
+        .registration_store
+        .registrations
+        .insert(client_id.to_string(), Registration {
+            id: None,
+            client_id: client_id.clone().into_value(),
+            tags: vec![Arc::from("4000")],
+            relay_url: Arc::from(TEST_RELAY_URL),
+            max_age: None,
+        })
+        .await;
+}
+
+async fn save_message(ctx: &MemoryServerContext, client_id: &ClientId, message_id: &str) {
+    let response = reqwest::Client::new()
+        .post(format!("http://{}/messages", ctx.server.public_addr))
+        .json(&HistoryPayload {
+            method: Arc::from(TEST_METHOD),
+            client_id: client_id.clone().into_value(),
+            topic: Arc::from(TEST_TOPIC),
+            message_id: Arc::from(message_id),
+            tag: 4000,
+            message: Arc::from(TEST_MESSAGE),
+        })
+        .send()
+        .await
+        .expect("Call failed");
+    assert!(response.status().is_success());
+}
+
+async fn get_messages(ctx: &MemoryServerContext, jwt: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .get(format!("http://{}/messages", ctx.server.public_addr))
+        .query(&[("topic", TEST_TOPIC)])
+        .header(http::header::AUTHORIZATION, format!("Bearer {jwt}"))
+        .send()
+        .await
+        .expect("Call failed")
+}
+
+async fn query_messages(ctx: &MemoryServerContext, jwt: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("http://{}/messages/query", ctx.server.public_addr))
+        .json(&QueryMessagesBody {
+            topics: vec![Arc::from(TEST_TOPIC)],
+            cursor: None,
+            message_count: Default::default(),
+            direction: None,
+            since: None,
+            until: None,
+            method: None,
+            tag: None,
+        })
+        .header(http::header::AUTHORIZATION, format!("Bearer {jwt}"))
+        .send()
+        .await
+        .expect("Call failed")
+}
+
+#[test_context(MemoryServerContext)]
+#[tokio::test]
+async fn test_other_client_denied(ctx: &mut MemoryServerContext) {
+    let (_, client_a) = get_client_jwt();
+    let (jwt_b, _) = get_client_jwt();
+    register(ctx, &client_a).await;
+    save_message(ctx, &client_a, "1").await;
+
+    let response = get_messages(ctx, &jwt_b).await;
+    assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+
+    let response = query_messages(ctx, &jwt_b).await;
+    assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+}
+
+#[test_context(MemoryServerContext)]
+#[tokio::test]
+async fn test_shared_topic_isolated(ctx: &mut MemoryServerContext) {
+    let (jwt_a, client_a) = get_client_jwt();
+    let (jwt_b, client_b) = get_client_jwt();
+    register(ctx, &client_a).await;
+    register(ctx, &client_b).await;
+    save_message(ctx, &client_a, "1").await;
+    save_message(ctx, &client_b, "2").await;
+
+    for (jwt, client_id, message_id) in [(&jwt_a, &client_a, "1"), (&jwt_b, &client_b, "2")] {
+        let response = get_messages(ctx, jwt).await;
+        assert!(response.status().is_success());
+
+        let response: GetMessagesResponse = response.json().await.unwrap();
+        assert_eq!(response.messages.len(), 1);
+        assert_eq!(response.messages[0].client_id.as_ref(), client_id.as_ref());
+        assert_eq!(response.messages[0].message_id.as_ref(), message_id);
+    }
+
+    let response = query_messages(ctx, &jwt_b).await;
+    assert!(response.status().is_success());
+
+    let response: QueryMessagesResponse = response.json().await.unwrap();
+    assert_eq!(response.messages.len(), 1);
+    assert_eq!(response.messages[0].client_id.as_ref(), client_b.as_ref());
+}
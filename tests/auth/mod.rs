@@ -0,0 +1,65 @@
+use {
+    crate::{
+        context::{AuthContext, TEST_AUTH_AUDIENCE},
+        get_client_jwt,
+    },
+    axum::http,
+    relay_rpc::{
+        auth::{
+            ed25519_dalek::Keypair,
+            rand::{rngs::StdRng, SeedableRng},
+            AuthToken,
+        },
+        domain::{ClientId, DecodedClientId},
+    },
+    serde_json::Value,
+    std::time::Duration,
+    test_context::test_context,
+};
+
+fn get_jwt(aud: &str, ttl: Option<Duration>) -> String {
+    let keypair = Keypair::generate(&mut StdRng::from_entropy());
+    let client_id = ClientId::from(DecodedClientId(*keypair.public_key().as_bytes()));
+
+    AuthToken::new(client_id.to_string())
+        .aud(aud.to_string())
+        .ttl(ttl)
+        .as_jwt(&keypair)
+        .unwrap()
+        .to_string()
+}
+
+async fn get_registration(ctx: &AuthContext, jwt: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .get(format!("http://{}/register", ctx.server.public_addr))
+        .header(http::header::AUTHORIZATION, format!("Bearer {jwt}"))
+        .send()
+        .await
+        .expect("Call failed")
+}
+
+#[test_context(AuthContext)]
+#[tokio::test]
+async fn test_configured_audience(ctx: &mut AuthContext) {
+    let jwt = get_jwt(TEST_AUTH_AUDIENCE, Some(Duration::from_secs(60)));
+    let response = get_registration(ctx, &jwt).await;
+    assert_ne!(response.status(), http::StatusCode::UNAUTHORIZED);
+    assert_ne!(response.status(), http::StatusCode::FORBIDDEN);
+
+    // The default audiences are no longer accepted
+    let (jwt, _) = get_client_jwt();
+    let response = get_registration(ctx, &jwt).await;
+    assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+}
+
+#[test_context(AuthContext)]
+#[tokio::test]
+async fn test_required_expiration(ctx: &mut AuthContext) {
+    let jwt = get_jwt(TEST_AUTH_AUDIENCE, None);
+    let response = get_registration(ctx, &jwt).await;
+    assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "FAILURE");
+    assert_eq!(body["errors"][0]["name"], "jwt");
+}
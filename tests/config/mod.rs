@@ -0,0 +1,130 @@
+use {
+    gilgamesh::{
+        config::{get_config_from, QuotaPolicy, StorageBackend},
+        error::Error,
+    },
+    std::{env, fs, path::PathBuf},
+};
+
+/// Writes `contents` to a file unique to the test and process.
+fn write_file(name: &str, contents: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("gilgamesh-{}-{name}", std::process::id()));
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+    vars.iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+#[test]
+fn test_config_file_with_overrides() {
+    let file = write_file(
+        "config.toml",
+        r#"
+        port = 8080
+        public_url = "https://history.example.com"
+        log_level = "info"
+        storage_backend = "mongo"
+        quota_policy = "evict"
+        auth_audiences = ["https://history.example.com", "wss://relay.example.com"]
+        telemetry_prometheus_port = 8081
+        "#,
+    );
+    let secret = write_file("file-mongo-address", "mongodb://user:secret@db/history\n");
+
+    let config = get_config_from(vars(&[
+        ("CONFIG_FILE", file.to_str().unwrap()),
+        ("LOG_LEVEL", "debug"),
+        ("MONGO_ADDRESS_FILE", secret.to_str().unwrap()),
+    ]))
+    .unwrap();
+
+    assert_eq!(config.port, 8080);
+    assert_eq!(config.public_url, "https://history.example.com");
+    assert_eq!(config.log_level, "debug");
+    assert_eq!(config.storage_backend, StorageBackend::Mongo);
+    assert_eq!(config.quota_policy, QuotaPolicy::Evict);
+    assert_eq!(config.auth_audiences, vec![
+        "https://history.example.com".to_string(),
+        "wss://relay.example.com".to_string(),
+    ]);
+    assert_eq!(config.mongo_address, "mongodb://user:secret@db/history");
+    assert!(config.is_valid().is_ok());
+}
+
+#[test]
+fn test_default_config_valid() {
+    // Only the settings without a default are set
+    let config = get_config_from(vars(&[
+        ("PUBLIC_URL", "http://localhost:3001"),
+        ("MONGO_ADDRESS", "mongodb://localhost:27017"),
+    ]))
+    .unwrap();
+
+    assert_ne!(config.port, config.prometheus_port());
+    assert!(config.is_valid().is_ok());
+}
+
+#[test]
+fn test_secret_set_twice() {
+    let secret = write_file("mongo-address", "mongodb://mongo/history");
+
+    let result = get_config_from(vars(&[
+        ("PUBLIC_URL", "https://history.example.com"),
+        ("MONGO_ADDRESS", "mongodb://mongo/history"),
+        ("MONGO_ADDRESS_FILE", secret.to_str().unwrap()),
+    ]));
+
+    assert!(matches!(result, Err(Error::InvalidConfiguration(_))));
+}
+
+#[test]
+fn test_invalid_config_reports_every_problem() {
+    let config = get_config_from(vars(&[
+        ("PUBLIC_URL", "history.example.com"),
+        ("RELAY_URL", "ftp://relay.example.com"),
+        ("MONGO_ADDRESS", "mongo:27017"),
+        ("LOG_LEVEL", "info,gilgamesh=loud"),
+        ("MESSAGE_MAX_AGE", "18446744073709551615"),
+        ("TELEMETRY_PROMETHEUS_PORT", "3001"),
+    ]))
+    .unwrap();
+
+    let Err(Error::InvalidConfiguration(problems)) = config.is_valid() else {
+        panic!("the configuration should be invalid");
+    };
+    for name in [
+        "PUBLIC_URL",
+        "RELAY_URL",
+        "MONGO_ADDRESS",
+        "LOG_LEVEL",
+        "MESSAGE_MAX_AGE",
+        "TELEMETRY_PROMETHEUS_PORT",
+    ] {
+        assert!(problems.contains(name), "{name} not reported: {problems}");
+    }
+}
+
+#[test]
+fn test_zero_limits_reported() {
+    let config = get_config_from(vars(&[
+        ("PUBLIC_URL", "http://localhost:3001"),
+        ("MONGO_ADDRESS", "mongodb://localhost:27017"),
+        ("MESSAGE_MAX_AGE", "0"),
+        ("SIGNATURE_MAX_AGE", "0"),
+    ]))
+    .unwrap();
+
+    let Err(Error::InvalidConfiguration(problems)) = config.is_valid() else {
+        panic!("the configuration should be invalid");
+    };
+    for name in ["MESSAGE_MAX_AGE", "SIGNATURE_MAX_AGE"] {
+        assert!(
+            problems.contains(&format!("`{name}` must be greater than zero")),
+            "{name} not reported: {problems}"
+        );
+    }
+}
@@ -1,9 +1,15 @@
 use {
-    self::{server::Gilgamesh, store::PersistentStorage},
+    self::{relay::StandInRelay, server::Gilgamesh, store::PersistentStorage},
     async_trait::async_trait,
+    gilgamesh::config::QuotaPolicy,
+    relay_rpc::auth::{
+        ed25519_dalek::Keypair,
+        rand::{rngs::StdRng, SeedableRng},
+    },
     test_context::AsyncTestContext,
 };
 
+pub mod relay;
 mod server;
 mod store;
 
@@ -23,6 +29,161 @@ impl AsyncTestContext for ServerContext {
     }
 }
 
+/// A server storing its messages in memory.
+#[cfg(feature = "memory-store")]
+pub struct MemoryServerContext {
+    pub server: Gilgamesh,
+}
+
+#[cfg(feature = "memory-store")]
+#[async_trait]
+impl AsyncTestContext for MemoryServerContext {
+    async fn setup() -> Self {
+        let server = Gilgamesh::start_in_memory().await;
+        Self { server }
+    }
+
+    async fn teardown(mut self) {
+        self.server.shutdown().await;
+    }
+}
+
+/// The size and count limits of the quota contexts.
+pub const TEST_MAX_MESSAGE_SIZE: usize = 32;
+pub const TEST_CLIENT_MAX_MESSAGES: u64 = 2;
+
+/// A server limiting the size of messages and the number stored per client,
+/// rejecting the messages over quota.
+pub struct QuotaContext {
+    pub server: Gilgamesh,
+}
+
+/// A server limiting the number of messages stored per client, evicting the
+/// oldest ones to make room.
+pub struct EvictionContext {
+    pub server: Gilgamesh,
+}
+
+async fn start_with_quota(policy: QuotaPolicy) -> Gilgamesh {
+    Gilgamesh::start_with(move |config| {
+        config.max_message_size = Some(TEST_MAX_MESSAGE_SIZE);
+        config.client_max_messages = Some(TEST_CLIENT_MAX_MESSAGES);
+        config.quota_policy = policy;
+    })
+    .await
+}
+
+#[async_trait]
+impl AsyncTestContext for QuotaContext {
+    async fn setup() -> Self {
+        let server = start_with_quota(QuotaPolicy::Reject).await;
+        Self { server }
+    }
+
+    async fn teardown(mut self) {
+        self.server.shutdown().await;
+    }
+}
+
+#[async_trait]
+impl AsyncTestContext for EvictionContext {
+    async fn setup() -> Self {
+        let server = start_with_quota(QuotaPolicy::Evict).await;
+        Self { server }
+    }
+
+    async fn teardown(mut self) {
+        self.server.shutdown().await;
+    }
+}
+
+/// The rate limits of `/register` and of the relay's deliveries in the rate
+/// limit context, and of the relay's routes in the relay context.
+pub const TEST_RATE_LIMIT: &str = "2/60";
+
+/// A server limiting the rate of `/register` and of the relay's deliveries.
+pub struct RateLimitContext {
+    pub server: Gilgamesh,
+}
+
+#[async_trait]
+impl AsyncTestContext for RateLimitContext {
+    async fn setup() -> Self {
+        let server = Gilgamesh::start_with(|config| {
+            config.register_rate_limit = Some(TEST_RATE_LIMIT.parse().unwrap());
+            config.relay_rate_limit = Some(TEST_RATE_LIMIT.parse().unwrap());
+        })
+        .await;
+        Self { server }
+    }
+
+    async fn teardown(mut self) {
+        self.server.shutdown().await;
+    }
+}
+
+/// The only audience accepted by the auth context.
+pub const TEST_AUTH_AUDIENCE: &str = "https://history.example.com";
+
+/// A server accepting its own audience only, in JWTs which must expire.
+pub struct AuthContext {
+    pub server: Gilgamesh,
+}
+
+#[async_trait]
+impl AsyncTestContext for AuthContext {
+    async fn setup() -> Self {
+        let server = Gilgamesh::start_with(|config| {
+            config.auth_audiences = vec![TEST_AUTH_AUDIENCE.to_string()];
+            config.auth_require_exp = true;
+        })
+        .await;
+        Self { server }
+    }
+
+    async fn teardown(mut self) {
+        self.server.shutdown().await;
+    }
+}
+
+/// A server backed by a stand-in relay, validating signatures, limiting the
+/// rate of the other requests to the relay's routes and registering its watch
+/// with it.
+pub struct RelayContext {
+    pub server: Gilgamesh,
+    pub relay: StandInRelay,
+    /// The keypair the server identifies its watch with.
+    pub keypair: Keypair,
+}
+
+#[async_trait]
+impl AsyncTestContext for RelayContext {
+    async fn setup() -> Self {
+        let relay = StandInRelay::start().await;
+        let seed = [7; 32];
+
+        let relay_url = relay.url();
+        let server = Gilgamesh::start_with(move |config| {
+            config.relay_url = relay_url;
+            config.relay_keypair_seed = Some(hex::encode(seed));
+            config.validate_signatures = true;
+            config.relay_rate_limit = Some(TEST_RATE_LIMIT.parse().unwrap());
+        })
+        .await;
+
+        Self {
+            server,
+            relay,
+            keypair: Keypair::generate(&mut StdRng::from_seed(seed)),
+        }
+    }
+
+    async fn teardown(mut self) {
+        self.server.shutdown().await;
+        self.relay.shutdown();
+    }
+}
+
 #[derive(Clone)]
 pub struct StoreContext {
     pub storage: PersistentStorage,
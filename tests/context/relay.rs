@@ -0,0 +1,101 @@
+use {
+    super::server::get_random_port,
+    axum::{
+        extract::State,
+        routing::{get, post},
+        Json,
+        Router,
+    },
+    relay_rpc::auth::{
+        ed25519_dalek::Keypair,
+        rand::{rngs::StdRng, SeedableRng},
+    },
+    serde_json::{json, Value},
+    std::{
+        net::{IpAddr, Ipv4Addr, SocketAddr},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+            Mutex,
+        },
+    },
+    tokio::sync::oneshot,
+};
+
+/// A stand-in for the relay, serving its public key and recording the RPC
+/// requests it receives.
+pub struct StandInRelay {
+    pub addr: SocketAddr,
+    pub keypair: Arc<Keypair>,
+    pub requests: Arc<Mutex<Vec<Value>>>,
+    /// The number of times the public key was fetched.
+    pub key_fetches: Arc<AtomicUsize>,
+    shutdown_signal: Option<oneshot::Sender<()>>,
+}
+
+#[derive(Clone)]
+struct RelayState {
+    keypair: Arc<Keypair>,
+    requests: Arc<Mutex<Vec<Value>>>,
+    key_fetches: Arc<AtomicUsize>,
+}
+
+impl StandInRelay {
+    pub async fn start() -> Self {
+        let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), get_random_port());
+        let keypair = Arc::new(Keypair::generate(&mut StdRng::from_entropy()));
+        let requests = Arc::new(Mutex::new(vec![]));
+        let key_fetches = Arc::new(AtomicUsize::new(0));
+
+        let app = Router::new()
+            .route("/public-key", get(public_key))
+            .route("/rpc", post(rpc))
+            .with_state(RelayState {
+                keypair: keypair.clone(),
+                requests: requests.clone(),
+                key_fetches: key_fetches.clone(),
+            });
+
+        let (signal, shutdown) = oneshot::channel();
+        let server = axum::Server::bind(&addr)
+            .serve(app.into_make_service())
+            .with_graceful_shutdown(async {
+                let _ = shutdown.await;
+            });
+        tokio::spawn(server);
+
+        Self {
+            addr,
+            keypair,
+            requests,
+            key_fetches,
+            shutdown_signal: Some(signal),
+        }
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    pub fn shutdown(&mut self) {
+        if let Some(signal) = self.shutdown_signal.take() {
+            let _ = signal.send(());
+        }
+    }
+}
+
+async fn public_key(State(state): State<RelayState>) -> String {
+    state.key_fetches.fetch_add(1, Ordering::SeqCst);
+    hex::encode(state.keypair.public_key().as_bytes())
+}
+
+async fn rpc(State(state): State<RelayState>, Json(request): Json<Value>) -> Json<Value> {
+    let id = request["id"].clone();
+    state.requests.lock().unwrap().push(request);
+
+    Json(json!({
+        "id": id,
+        "jsonrpc": "2.0",
+        "result": true,
+    }))
+}
@@ -0,0 +1,78 @@
+use {gilgamesh::hub::MessageHub, std::time::Duration, tokio::time::timeout};
+
+const TEST_CLIENT_ID: &str = "12345";
+const TEST_TOPIC: &str = "test-topic";
+
+#[tokio::test]
+async fn test_notify_subscribers() {
+    let hub = MessageHub::new();
+    let mut subscription = hub.subscribe(TEST_CLIENT_ID, TEST_TOPIC);
+    let mut other_topic = hub.subscribe(TEST_CLIENT_ID, "other-topic");
+
+    hub.notify(TEST_CLIENT_ID, TEST_TOPIC);
+    timeout(Duration::from_secs(1), subscription.changed())
+        .await
+        .expect("The subscriber wasn't notified")
+        .unwrap();
+    assert!(
+        timeout(Duration::from_millis(100), other_topic.changed())
+            .await
+            .is_err(),
+        "Another topic's subscriber was notified"
+    );
+}
+
+#[tokio::test]
+async fn test_unsubscribe_removes_topic() {
+    let hub = MessageHub::new();
+    let first = hub.subscribe(TEST_CLIENT_ID, TEST_TOPIC);
+    let second = hub.subscribe(TEST_CLIENT_ID, TEST_TOPIC);
+    let other_topic = hub.subscribe(TEST_CLIENT_ID, "other-topic");
+    assert_eq!(hub.topic_count(), 2);
+
+    drop(first);
+    assert_eq!(
+        hub.topic_count(),
+        2,
+        "check the topic kept with a subscriber"
+    );
+
+    drop(second);
+    drop(other_topic);
+    assert_eq!(hub.topic_count(), 0, "check the topics removed");
+
+    // Notifying topics without subscribers doesn't track them.
+    hub.notify(TEST_CLIENT_ID, TEST_TOPIC);
+    assert_eq!(hub.topic_count(), 0);
+}
+
+#[tokio::test]
+async fn test_client_streams() {
+    let hub = MessageHub::new();
+    let mut first = hub.open_stream(TEST_CLIENT_ID, 2).unwrap();
+    let second = hub.open_stream(TEST_CLIENT_ID, 2).unwrap();
+    let mut other_client = hub.open_stream("67890", 2).unwrap();
+    assert!(
+        hub.open_stream(TEST_CLIENT_ID, 2).is_none(),
+        "check the client's streams limited"
+    );
+    assert_eq!(hub.stream_count(TEST_CLIENT_ID), 2);
+
+    drop(second);
+    assert_eq!(hub.stream_count(TEST_CLIENT_ID), 1);
+
+    hub.close_streams(TEST_CLIENT_ID);
+    timeout(Duration::from_secs(1), first.closed())
+        .await
+        .expect("The stream wasn't closed");
+    assert!(
+        timeout(Duration::from_millis(100), other_client.closed())
+            .await
+            .is_err(),
+        "Another client's stream was closed"
+    );
+
+    drop(first);
+    drop(other_client);
+    assert_eq!(hub.stream_count(TEST_CLIENT_ID), 0);
+}
@@ -11,12 +11,26 @@ use {
     },
 };
 
+#[cfg(feature = "memory-store")]
+mod access;
+mod auth;
+mod config;
 mod context;
+mod hub;
 mod messages;
 mod metrics;
+mod quota;
+mod rate_limit;
 mod registration;
+mod relay;
+mod reload;
+#[cfg(feature = "memory-store")]
+mod resume;
 mod simple;
 mod storage;
+#[cfg(feature = "memory-store")]
+mod stream;
+mod watch;
 
 const TEST_RELAY_URL: &str = "https://history.walletconnect.com";
 
@@ -1,16 +1,24 @@
 use {
-    crate::{context::ServerContext, get_client_jwt, TEST_RELAY_URL},
+    crate::{context::ServerContext, get_client_jwt, get_invalid_client_jwt, TEST_RELAY_URL},
     axum::http,
     chrono::Utc,
     gilgamesh::{
         handlers::{
+            delete_messages::{DeleteMessagesBody, DeleteMessagesResponse},
             get_messages::{Direction, GetMessagesResponse},
+            get_topics::GetTopicsResponse,
+            query_messages::{QueryMessagesBody, QueryMessagesResponse, MAX_QUERY_TOPICS},
             save_message::HistoryPayload,
+            save_messages::{SaveMessageStatus, SaveMessagesResponse, MAX_BATCH_SIZE},
+        },
+        store::{
+            messages::{Message, MessageCursor, MessagesStore},
+            registrations::Registration,
         },
-        store::{messages::Message, registrations::Registration},
     },
     std::sync::Arc,
     test_context::test_context,
+    tokio::time::{sleep, Duration, Instant},
 };
 
 const TEST_METHOD: &str = "publish";
@@ -22,18 +30,24 @@ const TEST_MESSAGE: &str = "test-message";
 #[test_context(ServerContext)]
 #[tokio::test]
 async fn test_get_message_no_origin_no_count_no_direction(ctx: &mut ServerContext) {
-    let (jwt, _) = get_client_jwt();
+    let (jwt, client_id) = get_client_jwt();
 
+    ctx.server
+        .message_store
+        .test_add_topic(client_id.value(), TEST_TOPIC)
+        .await;
     ctx.server
         .message_store
         .test_add(Message {
             id: None,
             timestamp: Utc::now().into(),
             method: Arc::from(TEST_METHOD),
+            tag: None,
             client_id: Arc::from(TEST_CLIENT_ID),
             message_id: Arc::from(TEST_MESSAGE_ID),
             topic: Arc::from(TEST_TOPIC),
             message: Arc::from(TEST_MESSAGE),
+            expires_at: None,
         })
         .await;
 
@@ -58,6 +72,14 @@ async fn test_get_message_no_origin_no_count_no_direction(ctx: &mut ServerContex
     assert_eq!(response.topic.as_ref(), TEST_TOPIC);
     assert_eq!(response.direction, Direction::Forward);
     assert_eq!(response.next_id.unwrap().as_ref(), "after");
+    assert_eq!(
+        response.next_cursor,
+        Some(MessageCursor::new(2, "after").encode())
+    );
+    assert_eq!(
+        response.prev_cursor,
+        Some(MessageCursor::new(1, "after").encode())
+    );
 
     assert_eq!(response.messages.len(), 1);
     assert_eq!(response.messages[0].client_id.as_ref(), TEST_CLIENT_ID);
@@ -69,18 +91,24 @@ async fn test_get_message_no_origin_no_count_no_direction(ctx: &mut ServerContex
 #[test_context(ServerContext)]
 #[tokio::test]
 async fn test_get_message_origin_count_forward(ctx: &mut ServerContext) {
-    let (jwt, _) = get_client_jwt();
+    let (jwt, client_id) = get_client_jwt();
 
+    ctx.server
+        .message_store
+        .test_add_topic(client_id.value(), TEST_TOPIC)
+        .await;
     ctx.server
         .message_store
         .test_add(Message {
             id: None,
             timestamp: Utc::now().into(),
             method: Arc::from(TEST_METHOD),
+            tag: None,
             client_id: Arc::from(TEST_CLIENT_ID),
             message_id: Arc::from(TEST_MESSAGE_ID),
             topic: Arc::from(TEST_TOPIC),
             message: Arc::from(TEST_MESSAGE),
+            expires_at: None,
         })
         .await;
 
@@ -110,6 +138,14 @@ async fn test_get_message_origin_count_forward(ctx: &mut ServerContext) {
     assert_eq!(response.topic.as_ref(), TEST_TOPIC);
     assert_eq!(response.direction, Direction::Forward);
     assert_eq!(response.next_id.unwrap().as_ref(), "after");
+    assert_eq!(
+        response.next_cursor,
+        Some(MessageCursor::new(2, "after").encode())
+    );
+    assert_eq!(
+        response.prev_cursor,
+        Some(MessageCursor::new(1, "after").encode())
+    );
 
     assert_eq!(response.messages.len(), 1);
     assert_eq!(response.messages[0].client_id.as_ref(), TEST_CLIENT_ID);
@@ -121,18 +157,24 @@ async fn test_get_message_origin_count_forward(ctx: &mut ServerContext) {
 #[test_context(ServerContext)]
 #[tokio::test]
 async fn test_get_message_origin_count_backward(ctx: &mut ServerContext) {
-    let (jwt, _) = get_client_jwt();
+    let (jwt, client_id) = get_client_jwt();
 
+    ctx.server
+        .message_store
+        .test_add_topic(client_id.value(), TEST_TOPIC)
+        .await;
     ctx.server
         .message_store
         .test_add(Message {
             id: None,
             timestamp: Utc::now().into(),
             method: Arc::from(TEST_METHOD),
+            tag: None,
             client_id: Arc::from(TEST_CLIENT_ID),
             message_id: Arc::from(TEST_MESSAGE_ID),
             topic: Arc::from(TEST_TOPIC),
             message: Arc::from(TEST_MESSAGE),
+            expires_at: None,
         })
         .await;
 
@@ -170,6 +212,460 @@ async fn test_get_message_origin_count_backward(ctx: &mut ServerContext) {
     assert_eq!(response.messages[0].message.as_ref(), TEST_MESSAGE);
 }
 
+#[test_context(ServerContext)]
+#[tokio::test]
+async fn test_get_message_cursor(ctx: &mut ServerContext) {
+    let (jwt, client_id) = get_client_jwt();
+
+    ctx.server
+        .message_store
+        .test_add_topic(client_id.value(), TEST_TOPIC)
+        .await;
+
+    let cursor = MessageCursor::new(1, "1").encode();
+
+    let client = reqwest::Client::new();
+    let response = client
+        .get(format!("http://{}/messages", ctx.server.public_addr))
+        .query(&[
+            ("topic", TEST_TOPIC),
+            ("cursor", cursor.as_str()),
+            ("direction", "backward"),
+        ])
+        .header(http::header::AUTHORIZATION, format!("Bearer {jwt}"))
+        .send()
+        .await
+        .expect("Call failed");
+
+    assert!(
+        response.status().is_success(),
+        "Response was not successful: {:?} - {:?}",
+        response.status(),
+        response.text().await
+    );
+
+    let response: GetMessagesResponse = response.json().await.unwrap();
+
+    assert_eq!(response.direction, Direction::Backward);
+    assert_eq!(
+        response.next_cursor,
+        Some(MessageCursor::new(1, "before").encode())
+    );
+    assert_eq!(
+        response.prev_cursor,
+        Some(MessageCursor::new(2, "before").encode())
+    );
+}
+
+#[test_context(ServerContext)]
+#[tokio::test]
+async fn test_get_message_invalid_cursor(ctx: &mut ServerContext) {
+    let (jwt, client_id) = get_client_jwt();
+
+    ctx.server
+        .message_store
+        .test_add_topic(client_id.value(), TEST_TOPIC)
+        .await;
+
+    let client = reqwest::Client::new();
+    let response = client
+        .get(format!("http://{}/messages", ctx.server.public_addr))
+        .query(&[("topic", TEST_TOPIC), ("cursor", "not a cursor")])
+        .header(http::header::AUTHORIZATION, format!("Bearer {jwt}"))
+        .send()
+        .await
+        .expect("Call failed");
+
+    assert_eq!(
+        response.status(),
+        http::StatusCode::BAD_REQUEST,
+        "Response status was invalid: {:?} - {:?}",
+        response.status(),
+        response.text().await
+    );
+}
+
+#[test_context(ServerContext)]
+#[tokio::test]
+async fn test_get_message_time_range(ctx: &mut ServerContext) {
+    let (jwt, client_id) = get_client_jwt();
+
+    ctx.server
+        .message_store
+        .test_add_topic(client_id.value(), TEST_TOPIC)
+        .await;
+
+    let client = reqwest::Client::new();
+    for (since, until, status) in [
+        (
+            "2023-05-01T00:00:00Z",
+            "2023-05-08T00:00:00Z",
+            http::StatusCode::OK,
+        ),
+        (
+            "2023-05-08T00:00:00Z",
+            "2023-05-01T00:00:00Z",
+            http::StatusCode::BAD_REQUEST,
+        ),
+        (
+            "last week",
+            "2023-05-01T00:00:00Z",
+            http::StatusCode::BAD_REQUEST,
+        ),
+    ] {
+        let response = client
+            .get(format!("http://{}/messages", ctx.server.public_addr))
+            .query(&[("topic", TEST_TOPIC), ("since", since), ("until", until)])
+            .header(http::header::AUTHORIZATION, format!("Bearer {jwt}"))
+            .send()
+            .await
+            .expect("Call failed");
+
+        assert_eq!(
+            response.status(),
+            status,
+            "Response status was invalid: {:?} - {:?}",
+            response.status(),
+            response.text().await
+        );
+    }
+}
+
+#[test_context(ServerContext)]
+#[tokio::test]
+async fn test_get_message_invalid_tag_filter(ctx: &mut ServerContext) {
+    let (jwt, client_id) = get_client_jwt();
+
+    ctx.server
+        .message_store
+        .test_add_topic(client_id.value(), TEST_TOPIC)
+        .await;
+
+    let client = reqwest::Client::new();
+    for (tag, status) in [
+        ("11%,!1109", http::StatusCode::OK),
+        ("11x", http::StatusCode::BAD_REQUEST),
+    ] {
+        let response = client
+            .get(format!("http://{}/messages", ctx.server.public_addr))
+            .query(&[("topic", TEST_TOPIC), ("method", "publish"), ("tag", tag)])
+            .header(http::header::AUTHORIZATION, format!("Bearer {jwt}"))
+            .send()
+            .await
+            .expect("Call failed");
+
+        assert_eq!(
+            response.status(),
+            status,
+            "Response status was invalid: {:?} - {:?}",
+            response.status(),
+            response.text().await
+        );
+    }
+}
+
+#[test_context(ServerContext)]
+#[tokio::test]
+async fn test_get_message_wait(ctx: &mut ServerContext) {
+    let (jwt, client_id) = get_client_jwt();
+
+    ctx.server
+        .message_store
+        .test_add_topic(client_id.value(), TEST_TOPIC)
+        .await;
+    ctx.server
+        .registration_store
+        .registrations
+        .insert(client_id.to_string(), Registration {
+            id: None,
+            client_id: client_id.clone().into_value(),
+            tags: vec![Arc::from("4000")],
+            relay_url: Arc::from(TEST_RELAY_URL),
+            max_age: None,
+        })
+        .await;
+
+    let started = Instant::now();
+    let get_messages = reqwest::Client::new()
+        .get(format!("http://{}/messages", ctx.server.public_addr))
+        .query(&[("topic", TEST_TOPIC), ("wait", "10")])
+        .header(http::header::AUTHORIZATION, format!("Bearer {jwt}"))
+        .send();
+    let save_message = async {
+        sleep(Duration::from_millis(200)).await;
+        reqwest::Client::new()
+            .post(format!("http://{}/messages", ctx.server.public_addr))
+            .json(&HistoryPayload {
+                method: Arc::from(TEST_METHOD),
+                client_id: client_id.clone().into_value(),
+                message_id: Arc::from(TEST_MESSAGE_ID),
+                topic: Arc::from(TEST_TOPIC),
+                tag: 4000,
+                message: Arc::from(TEST_MESSAGE),
+            })
+            .send()
+            .await
+            .expect("Call failed")
+    };
+    let (response, saved) = tokio::join!(get_messages, save_message);
+    assert!(saved.status().is_success());
+
+    let response: GetMessagesResponse = response.expect("Call failed").json().await.unwrap();
+    assert_eq!(response.messages.len(), 1);
+    assert_eq!(response.messages[0].message_id.as_ref(), TEST_MESSAGE_ID);
+    assert!(started.elapsed() < Duration::from_secs(10));
+}
+
+#[test_context(ServerContext)]
+#[tokio::test]
+async fn test_get_message_wait_timeout(ctx: &mut ServerContext) {
+    let (jwt, client_id) = get_client_jwt();
+
+    ctx.server
+        .message_store
+        .test_add_topic(client_id.value(), TEST_TOPIC)
+        .await;
+
+    let started = Instant::now();
+    let response = reqwest::Client::new()
+        .get(format!("http://{}/messages", ctx.server.public_addr))
+        .query(&[("topic", TEST_TOPIC), ("wait", "1")])
+        .header(http::header::AUTHORIZATION, format!("Bearer {jwt}"))
+        .send()
+        .await
+        .expect("Call failed");
+
+    assert!(response.status().is_success());
+    let response: GetMessagesResponse = response.json().await.unwrap();
+    assert!(response.messages.is_empty());
+    assert!(started.elapsed() >= Duration::from_secs(1));
+}
+
+#[test_context(ServerContext)]
+#[tokio::test]
+async fn test_get_message_missing_jwt(ctx: &mut ServerContext) {
+    let client = reqwest::Client::new();
+    let response = client
+        .get(format!("http://{}/messages", ctx.server.public_addr))
+        .query(&[("topic", TEST_TOPIC)])
+        .send()
+        .await
+        .expect("Call failed");
+
+    assert_eq!(
+        response.status(),
+        http::StatusCode::UNAUTHORIZED,
+        "Response status was invalid: {:?} - {:?}",
+        response.status(),
+        response.text().await
+    );
+}
+
+#[test_context(ServerContext)]
+#[tokio::test]
+async fn test_get_message_invalid_jwt(ctx: &mut ServerContext) {
+    let (jwt, _) = get_invalid_client_jwt();
+
+    let client = reqwest::Client::new();
+    let response = client
+        .get(format!("http://{}/messages", ctx.server.public_addr))
+        .query(&[("topic", TEST_TOPIC)])
+        .header(http::header::AUTHORIZATION, format!("Bearer {jwt}"))
+        .send()
+        .await
+        .expect("Call failed");
+
+    assert_eq!(
+        response.status(),
+        http::StatusCode::UNAUTHORIZED,
+        "Response status was invalid: {:?} - {:?}",
+        response.status(),
+        response.text().await
+    );
+}
+
+#[test_context(ServerContext)]
+#[tokio::test]
+async fn test_get_message_unknown_topic(ctx: &mut ServerContext) {
+    let (jwt, client_id) = get_client_jwt();
+
+    ctx.server
+        .message_store
+        .test_add_topic(client_id.value(), TEST_TOPIC)
+        .await;
+
+    let client = reqwest::Client::new();
+    let response = client
+        .get(format!("http://{}/messages", ctx.server.public_addr))
+        .query(&[("topic", "another-topic")])
+        .header(http::header::AUTHORIZATION, format!("Bearer {jwt}"))
+        .send()
+        .await
+        .expect("Call failed");
+
+    assert_eq!(
+        response.status(),
+        http::StatusCode::FORBIDDEN,
+        "Response status was invalid: {:?} - {:?}",
+        response.status(),
+        response.text().await
+    );
+}
+
+#[test_context(ServerContext)]
+#[tokio::test]
+async fn test_get_topics(ctx: &mut ServerContext) {
+    let (jwt, client_id) = get_client_jwt();
+
+    ctx.server
+        .message_store
+        .test_add_topic(client_id.value(), TEST_TOPIC)
+        .await;
+    ctx.server
+        .message_store
+        .test_add_topic(TEST_CLIENT_ID, "another-topic")
+        .await;
+
+    let client = reqwest::Client::new();
+    let response = client
+        .get(format!("http://{}/topics", ctx.server.public_addr))
+        .header(http::header::AUTHORIZATION, format!("Bearer {jwt}"))
+        .send()
+        .await
+        .expect("Call failed");
+
+    assert!(
+        response.status().is_success(),
+        "Response was not successful: {:?} - {:?}",
+        response.status(),
+        response.text().await
+    );
+
+    let response: GetTopicsResponse = response.json().await.unwrap();
+    assert_eq!(response.topics, vec![Arc::from(TEST_TOPIC)]);
+}
+
+async fn query_messages(
+    ctx: &ServerContext,
+    jwt: &str,
+    body: &QueryMessagesBody,
+) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("http://{}/messages/query", ctx.server.public_addr))
+        .json(body)
+        .header(http::header::AUTHORIZATION, format!("Bearer {jwt}"))
+        .send()
+        .await
+        .expect("Call failed")
+}
+
+#[test_context(ServerContext)]
+#[tokio::test]
+async fn test_query_messages(ctx: &mut ServerContext) {
+    let (jwt, client_id) = get_client_jwt();
+    add_test_messages(ctx, client_id.value()).await;
+    add_test_messages(ctx, TEST_CLIENT_ID).await;
+
+    let topics = vec![Arc::from(TEST_TOPIC), Arc::from("another-topic")];
+    let response = query_messages(ctx, &jwt, &QueryMessagesBody {
+        topics: topics.clone(),
+        cursor: None,
+        message_count: Default::default(),
+        direction: None,
+        since: None,
+        until: None,
+        method: None,
+        tag: None,
+    })
+    .await;
+
+    assert!(
+        response.status().is_success(),
+        "Response was not successful: {:?} - {:?}",
+        response.status(),
+        response.text().await
+    );
+
+    let response: QueryMessagesResponse = response.json().await.unwrap();
+    assert_eq!(response.topics, topics);
+    assert_eq!(response.direction, Direction::Forward);
+    assert_eq!(
+        response.next_cursor,
+        Some(MessageCursor::new(2, "after").encode())
+    );
+
+    let mut message_ids: Vec<&str> = response
+        .messages
+        .iter()
+        .map(|message| {
+            assert_eq!(message.client_id.as_ref(), client_id.value());
+            message.message_id.as_ref()
+        })
+        .collect();
+    message_ids.sort_unstable();
+    assert_eq!(message_ids, vec!["1", "2", "3"]);
+}
+
+#[test_context(ServerContext)]
+#[tokio::test]
+async fn test_query_messages_unknown_topic(ctx: &mut ServerContext) {
+    let (jwt, client_id) = get_client_jwt();
+    add_test_messages(ctx, client_id.value()).await;
+
+    let response = query_messages(ctx, &jwt, &QueryMessagesBody {
+        topics: vec![Arc::from(TEST_TOPIC), Arc::from("unknown-topic")],
+        cursor: None,
+        message_count: Default::default(),
+        direction: None,
+        since: None,
+        until: None,
+        method: None,
+        tag: None,
+    })
+    .await;
+
+    assert_eq!(
+        response.status(),
+        http::StatusCode::FORBIDDEN,
+        "Response status was invalid: {:?} - {:?}",
+        response.status(),
+        response.text().await
+    );
+}
+
+#[test_context(ServerContext)]
+#[tokio::test]
+async fn test_query_messages_invalid(ctx: &mut ServerContext) {
+    let (jwt, client_id) = get_client_jwt();
+    add_test_messages(ctx, client_id.value()).await;
+
+    for topics in [
+        vec![],
+        vec![Arc::from(TEST_TOPIC), Arc::from(TEST_TOPIC)],
+        vec![Arc::from(TEST_TOPIC); MAX_QUERY_TOPICS + 1],
+    ] {
+        let response = query_messages(ctx, &jwt, &QueryMessagesBody {
+            topics,
+            cursor: None,
+            message_count: Default::default(),
+            direction: None,
+            since: None,
+            until: None,
+            method: None,
+            tag: None,
+        })
+        .await;
+
+        assert_eq!(
+            response.status(),
+            http::StatusCode::BAD_REQUEST,
+            "Response status was invalid: {:?} - {:?}",
+            response.status(),
+            response.text().await
+        );
+    }
+}
+
 #[test_context(ServerContext)]
 #[tokio::test]
 async fn test_save_message_saved(ctx: &mut ServerContext) {
@@ -181,6 +677,7 @@ async fn test_save_message_saved(ctx: &mut ServerContext) {
         client_id: client_id.clone().into_value(),
         tags: tags.clone(),
         relay_url: Arc::from(TEST_RELAY_URL),
+        max_age: None,
     };
 
     ctx.server
@@ -249,6 +746,7 @@ async fn test_save_message_saved(ctx: &mut ServerContext) {
     assert_eq!(msg.topic.as_ref(), TEST_TOPIC);
     assert_eq!(msg.message_id.as_ref(), format!("{TEST_MESSAGE_ID}-1"));
     assert_eq!(msg.message.as_ref(), TEST_MESSAGE);
+    assert_eq!(msg.tag, Some(4000));
 
     let msg = ctx
         .server
@@ -264,6 +762,14 @@ async fn test_save_message_saved(ctx: &mut ServerContext) {
     assert_eq!(msg.topic.as_ref(), TEST_TOPIC);
     assert_eq!(msg.message_id.as_ref(), format!("{TEST_MESSAGE_ID}-2"));
     assert_eq!(msg.message.as_ref(), TEST_MESSAGE);
+    assert_eq!(msg.tag, Some(5123));
+
+    assert!(ctx
+        .server
+        .message_store
+        .has_topic(client_id.value(), TEST_TOPIC)
+        .await
+        .unwrap());
 }
 
 #[test_context(ServerContext)]
@@ -277,6 +783,7 @@ async fn test_save_message_filtered_out(ctx: &mut ServerContext) {
         client_id: client_id.clone().into_value(),
         tags: tags.clone(),
         relay_url: Arc::from(TEST_RELAY_URL),
+        max_age: None,
     };
 
     ctx.server
@@ -369,6 +876,7 @@ async fn test_save_message_no_registration(ctx: &mut ServerContext) {
         client_id: client_id.clone().into_value(),
         tags: tags.clone(),
         relay_url: Arc::from(TEST_RELAY_URL),
+        max_age: None,
     };
 
     ctx.server
@@ -408,3 +916,349 @@ async fn test_save_message_no_registration(ctx: &mut ServerContext) {
         .await;
     assert!(msg.is_none());
 }
+
+#[test_context(ServerContext)]
+#[tokio::test]
+async fn test_save_message_max_age_overflow(ctx: &mut ServerContext) {
+    let (_, client_id) = get_client_jwt();
+
+    let registration = Registration {
+        id: None,
+        client_id: client_id.clone().into_value(),
+        tags: vec![Arc::from("4000")],
+        relay_url: Arc::from(TEST_RELAY_URL),
+        max_age: Some(u64::MAX),
+    };
+
+    ctx.server
+        .registration_store
+        .registrations
+        .insert(client_id.to_string(), registration)
+        .await;
+
+    let response = reqwest::Client::new()
+        .post(format!("http://{}/messages", ctx.server.public_addr))
+        .json(&HistoryPayload {
+            method: Arc::from(TEST_METHOD),
+            client_id: client_id.clone().into_value(),
+            message_id: Arc::from(TEST_MESSAGE_ID),
+            topic: Arc::from(TEST_TOPIC),
+            tag: 4000,
+            message: Arc::from(TEST_MESSAGE),
+        })
+        .send()
+        .await
+        .expect("Call failed");
+    assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
+
+    let msg = ctx
+        .server
+        .message_store
+        .test_get(client_id.value(), TEST_TOPIC, TEST_MESSAGE_ID)
+        .await;
+    assert!(msg.is_none());
+}
+
+#[test_context(ServerContext)]
+#[tokio::test]
+async fn test_save_messages_batch(ctx: &mut ServerContext) {
+    let (_, client_id) = get_client_jwt();
+    let (_, unregistered_client_id) = get_client_jwt();
+
+    ctx.server
+        .registration_store
+        .registrations
+        .insert(client_id.to_string(), Registration {
+            id: None,
+            client_id: client_id.clone().into_value(),
+            tags: vec![Arc::from("4000"), Arc::from("5***")],
+            relay_url: Arc::from(TEST_RELAY_URL),
+            max_age: None,
+        })
+        .await;
+
+    let payload = |client_id: Arc<str>, message_id: &str, tag: u32| HistoryPayload {
+        method: Arc::from(TEST_METHOD),
+        client_id,
+        message_id: Arc::from(message_id),
+        topic: Arc::from(TEST_TOPIC),
+        tag,
+        message: Arc::from(TEST_MESSAGE),
+    };
+    let payloads = vec![
+        payload(client_id.clone().into_value(), "1", 4000),
+        payload(client_id.clone().into_value(), "2", 4001),
+        payload(unregistered_client_id.clone().into_value(), "3", 4000),
+        payload(client_id.clone().into_value(), "4", 5123),
+    ];
+
+    let client = reqwest::Client::new();
+    let response = client
+        .post(format!("http://{}/messages/batch", ctx.server.public_addr))
+        .json(&payloads)
+        .send()
+        .await
+        .expect("Call failed");
+
+    assert!(
+        response.status().is_success(),
+        "Response was not successful: {:?} - {:?}",
+        response.status(),
+        response.text().await
+    );
+
+    let response: SaveMessagesResponse = response.json().await.unwrap();
+    let results: Vec<(&str, SaveMessageStatus)> = response
+        .results
+        .iter()
+        .map(|result| (result.message_id.as_ref(), result.status))
+        .collect();
+    assert_eq!(results, vec![
+        ("1", SaveMessageStatus::Stored),
+        ("2", SaveMessageStatus::Skipped),
+        ("3", SaveMessageStatus::Skipped),
+        ("4", SaveMessageStatus::Stored),
+    ]);
+
+    let store = &ctx.server.message_store;
+    for message_id in ["1", "4"] {
+        assert!(store
+            .test_get(client_id.value(), TEST_TOPIC, message_id)
+            .await
+            .is_some());
+    }
+    assert!(store
+        .test_get(client_id.value(), TEST_TOPIC, "2")
+        .await
+        .is_none());
+    assert!(store
+        .has_topic(client_id.value(), TEST_TOPIC)
+        .await
+        .unwrap());
+    assert!(!store
+        .has_topic(unregistered_client_id.value(), TEST_TOPIC)
+        .await
+        .unwrap());
+}
+
+#[test_context(ServerContext)]
+#[tokio::test]
+async fn test_save_messages_batch_too_large(ctx: &mut ServerContext) {
+    let payloads: Vec<HistoryPayload> = (0..=MAX_BATCH_SIZE)
+        .map(|id| HistoryPayload {
+            method: Arc::from(TEST_METHOD),
+            client_id: Arc::from(TEST_CLIENT_ID),
+            message_id: Arc::from(id.to_string()),
+            topic: Arc::from(TEST_TOPIC),
+            tag: 4000,
+            message: Arc::from(TEST_MESSAGE),
+        })
+        .collect();
+
+    let client = reqwest::Client::new();
+    let response = client
+        .post(format!("http://{}/messages/batch", ctx.server.public_addr))
+        .json(&payloads)
+        .send()
+        .await
+        .expect("Call failed");
+
+    assert_eq!(
+        response.status(),
+        http::StatusCode::BAD_REQUEST,
+        "Response status was invalid: {:?} - {:?}",
+        response.status(),
+        response.text().await
+    );
+}
+
+async fn add_test_messages(ctx: &ServerContext, client_id: &str) {
+    for (topic, message_id) in [(TEST_TOPIC, "1"), (TEST_TOPIC, "2"), ("another-topic", "3")] {
+        ctx.server
+            .message_store
+            .test_add_topic(client_id, topic)
+            .await;
+        ctx.server
+            .message_store
+            .test_add(Message {
+                id: None,
+                timestamp: Utc::now().into(),
+                method: Arc::from(TEST_METHOD),
+                tag: None,
+                client_id: Arc::from(client_id),
+                message_id: Arc::from(message_id),
+                topic: Arc::from(topic),
+                message: Arc::from(TEST_MESSAGE),
+                expires_at: None,
+            })
+            .await;
+    }
+}
+
+async fn delete_messages(
+    ctx: &ServerContext,
+    jwt: &str,
+    body: &DeleteMessagesBody,
+) -> reqwest::Response {
+    reqwest::Client::new()
+        .delete(format!("http://{}/messages", ctx.server.public_addr))
+        .json(body)
+        .header(http::header::AUTHORIZATION, format!("Bearer {jwt}"))
+        .send()
+        .await
+        .expect("Call failed")
+}
+
+#[test_context(ServerContext)]
+#[tokio::test]
+async fn test_delete_messages_topic(ctx: &mut ServerContext) {
+    let (jwt, client_id) = get_client_jwt();
+    add_test_messages(ctx, client_id.value()).await;
+    add_test_messages(ctx, TEST_CLIENT_ID).await;
+
+    let response = delete_messages(ctx, &jwt, &DeleteMessagesBody {
+        topic: Some(Arc::from(TEST_TOPIC)),
+        ..Default::default()
+    })
+    .await;
+
+    assert!(
+        response.status().is_success(),
+        "Response was not successful: {:?} - {:?}",
+        response.status(),
+        response.text().await
+    );
+
+    let response: DeleteMessagesResponse = response.json().await.unwrap();
+    assert_eq!(response.deleted_count, 2);
+
+    let store = &ctx.server.message_store;
+    assert!(!store
+        .has_topic(client_id.value(), TEST_TOPIC)
+        .await
+        .unwrap());
+    assert!(store
+        .test_get(client_id.value(), "another-topic", "3")
+        .await
+        .is_some());
+    assert!(store
+        .test_get(TEST_CLIENT_ID, TEST_TOPIC, "1")
+        .await
+        .is_some());
+}
+
+#[test_context(ServerContext)]
+#[tokio::test]
+async fn test_delete_messages_ids(ctx: &mut ServerContext) {
+    let (jwt, client_id) = get_client_jwt();
+    add_test_messages(ctx, client_id.value()).await;
+
+    let response = delete_messages(ctx, &jwt, &DeleteMessagesBody {
+        message_ids: Some(vec![Arc::from("1"), Arc::from("3")]),
+        ..Default::default()
+    })
+    .await;
+
+    assert!(
+        response.status().is_success(),
+        "Response was not successful: {:?} - {:?}",
+        response.status(),
+        response.text().await
+    );
+
+    let response: DeleteMessagesResponse = response.json().await.unwrap();
+    assert_eq!(response.deleted_count, 2);
+
+    let store = &ctx.server.message_store;
+    assert!(store
+        .has_topic(client_id.value(), TEST_TOPIC)
+        .await
+        .unwrap());
+    assert!(store
+        .test_get(client_id.value(), TEST_TOPIC, "2")
+        .await
+        .is_some());
+}
+
+#[test_context(ServerContext)]
+#[tokio::test]
+async fn test_delete_messages_all(ctx: &mut ServerContext) {
+    let (jwt, client_id) = get_client_jwt();
+    add_test_messages(ctx, client_id.value()).await;
+    add_test_messages(ctx, TEST_CLIENT_ID).await;
+
+    let response = delete_messages(ctx, &jwt, &DeleteMessagesBody {
+        all: true,
+        ..Default::default()
+    })
+    .await;
+
+    assert!(
+        response.status().is_success(),
+        "Response was not successful: {:?} - {:?}",
+        response.status(),
+        response.text().await
+    );
+
+    let response: DeleteMessagesResponse = response.json().await.unwrap();
+    assert_eq!(response.deleted_count, 3);
+
+    let store = &ctx.server.message_store;
+    assert!(store
+        .get_topics(client_id.value())
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(store.get_topics(TEST_CLIENT_ID).await.unwrap().len(), 2);
+}
+
+#[test_context(ServerContext)]
+#[tokio::test]
+async fn test_delete_messages_invalid(ctx: &mut ServerContext) {
+    let (jwt, _) = get_client_jwt();
+
+    for body in [
+        DeleteMessagesBody::default(),
+        DeleteMessagesBody {
+            topic: Some(Arc::from(TEST_TOPIC)),
+            all: true,
+            ..Default::default()
+        },
+        DeleteMessagesBody {
+            message_ids: Some(vec![]),
+            ..Default::default()
+        },
+    ] {
+        let response = delete_messages(ctx, &jwt, &body).await;
+
+        assert_eq!(
+            response.status(),
+            http::StatusCode::BAD_REQUEST,
+            "Response status was invalid for {body:?}: {:?} - {:?}",
+            response.status(),
+            response.text().await
+        );
+    }
+}
+
+#[test_context(ServerContext)]
+#[tokio::test]
+async fn test_delete_messages_missing_jwt(ctx: &mut ServerContext) {
+    let response = reqwest::Client::new()
+        .delete(format!("http://{}/messages", ctx.server.public_addr))
+        .json(&DeleteMessagesBody {
+            all: true,
+            ..Default::default()
+        })
+        .send()
+        .await
+        .expect("Call failed");
+
+    assert_eq!(
+        response.status(),
+        http::StatusCode::UNAUTHORIZED,
+        "Response status was invalid: {:?} - {:?}",
+        response.status(),
+        response.text().await
+    );
+}
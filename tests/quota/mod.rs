@@ -0,0 +1,195 @@
+use {
+    crate::{
+        context::{EvictionContext, QuotaContext, TEST_CLIENT_MAX_MESSAGES, TEST_MAX_MESSAGE_SIZE},
+        get_client_jwt,
+        TEST_RELAY_URL,
+    },
+    axum::http,
+    gilgamesh::{
+        handlers::{
+            save_message::HistoryPayload,
+            save_messages::{SaveMessageStatus, SaveMessagesResponse},
+        },
+        store::{messages::MessagesStore, registrations::Registration},
+    },
+    moka::future::Cache,
+    relay_rpc::domain::ClientId,
+    std::{net::SocketAddr, sync::Arc},
+    test_context::test_context,
+    tokio::time::{sleep, Duration},
+};
+
+const TEST_METHOD: &str = "publish";
+const TEST_TOPIC: &str = "test-topic";
+const TEST_MESSAGE: &str = "test-message";
+
+async fn register(registrations: &Cache<String, Registration>, client_id: &ClientId) {
+    registrations
+        .insert(client_id.to_string(), Registration {
+            id: None,
+            client_id: client_id.clone().into_value(),
+            tags: vec![Arc::from("4000")],
+            relay_url: Arc::from(TEST_RELAY_URL),
+            max_age: None,
+        })
+        .await;
+}
+
+fn payload(client_id: &ClientId, message_id: &str, message: &str) -> HistoryPayload {
+    HistoryPayload {
+        method: Arc::from(TEST_METHOD),
+        client_id: client_id.clone().into_value(),
+        topic: Arc::from(TEST_TOPIC),
+        message_id: Arc::from(message_id),
+        tag: 4000,
+        message: Arc::from(message),
+    }
+}
+
+async fn save_message(addr: SocketAddr, payload: &HistoryPayload) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("http://{addr}/messages"))
+        .json(payload)
+        .send()
+        .await
+        .expect("Call failed")
+}
+
+#[test_context(QuotaContext)]
+#[tokio::test]
+async fn test_save_message_too_large(ctx: &mut QuotaContext) {
+    let (_, client_id) = get_client_jwt();
+    register(&ctx.server.registration_store.registrations, &client_id).await;
+
+    let message = "a".repeat(TEST_MAX_MESSAGE_SIZE + 1);
+    let response = save_message(ctx.server.public_addr, &payload(&client_id, "1", &message)).await;
+    assert_eq!(response.status(), http::StatusCode::PAYLOAD_TOO_LARGE);
+
+    let message = "a".repeat(TEST_MAX_MESSAGE_SIZE);
+    let response = save_message(ctx.server.public_addr, &payload(&client_id, "2", &message)).await;
+    assert!(response.status().is_success());
+
+    let store = &ctx.server.message_store;
+    assert!(store
+        .test_get(client_id.value(), TEST_TOPIC, "1")
+        .await
+        .is_none());
+    assert!(store
+        .test_get(client_id.value(), TEST_TOPIC, "2")
+        .await
+        .is_some());
+}
+
+#[test_context(QuotaContext)]
+#[tokio::test]
+async fn test_save_message_over_quota(ctx: &mut QuotaContext) {
+    let (_, client_id) = get_client_jwt();
+    register(&ctx.server.registration_store.registrations, &client_id).await;
+
+    for message_id in 1..=TEST_CLIENT_MAX_MESSAGES {
+        let payload = payload(&client_id, &message_id.to_string(), TEST_MESSAGE);
+        let response = save_message(ctx.server.public_addr, &payload).await;
+        assert!(response.status().is_success());
+    }
+
+    let payload = payload(&client_id, "over", TEST_MESSAGE);
+    let response = save_message(ctx.server.public_addr, &payload).await;
+    assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+
+    let usage = ctx
+        .server
+        .message_store
+        .get_usage(client_id.value())
+        .await
+        .unwrap();
+    assert_eq!(usage.messages, TEST_CLIENT_MAX_MESSAGES);
+}
+
+#[test_context(QuotaContext)]
+#[tokio::test]
+async fn test_save_message_redelivered_at_quota(ctx: &mut QuotaContext) {
+    let (_, client_id) = get_client_jwt();
+    register(&ctx.server.registration_store.registrations, &client_id).await;
+
+    for message_id in 1..=TEST_CLIENT_MAX_MESSAGES {
+        let payload = payload(&client_id, &message_id.to_string(), TEST_MESSAGE);
+        let response = save_message(ctx.server.public_addr, &payload).await;
+        assert!(response.status().is_success());
+    }
+
+    // The already stored message is replaced without counting again.
+    let payload = payload(&client_id, "1", TEST_MESSAGE);
+    let response = save_message(ctx.server.public_addr, &payload).await;
+    assert!(response.status().is_success());
+
+    let usage = ctx
+        .server
+        .message_store
+        .get_usage(client_id.value())
+        .await
+        .unwrap();
+    assert_eq!(usage.messages, TEST_CLIENT_MAX_MESSAGES);
+}
+
+#[test_context(QuotaContext)]
+#[tokio::test]
+async fn test_save_messages_batch_over_quota(ctx: &mut QuotaContext) {
+    let (_, client_id) = get_client_jwt();
+    register(&ctx.server.registration_store.registrations, &client_id).await;
+
+    let too_large = "a".repeat(TEST_MAX_MESSAGE_SIZE + 1);
+    let payloads = vec![
+        payload(&client_id, "1", TEST_MESSAGE),
+        payload(&client_id, "2", &too_large),
+        payload(&client_id, "3", TEST_MESSAGE),
+        payload(&client_id, "4", TEST_MESSAGE),
+    ];
+
+    let response = reqwest::Client::new()
+        .post(format!("http://{}/messages/batch", ctx.server.public_addr))
+        .json(&payloads)
+        .send()
+        .await
+        .expect("Call failed");
+    assert!(response.status().is_success());
+
+    let response: SaveMessagesResponse = response.json().await.unwrap();
+    let results: Vec<(&str, SaveMessageStatus)> = response
+        .results
+        .iter()
+        .map(|result| (result.message_id.as_ref(), result.status))
+        .collect();
+    assert_eq!(results, vec![
+        ("1", SaveMessageStatus::Stored),
+        ("2", SaveMessageStatus::Failed),
+        ("3", SaveMessageStatus::Stored),
+        ("4", SaveMessageStatus::Failed),
+    ]);
+}
+
+#[test_context(EvictionContext)]
+#[tokio::test]
+async fn test_save_message_evicts_oldest(ctx: &mut EvictionContext) {
+    let (_, client_id) = get_client_jwt();
+    register(&ctx.server.registration_store.registrations, &client_id).await;
+
+    for message_id in ["1", "2", "3"] {
+        // Spaced out so that the messages' timestamps order them
+        sleep(Duration::from_millis(2)).await;
+        let payload = payload(&client_id, message_id, TEST_MESSAGE);
+        let response = save_message(ctx.server.public_addr, &payload).await;
+        assert!(response.status().is_success());
+    }
+
+    let store = &ctx.server.message_store;
+    assert!(store
+        .test_get(client_id.value(), TEST_TOPIC, "1")
+        .await
+        .is_none());
+    for message_id in ["2", "3"] {
+        assert!(store
+            .test_get(client_id.value(), TEST_TOPIC, message_id)
+            .await
+            .is_some());
+    }
+}
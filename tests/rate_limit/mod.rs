@@ -0,0 +1,95 @@
+use {
+    crate::{context::RateLimitContext, get_client_jwt, get_invalid_client_jwt},
+    axum::http,
+    gilgamesh::handlers::save_message::HistoryPayload,
+    serde_json::Value,
+    std::sync::Arc,
+    test_context::test_context,
+};
+
+async fn get_registration(ctx: &RateLimitContext, jwt: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .get(format!("http://{}/register", ctx.server.public_addr))
+        .header(http::header::AUTHORIZATION, format!("Bearer {jwt}"))
+        .send()
+        .await
+        .expect("Call failed")
+}
+
+#[test_context(RateLimitContext)]
+#[tokio::test]
+async fn test_rate_limit_per_client(ctx: &mut RateLimitContext) {
+    let (jwt, _) = get_client_jwt();
+    for _ in 0..2 {
+        let response = get_registration(ctx, &jwt).await;
+        assert_ne!(response.status(), http::StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    let response = get_registration(ctx, &jwt).await;
+    assert_eq!(response.status(), http::StatusCode::TOO_MANY_REQUESTS);
+    let retry_after: u64 = response.headers()[http::header::RETRY_AFTER]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(retry_after > 0 && retry_after <= 30);
+
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "FAILURE");
+    assert_eq!(body["errors"][0]["name"], "rate_limit");
+
+    // Other clients have their own bucket.
+    let (jwt, _) = get_client_jwt();
+    let response = get_registration(ctx, &jwt).await;
+    assert_ne!(response.status(), http::StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[test_context(RateLimitContext)]
+#[tokio::test]
+async fn test_rate_limit_per_ip(ctx: &mut RateLimitContext) {
+    let (_, client_id) = get_client_jwt();
+    let payload = HistoryPayload {
+        method: Arc::from("publish"),
+        client_id: client_id.into_value(),
+        topic: Arc::from("test-topic"),
+        message_id: Arc::from("1"),
+        tag: 4000,
+        message: Arc::from("test-message"),
+    };
+
+    let save_message = || {
+        reqwest::Client::new()
+            .post(format!("http://{}/messages", ctx.server.public_addr))
+            .json(&payload)
+            .send()
+    };
+
+    for _ in 0..2 {
+        let response = save_message().await.expect("Call failed");
+        assert!(response.status().is_success());
+    }
+
+    let response = save_message().await.expect("Call failed");
+    assert_eq!(response.status(), http::StatusCode::TOO_MANY_REQUESTS);
+
+    // The relay's deliveries don't count against the other routes.
+    let (jwt, _) = get_client_jwt();
+    let response = get_registration(ctx, &jwt).await;
+    assert_ne!(response.status(), http::StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[test_context(RateLimitContext)]
+#[tokio::test]
+async fn test_rate_limit_unverified_jwt(ctx: &mut RateLimitContext) {
+    // Each token claims another client, but none is valid, so they share the
+    // bucket of their IP address.
+    for _ in 0..2 {
+        let (jwt, _) = get_invalid_client_jwt();
+        let response = get_registration(ctx, &jwt).await;
+        assert_ne!(response.status(), http::StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    let (jwt, _) = get_invalid_client_jwt();
+    let response = get_registration(ctx, &jwt).await;
+    assert_eq!(response.status(), http::StatusCode::TOO_MANY_REQUESTS);
+}
@@ -1,7 +1,11 @@
 use {
     crate::{context::ServerContext, get_client_jwt, get_invalid_client_jwt, TEST_RELAY_URL},
     axum::http,
-    gilgamesh::{handlers::register::RegisterPayload, store::registrations::Registration},
+    gilgamesh::{
+        config::MAX_MESSAGE_MAX_AGE,
+        handlers::{register::RegisterPayload, save_message::HistoryPayload},
+        store::{messages::MessagesStore, registrations::Registration},
+    },
     std::sync::Arc,
     test_context::test_context,
 };
@@ -16,6 +20,7 @@ async fn test_register_invalid_jwt(ctx: &mut ServerContext) {
         append_tags: None,
         remove_tags: None,
         relay_url: Arc::from(TEST_RELAY_URL),
+        max_age: None,
     };
 
     let client = reqwest::Client::new();
@@ -35,6 +40,37 @@ async fn test_register_invalid_jwt(ctx: &mut ServerContext) {
     );
 }
 
+#[test_context(ServerContext)]
+#[tokio::test]
+async fn test_register_max_age_too_large(ctx: &mut ServerContext) {
+    let (jwt, client_id) = get_client_jwt();
+
+    let payload = RegisterPayload {
+        tags: Some(vec![Arc::from("4000")]),
+        append_tags: None,
+        remove_tags: None,
+        relay_url: Arc::from(TEST_RELAY_URL),
+        max_age: Some(MAX_MESSAGE_MAX_AGE + 1),
+    };
+
+    let client = reqwest::Client::new();
+    let response = client
+        .post(format!("http://{}/register", ctx.server.public_addr))
+        .json(&payload)
+        .header(http::header::AUTHORIZATION, format!("Bearer {jwt}"))
+        .send()
+        .await
+        .expect("Call failed");
+
+    assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
+    assert!(ctx
+        .server
+        .registration_store
+        .registrations
+        .get(client_id.as_ref())
+        .is_none());
+}
+
 #[test_context(ServerContext)]
 #[tokio::test]
 async fn test_register_new(ctx: &mut ServerContext) {
@@ -45,6 +81,7 @@ async fn test_register_new(ctx: &mut ServerContext) {
         append_tags: None,
         remove_tags: None,
         relay_url: Arc::from(TEST_RELAY_URL),
+        max_age: None,
     };
 
     let client = reqwest::Client::new();
@@ -154,6 +191,7 @@ async fn test_register(ctx: &mut ServerContext) {
                 client_id: client_id.clone().into_value(),
                 tags: test.start.clone(),
                 relay_url: relay_url.clone(),
+                max_age: None,
             })
             .await;
 
@@ -162,6 +200,7 @@ async fn test_register(ctx: &mut ServerContext) {
             append_tags: test.append.clone(),
             remove_tags: test.remove.clone(),
             relay_url: relay_url.clone(),
+            max_age: None,
         };
 
         let client = reqwest::Client::new();
@@ -204,6 +243,51 @@ async fn test_register(ctx: &mut ServerContext) {
     }
 }
 
+#[test_context(ServerContext)]
+#[tokio::test]
+async fn test_register_invalid_tag_pattern(ctx: &mut ServerContext) {
+    let (jwt, client_id) = get_client_jwt();
+
+    for (tags, append_tags) in [
+        (Some(vec![Arc::from("4000"), Arc::from("1199-1100")]), None),
+        (None, Some(vec![Arc::from("5*a*")])),
+    ] {
+        let payload = RegisterPayload {
+            tags,
+            append_tags,
+            remove_tags: None,
+            relay_url: Arc::from(TEST_RELAY_URL),
+            max_age: None,
+        };
+
+        let client = reqwest::Client::new();
+        let response = client
+            .post(format!("http://{}/register", ctx.server.public_addr))
+            .json(&payload)
+            .header(http::header::AUTHORIZATION, format!("Bearer {jwt}"))
+            .send()
+            .await
+            .expect("Call failed");
+
+        assert_eq!(
+            response.status(),
+            http::StatusCode::BAD_REQUEST,
+            "Response status was invalid: {:?} - {:?}",
+            response.status(),
+            response.text().await
+        );
+    }
+
+    assert!(
+        ctx.server
+            .registration_store
+            .registrations
+            .get(client_id.value().as_ref())
+            .is_none(),
+        "Registration was stored"
+    );
+}
+
 #[test_context(ServerContext)]
 #[tokio::test]
 async fn test_register_update_bad_update(ctx: &mut ServerContext) {
@@ -214,6 +298,7 @@ async fn test_register_update_bad_update(ctx: &mut ServerContext) {
         append_tags: Some(vec![Arc::from("5000")]),
         remove_tags: Some(vec![Arc::from("5000")]),
         relay_url: Arc::from(TEST_RELAY_URL),
+        max_age: None,
     };
 
     let client = reqwest::Client::new();
@@ -255,6 +340,7 @@ async fn test_register_update_bad_update_with_overwrite(ctx: &mut ServerContext)
         append_tags: Some(vec![Arc::from("5000")]),
         remove_tags: Some(vec![Arc::from("5000")]),
         relay_url: Arc::from(TEST_RELAY_URL),
+        max_age: None,
     };
 
     let client = reqwest::Client::new();
@@ -309,6 +395,7 @@ async fn test_get_registration(ctx: &mut ServerContext) {
         client_id: client_id.clone().into_value(),
         tags: tags.clone(),
         relay_url: Arc::from(TEST_RELAY_URL),
+        max_age: None,
     };
 
     ctx.server
@@ -357,6 +444,7 @@ async fn test_get_registration_invalid_jwt(ctx: &mut ServerContext) {
         client_id: client_id.clone().into_value(),
         tags: tags.clone(),
         relay_url: Arc::from(TEST_RELAY_URL),
+        max_age: None,
     };
 
     ctx.server
@@ -380,3 +468,211 @@ async fn test_get_registration_invalid_jwt(ctx: &mut ServerContext) {
         response.text().await
     );
 }
+
+#[test_context(ServerContext)]
+#[tokio::test]
+async fn test_delete_registration(ctx: &mut ServerContext) {
+    let (jwt, client_id) = get_client_jwt();
+
+    ctx.server
+        .registration_store
+        .registrations
+        .insert(client_id.to_string(), Registration {
+            id: None,
+            client_id: client_id.clone().into_value(),
+            tags: vec![Arc::from("4000")],
+            relay_url: Arc::from(TEST_RELAY_URL),
+            max_age: None,
+        })
+        .await;
+    ctx.server
+        .message_store
+        .test_add_topic(client_id.value(), "topic")
+        .await;
+
+    let client = reqwest::Client::new();
+    let response = client
+        .delete(format!("http://{}/register", ctx.server.public_addr))
+        .header(http::header::AUTHORIZATION, format!("Bearer {jwt}"))
+        .send()
+        .await
+        .expect("Call failed");
+
+    assert!(
+        response.status().is_success(),
+        "Response was not successful: {:?} - {:?}",
+        response.status(),
+        response.text().await
+    );
+
+    assert!(
+        ctx.server
+            .registration_store
+            .registrations
+            .get(client_id.value().as_ref())
+            .is_none(),
+        "Registration was not deleted"
+    );
+    assert!(
+        ctx.server
+            .message_store
+            .has_topic(client_id.value(), "topic")
+            .await
+            .unwrap(),
+        "Messages were purged"
+    );
+
+    // Unregistering twice fails as there is nothing left to delete.
+    let response = client
+        .delete(format!("http://{}/register", ctx.server.public_addr))
+        .header(http::header::AUTHORIZATION, format!("Bearer {jwt}"))
+        .send()
+        .await
+        .expect("Call failed");
+
+    assert_eq!(
+        response.status(),
+        http::StatusCode::NOT_FOUND,
+        "Response status was invalid: {:?} - {:?}",
+        response.status(),
+        response.text().await
+    );
+}
+
+#[test_context(ServerContext)]
+#[tokio::test]
+async fn test_delete_registration_purge_messages(ctx: &mut ServerContext) {
+    let (jwt, client_id) = get_client_jwt();
+
+    ctx.server
+        .registration_store
+        .registrations
+        .insert(client_id.to_string(), Registration {
+            id: None,
+            client_id: client_id.clone().into_value(),
+            tags: vec![Arc::from("4000")],
+            relay_url: Arc::from(TEST_RELAY_URL),
+            max_age: None,
+        })
+        .await;
+    ctx.server
+        .message_store
+        .test_add_topic(client_id.value(), "topic")
+        .await;
+
+    let client = reqwest::Client::new();
+    let response = client
+        .delete(format!("http://{}/register", ctx.server.public_addr))
+        .query(&[("purgeMessages", "true")])
+        .header(http::header::AUTHORIZATION, format!("Bearer {jwt}"))
+        .send()
+        .await
+        .expect("Call failed");
+
+    assert!(
+        response.status().is_success(),
+        "Response was not successful: {:?} - {:?}",
+        response.status(),
+        response.text().await
+    );
+
+    assert!(
+        !ctx.server
+            .message_store
+            .has_topic(client_id.value(), "topic")
+            .await
+            .unwrap(),
+        "Messages were not purged"
+    );
+}
+
+#[test_context(ServerContext)]
+#[tokio::test]
+async fn test_delete_missing_registration_purge_messages(ctx: &mut ServerContext) {
+    let (jwt, client_id) = get_client_jwt();
+
+    ctx.server
+        .message_store
+        .test_add_topic(client_id.value(), "topic")
+        .await;
+
+    let client = reqwest::Client::new();
+    let response = client
+        .delete(format!("http://{}/register", ctx.server.public_addr))
+        .query(&[("purgeMessages", "true")])
+        .header(http::header::AUTHORIZATION, format!("Bearer {jwt}"))
+        .send()
+        .await
+        .expect("Call failed");
+
+    assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
+    assert!(
+        !ctx.server
+            .message_store
+            .has_topic(client_id.value(), "topic")
+            .await
+            .unwrap(),
+        "Messages were not purged"
+    );
+}
+
+#[test_context(ServerContext)]
+#[tokio::test]
+async fn test_delete_missing_registration_invalidates_cache(ctx: &mut ServerContext) {
+    let (jwt, client_id) = get_client_jwt();
+
+    ctx.server
+        .registration_store
+        .registrations
+        .insert(client_id.to_string(), Registration {
+            id: None,
+            client_id: client_id.clone().into_value(),
+            tags: vec![Arc::from("4000")],
+            relay_url: Arc::from(TEST_RELAY_URL),
+            max_age: None,
+        })
+        .await;
+
+    let client = reqwest::Client::new();
+    let save_message = |message_id: &'static str| {
+        client
+            .post(format!("http://{}/messages", ctx.server.public_addr))
+            .json(&HistoryPayload {
+                method: Arc::from("publish"),
+                client_id: client_id.clone().into_value(),
+                topic: Arc::from("topic"),
+                message_id: Arc::from(message_id),
+                tag: 4000,
+                message: Arc::from("message"),
+            })
+            .send()
+    };
+
+    // Caches the registration, then only the cache holds it
+    let response = save_message("1").await.expect("Call failed");
+    assert!(response.status().is_success());
+    ctx.server
+        .registration_store
+        .registrations
+        .invalidate(client_id.value().as_ref())
+        .await;
+
+    let response = client
+        .delete(format!("http://{}/register", ctx.server.public_addr))
+        .header(http::header::AUTHORIZATION, format!("Bearer {jwt}"))
+        .send()
+        .await
+        .expect("Call failed");
+    assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
+
+    let response = save_message("2").await.expect("Call failed");
+    assert!(response.status().is_success());
+    assert!(
+        ctx.server
+            .message_store
+            .test_get(client_id.value(), "topic", "2")
+            .await
+            .is_none(),
+        "Message stored for an unregistered client"
+    );
+}
@@ -0,0 +1,200 @@
+use {
+    crate::{context::RelayContext, get_client_jwt},
+    axum::http::StatusCode,
+    chrono::{Duration, Utc},
+    gilgamesh::{
+        handlers::save_message::HistoryPayload,
+        relay::signature::{SIGNATURE_HEADER_NAME, TIMESTAMP_HEADER_NAME},
+    },
+    relay_rpc::auth::{
+        ed25519_dalek::{Keypair, Signer},
+        rand::{rngs::StdRng, SeedableRng},
+    },
+    std::sync::{atomic::Ordering, Arc},
+    test_context::test_context,
+};
+
+fn history_payload() -> String {
+    let (_, client_id) = get_client_jwt();
+    serde_json::to_string(&HistoryPayload {
+        method: Arc::from("publish"),
+        client_id: client_id.into_value(),
+        topic: Arc::from("test-topic"),
+        message_id: Arc::from("1"),
+        tag: 4000,
+        message: Arc::from("test-message"),
+    })
+    .unwrap()
+}
+
+async fn save_signed_message(
+    ctx: &RelayContext,
+    signer: &Keypair,
+    timestamp: i64,
+    body: &str,
+) -> StatusCode {
+    let signature = signer.sign(format!("{timestamp}.{}.{body}", body.len()).as_bytes());
+    send_signed_message(
+        ctx,
+        &signature.to_bytes(),
+        timestamp,
+        body.as_bytes().to_vec(),
+    )
+    .await
+}
+
+async fn send_signed_message(
+    ctx: &RelayContext,
+    signature: &[u8],
+    timestamp: i64,
+    body: Vec<u8>,
+) -> StatusCode {
+    reqwest::Client::new()
+        .post(format!("http://{}/messages", ctx.server.public_addr))
+        .header(SIGNATURE_HEADER_NAME, hex::encode(signature))
+        .header(TIMESTAMP_HEADER_NAME, timestamp.to_string())
+        .header("Content-Type", "application/json")
+        .body(body)
+        .send()
+        .await
+        .expect("Call failed")
+        .status()
+}
+
+#[test_context(RelayContext)]
+#[tokio::test]
+async fn test_public_key_cached(ctx: &mut RelayContext) {
+    assert_eq!(
+        save_signed_message(
+            ctx,
+            &ctx.relay.keypair,
+            Utc::now().timestamp(),
+            &history_payload()
+        )
+        .await,
+        StatusCode::OK
+    );
+    let key_fetches = ctx.relay.key_fetches.load(Ordering::SeqCst);
+    assert!(key_fetches > 0);
+
+    for _ in 0..3 {
+        assert_eq!(
+            save_signed_message(
+                ctx,
+                &ctx.relay.keypair,
+                Utc::now().timestamp(),
+                &history_payload()
+            )
+            .await,
+            StatusCode::OK
+        );
+    }
+    assert_eq!(ctx.relay.key_fetches.load(Ordering::SeqCst), key_fetches);
+}
+
+#[test_context(RelayContext)]
+#[tokio::test]
+async fn test_invalid_signature(ctx: &mut RelayContext) {
+    let impostor = Keypair::generate(&mut StdRng::from_entropy());
+    assert_eq!(
+        save_signed_message(ctx, &impostor, Utc::now().timestamp(), &history_payload()).await,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[test_context(RelayContext)]
+#[tokio::test]
+async fn test_replayed_signature(ctx: &mut RelayContext) {
+    let timestamp = Utc::now().timestamp();
+    let body = history_payload();
+
+    assert_eq!(
+        save_signed_message(ctx, &ctx.relay.keypair, timestamp, &body).await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        save_signed_message(ctx, &ctx.relay.keypair, timestamp, &body).await,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[test_context(RelayContext)]
+#[tokio::test]
+async fn test_stale_signature(ctx: &mut RelayContext) {
+    for timestamp in [
+        Utc::now() - Duration::minutes(10),
+        Utc::now() + Duration::minutes(10),
+    ] {
+        assert_eq!(
+            save_signed_message(
+                ctx,
+                &ctx.relay.keypair,
+                timestamp.timestamp(),
+                &history_payload()
+            )
+            .await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+}
+
+#[test_context(RelayContext)]
+#[tokio::test]
+async fn test_signature_over_raw_bytes(ctx: &mut RelayContext) {
+    let body = b"{\"message\": \"\xff\"}".to_vec();
+    let timestamp = Utc::now().timestamp();
+
+    // Signed over the lossy UTF-8 conversion of the body, not its bytes
+    let lossy = String::from_utf8_lossy(&body);
+    let signature = ctx
+        .relay
+        .keypair
+        .sign(format!("{timestamp}.{}.{lossy}", lossy.len()).as_bytes());
+
+    assert_eq!(
+        send_signed_message(ctx, &signature.to_bytes(), timestamp, body).await,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[test_context(RelayContext)]
+#[tokio::test]
+async fn test_payload_too_large(ctx: &mut RelayContext) {
+    let body = format!(r#"{{"message": "{}"}}"#, "a".repeat(5 * 1024 * 1024));
+
+    assert_eq!(
+        save_signed_message(ctx, &ctx.relay.keypair, Utc::now().timestamp(), &body).await,
+        StatusCode::PAYLOAD_TOO_LARGE
+    );
+}
+
+#[test_context(RelayContext)]
+#[tokio::test]
+async fn test_signed_deliveries_not_rate_limited(ctx: &mut RelayContext) {
+    for _ in 0..3 {
+        assert_eq!(
+            save_signed_message(
+                ctx,
+                &ctx.relay.keypair,
+                Utc::now().timestamp(),
+                &history_payload()
+            )
+            .await,
+            StatusCode::OK
+        );
+    }
+
+    // Requests not signed by the relay are limited by IP address.
+    let impostor = Keypair::generate(&mut StdRng::from_entropy());
+    let mut statuses = vec![];
+    for _ in 0..3 {
+        statuses.push(
+            save_signed_message(ctx, &impostor, Utc::now().timestamp(), &history_payload()).await,
+        );
+    }
+    assert_eq!(statuses, [
+        StatusCode::UNAUTHORIZED,
+        StatusCode::UNAUTHORIZED,
+        StatusCode::TOO_MANY_REQUESTS,
+    ]);
+}
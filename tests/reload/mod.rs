@@ -0,0 +1,72 @@
+use {
+    crate::{context::ServerContext, get_client_jwt},
+    axum::http,
+    serde_json::{json, Value},
+    test_context::test_context,
+};
+
+async fn reload(ctx: &ServerContext) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("http://{}/reload", ctx.server.private_addr))
+        .send()
+        .await
+        .expect("Call failed")
+}
+
+async fn get_registration(ctx: &ServerContext, jwt: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .get(format!("http://{}/register", ctx.server.public_addr))
+        .header(http::header::AUTHORIZATION, format!("Bearer {jwt}"))
+        .send()
+        .await
+        .expect("Call failed")
+}
+
+#[test_context(ServerContext)]
+#[tokio::test]
+async fn test_reload_applies_limits(ctx: &mut ServerContext) {
+    {
+        let mut config = ctx.server.next_config.lock().unwrap();
+        config.register_rate_limit = Some("1/60".parse().unwrap());
+        config.retention_prune_interval += 1;
+    }
+
+    let response = reload(ctx).await;
+    assert!(response.status().is_success());
+    let report: Value = response.json().await.unwrap();
+    assert_eq!(
+        report,
+        json!({
+            "applied": ["register_rate_limit"],
+            "restartRequired": ["retention_prune_interval"],
+        })
+    );
+
+    let (jwt, _) = get_client_jwt();
+    let response = get_registration(ctx, &jwt).await;
+    assert_ne!(response.status(), http::StatusCode::TOO_MANY_REQUESTS);
+    let response = get_registration(ctx, &jwt).await;
+    assert_eq!(response.status(), http::StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[test_context(ServerContext)]
+#[tokio::test]
+async fn test_reload_invalid_config(ctx: &mut ServerContext) {
+    {
+        let mut config = ctx.server.next_config.lock().unwrap();
+        config.register_rate_limit = Some("1/60".parse().unwrap());
+        config.relay_url = "relay.walletconnect.com".to_string();
+    }
+
+    let response = reload(ctx).await;
+    assert_eq!(response.status(), http::StatusCode::UNPROCESSABLE_ENTITY);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["errors"][0]["name"], "configuration");
+
+    // Nothing was applied
+    let (jwt, _) = get_client_jwt();
+    for _ in 0..2 {
+        let response = get_registration(ctx, &jwt).await;
+        assert_ne!(response.status(), http::StatusCode::TOO_MANY_REQUESTS);
+    }
+}
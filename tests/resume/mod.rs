@@ -0,0 +1,93 @@
+use {
+    crate::{context::MemoryServerContext, get_client_jwt, TEST_RELAY_URL},
+    axum::http,
+    gilgamesh::{
+        handlers::{get_messages::GetMessagesResponse, save_message::HistoryPayload},
+        store::registrations::Registration,
+    },
+    relay_rpc::domain::ClientId,
+    std::sync::Arc,
+    test_context::test_context,
+};
+
+const TEST_METHOD: &str = "publish";
+const TEST_TOPIC: &str = "test-topic";
+const TEST_MESSAGE: &str = "test-message";
+
+async fn register(ctx: &MemoryServerContext, client_id: &ClientId) {
+    ctx.server
+        .registration_store
+        .registrations
+        .insert(client_id.to_string(), Registration {
+            id: None,
+            client_id: client_id.clone().into_value(),
+            tags: vec![Arc::from("4000")],
+            relay_url: Arc::from(TEST_RELAY_URL),
+            max_age: None,
+        })
+        .await;
+}
+
+async fn save_message(ctx: &MemoryServerContext, client_id: &ClientId, message_id: &str) {
+    let response = reqwest::Client::new()
+        .post(format!("http://{}/messages", ctx.server.public_addr))
+        .json(&HistoryPayload {
+            method: Arc::from(TEST_METHOD),
+            client_id: client_id.clone().into_value(),
+            topic: Arc::from(TEST_TOPIC),
+            message_id: Arc::from(message_id),
+            tag: 4000,
+            message: Arc::from(TEST_MESSAGE),
+        })
+        .send()
+        .await
+        .expect("Call failed");
+    assert!(response.status().is_success());
+}
+
+async fn get_messages(
+    ctx: &MemoryServerContext,
+    jwt: &str,
+    query: &[(&str, &str)],
+) -> GetMessagesResponse {
+    let response = reqwest::Client::new()
+        .get(format!("http://{}/messages", ctx.server.public_addr))
+        .query(&[("topic", TEST_TOPIC)])
+        .query(query)
+        .header(http::header::AUTHORIZATION, format!("Bearer {jwt}"))
+        .send()
+        .await
+        .expect("Call failed");
+    assert!(response.status().is_success());
+
+    response.json().await.unwrap()
+}
+
+#[test_context(MemoryServerContext)]
+#[tokio::test]
+async fn test_resume_after_last_page(ctx: &mut MemoryServerContext) {
+    let (jwt, client_id) = get_client_jwt();
+    register(ctx, &client_id).await;
+    save_message(ctx, &client_id, "1").await;
+    save_message(ctx, &client_id, "2").await;
+
+    let response = get_messages(ctx, &jwt, &[]).await;
+    assert_eq!(response.messages.len(), 2);
+    assert!(response.next_cursor.is_none());
+    let cursor = response.resume_cursor.expect("No resume cursor");
+
+    // Long-polling from the resume cursor times out without new messages.
+    let response = get_messages(ctx, &jwt, &[("cursor", &cursor), ("wait", "1")]).await;
+    assert!(response.messages.is_empty());
+    assert!(response.resume_cursor.is_none());
+
+    save_message(ctx, &client_id, "3").await;
+
+    let response = get_messages(ctx, &jwt, &[("cursor", &cursor)]).await;
+    let message_ids: Vec<&str> = response
+        .messages
+        .iter()
+        .map(|message| message.message_id.as_ref())
+        .collect();
+    assert_eq!(message_ids, ["3"]);
+}
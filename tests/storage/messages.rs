@@ -1,15 +1,29 @@
 use {
     crate::context::StoreContext,
     ::function_name::named,
-    gilgamesh::store::messages::MessagesStore,
-    std::time,
+    chrono::{Duration, Utc},
+    gilgamesh::{
+        store::messages::{
+            ClientUsage,
+            Message,
+            MessageCursor,
+            MessageFilter,
+            MessagesStore,
+            NewMessage,
+            Origin,
+        },
+        tags::TagPattern,
+    },
+    std::{sync::Arc, time},
     test_context::test_context,
 };
 
 const TEST_CLIENT_ID: &str = "12345";
 const TEST_QUERY_SIZE: usize = 3;
+const TEST_TAG: u32 = 4000;
 
-// NOTE: Requires the dev MongoDB container (see `ops/docker-compose.yml`).
+// NOTE: Requires the dev storage containers (see
+// `ops/docker-compose.storage.yml`).
 #[named]
 #[test_context(StoreContext)]
 #[tokio::test]
@@ -21,7 +35,13 @@ async fn test_after_no_origin(ctx: &StoreContext) {
     let result = ctx
         .storage
         .store
-        .get_messages_after(topic, None, TEST_QUERY_SIZE)
+        .get_messages_after(
+            TEST_CLIENT_ID,
+            topic,
+            Origin::Start,
+            &MessageFilter::default(),
+            TEST_QUERY_SIZE,
+        )
         .await
         .unwrap();
 
@@ -44,7 +64,8 @@ async fn test_after_no_origin(ctx: &StoreContext) {
     assert_eq!(result.next_id.unwrap().as_ref(), "4", "Check next_id");
 }
 
-// NOTE: Requires the dev MongoDB container (see `ops/docker-compose.yml`).
+// NOTE: Requires the dev storage containers (see
+// `ops/docker-compose.storage.yml`).
 #[named]
 #[test_context(StoreContext)]
 #[tokio::test]
@@ -58,7 +79,13 @@ async fn test_after_origin(ctx: &StoreContext) {
     let result = ctx
         .storage
         .store
-        .get_messages_after(topic, Some(&origin.to_string()), TEST_QUERY_SIZE)
+        .get_messages_after(
+            TEST_CLIENT_ID,
+            topic,
+            Origin::MessageId(&origin.to_string()),
+            &MessageFilter::default(),
+            TEST_QUERY_SIZE,
+        )
         .await
         .unwrap();
 
@@ -85,7 +112,8 @@ async fn test_after_origin(ctx: &StoreContext) {
     );
 }
 
-// NOTE: Requires the dev MongoDB container (see `ops/docker-compose.yml`).
+// NOTE: Requires the dev storage containers (see
+// `ops/docker-compose.storage.yml`).
 #[named]
 #[test_context(StoreContext)]
 #[tokio::test]
@@ -99,7 +127,13 @@ async fn test_after_origin_overflow(ctx: &StoreContext) {
     let result = ctx
         .storage
         .store
-        .get_messages_after(topic, Some(&origin.to_string()), TEST_QUERY_SIZE)
+        .get_messages_after(
+            TEST_CLIENT_ID,
+            topic,
+            Origin::MessageId(&origin.to_string()),
+            &MessageFilter::default(),
+            TEST_QUERY_SIZE,
+        )
         .await
         .unwrap();
 
@@ -118,7 +152,8 @@ async fn test_after_origin_overflow(ctx: &StoreContext) {
     assert_eq!(result.next_id, None, "Check next_id");
 }
 
-// NOTE: Requires the dev MongoDB container (see `ops/docker-compose.yml`).
+// NOTE: Requires the dev storage containers (see
+// `ops/docker-compose.storage.yml`).
 #[named]
 #[test_context(StoreContext)]
 #[tokio::test]
@@ -130,7 +165,13 @@ async fn test_before_no_origin(ctx: &StoreContext) {
     let result = ctx
         .storage
         .store
-        .get_messages_before(topic, None, TEST_QUERY_SIZE)
+        .get_messages_before(
+            TEST_CLIENT_ID,
+            topic,
+            Origin::Start,
+            &MessageFilter::default(),
+            TEST_QUERY_SIZE,
+        )
         .await
         .unwrap();
 
@@ -153,7 +194,8 @@ async fn test_before_no_origin(ctx: &StoreContext) {
     assert_eq!(result.next_id.unwrap().as_ref(), "17", "Check next_id");
 }
 
-// NOTE: Requires the dev MongoDB container (see `ops/docker-compose.yml`).
+// NOTE: Requires the dev storage containers (see
+// `ops/docker-compose.storage.yml`).
 #[named]
 #[test_context(StoreContext)]
 #[tokio::test]
@@ -167,7 +209,13 @@ async fn test_before_origin(ctx: &StoreContext) {
     let result = ctx
         .storage
         .store
-        .get_messages_before(topic, Some(&origin.to_string()), TEST_QUERY_SIZE)
+        .get_messages_before(
+            TEST_CLIENT_ID,
+            topic,
+            Origin::MessageId(&origin.to_string()),
+            &MessageFilter::default(),
+            TEST_QUERY_SIZE,
+        )
         .await
         .unwrap();
 
@@ -194,7 +242,8 @@ async fn test_before_origin(ctx: &StoreContext) {
     );
 }
 
-// NOTE: Requires the dev MongoDB container (see `ops/docker-compose.yml`).
+// NOTE: Requires the dev storage containers (see
+// `ops/docker-compose.storage.yml`).
 #[named]
 #[test_context(StoreContext)]
 #[tokio::test]
@@ -208,7 +257,13 @@ async fn test_before_origin_overflow(ctx: &StoreContext) {
     let result = ctx
         .storage
         .store
-        .get_messages_before(topic, Some(&origin.to_string()), TEST_QUERY_SIZE)
+        .get_messages_before(
+            TEST_CLIENT_ID,
+            topic,
+            Origin::MessageId(&origin.to_string()),
+            &MessageFilter::default(),
+            TEST_QUERY_SIZE,
+        )
         .await
         .unwrap();
 
@@ -227,7 +282,8 @@ async fn test_before_origin_overflow(ctx: &StoreContext) {
     assert_eq!(result.next_id, None, "Check next_id");
 }
 
-// NOTE: Requires the dev MongoDB container (see `ops/docker-compose.yml`).
+// NOTE: Requires the dev storage containers (see
+// `ops/docker-compose.storage.yml`).
 #[named]
 #[test_context(StoreContext)]
 #[tokio::test]
@@ -247,7 +303,13 @@ async fn test_multi_topic(ctx: &StoreContext) {
         let result = ctx
             .storage
             .store
-            .get_messages_after(topic.as_str(), None, QUERY_SIZE)
+            .get_messages_after(
+                TEST_CLIENT_ID,
+                topic.as_str(),
+                Origin::Start,
+                &MessageFilter::default(),
+                QUERY_SIZE,
+            )
             .await
             .unwrap();
 
@@ -272,7 +334,94 @@ async fn test_multi_topic(ctx: &StoreContext) {
     }
 }
 
-// NOTE: Requires the dev MongoDB container (see `ops/docker-compose.yml`).
+// NOTE: Requires the dev storage containers (see
+// `ops/docker-compose.storage.yml`).
+#[named]
+#[test_context(StoreContext)]
+#[tokio::test]
+#[cfg_attr(not(feature = "storage-tests"), ignore)]
+async fn test_topics_messages(ctx: &StoreContext) {
+    let topics: Vec<String> = (1..=3)
+        .map(|t| format!("{}-{}", function_name!(), t))
+        .collect();
+
+    // Interleaves the topics' messages, most of them sharing their timestamp
+    let mut expected = vec![];
+    for id in 1..=4 {
+        for topic in &topics {
+            let message_id = format!("{topic}:{id}");
+            ctx.storage
+                .store
+                .upsert_message(
+                    "publish",
+                    TEST_TAG,
+                    TEST_CLIENT_ID,
+                    topic,
+                    &message_id,
+                    "",
+                    None,
+                )
+                .await
+                .unwrap();
+            if topic != &topics[2] {
+                expected.push(message_id);
+            }
+        }
+    }
+
+    // The last topic isn't queried
+    let queried = [topics[0].as_str(), topics[1].as_str()];
+    let page_through = |forward: bool| {
+        let queried = &queried;
+        async move {
+            let mut message_ids = vec![];
+            let mut cursor: Option<MessageCursor> = None;
+            loop {
+                let store = &ctx.storage.store;
+                let result = if forward {
+                    store
+                        .get_topics_messages_after(
+                            TEST_CLIENT_ID,
+                            queried,
+                            cursor.as_ref(),
+                            &MessageFilter::default(),
+                            TEST_QUERY_SIZE,
+                        )
+                        .await
+                } else {
+                    store
+                        .get_topics_messages_before(
+                            TEST_CLIENT_ID,
+                            queried,
+                            cursor.as_ref(),
+                            &MessageFilter::default(),
+                            TEST_QUERY_SIZE,
+                        )
+                        .await
+                }
+                .unwrap();
+
+                message_ids.extend(
+                    result
+                        .messages
+                        .iter()
+                        .map(|message| message.message_id.to_string()),
+                );
+                match result.next_cursor {
+                    Some(next_cursor) => cursor = Some(next_cursor),
+                    None => return message_ids,
+                }
+            }
+        }
+    };
+
+    assert_eq!(page_through(true).await, expected);
+    expected.reverse();
+    assert_eq!(page_through(false).await, expected);
+}
+
+// NOTE: Requires the dev storage containers (see
+// `ops/docker-compose.storage.yml`).
 #[named]
 #[test_context(StoreContext)]
 #[tokio::test]
@@ -292,7 +441,13 @@ async fn test_multi_clients(ctx: &StoreContext) {
         let result = ctx
             .storage
             .store
-            .get_messages_after(topic, None, QUERY_SIZE)
+            .get_messages_after(
+                TEST_CLIENT_ID,
+                topic,
+                Origin::Start,
+                &MessageFilter::default(),
+                QUERY_SIZE,
+            )
             .await
             .unwrap();
 
@@ -317,16 +472,655 @@ async fn test_multi_clients(ctx: &StoreContext) {
     }
 }
 
+// NOTE: Requires the dev storage containers (see
+// `ops/docker-compose.storage.yml`).
+#[named]
+#[test_context(StoreContext)]
+#[tokio::test]
+#[cfg_attr(not(feature = "storage-tests"), ignore)]
+async fn test_topics(ctx: &StoreContext) {
+    let client_id = format!("{}-{}", TEST_CLIENT_ID, function_name!());
+    let topic = function_name!();
+
+    assert!(!ctx
+        .storage
+        .store
+        .has_topic(client_id.as_str(), topic)
+        .await
+        .unwrap());
+
+    ctx.storage
+        .store
+        .upsert_topic(client_id.as_str(), topic)
+        .await
+        .unwrap();
+    ctx.storage
+        .store
+        .upsert_topic(client_id.as_str(), topic)
+        .await
+        .unwrap();
+
+    assert!(ctx
+        .storage
+        .store
+        .has_topic(client_id.as_str(), topic)
+        .await
+        .unwrap());
+
+    let topics = ctx
+        .storage
+        .store
+        .get_topics(client_id.as_str())
+        .await
+        .unwrap();
+    assert_eq!(topics.len(), 1, "check topics length");
+    assert_eq!(topics[0].topic.as_ref(), topic);
+}
+
+// NOTE: Requires the dev storage containers (see
+// `ops/docker-compose.storage.yml`).
+#[named]
+#[test_context(StoreContext)]
+#[tokio::test]
+#[cfg_attr(not(feature = "storage-tests"), ignore)]
+async fn test_expired_messages(ctx: &StoreContext) {
+    let topic = function_name!();
+    fill_store(ctx, TEST_CLIENT_ID, topic, 2).await;
+
+    for (message_id, expires_at) in [
+        ("expired", Utc::now() - Duration::seconds(60)),
+        ("not-expired", Utc::now() + Duration::seconds(60)),
+    ] {
+        ctx.storage
+            .store
+            .upsert_message(
+                "publish",
+                TEST_TAG,
+                TEST_CLIENT_ID,
+                topic,
+                message_id,
+                message_id,
+                Some(expires_at),
+            )
+            .await
+            .unwrap();
+    }
+
+    let deleted = ctx
+        .storage
+        .store
+        .delete_expired_messages(None)
+        .await
+        .unwrap();
+    assert!(deleted >= 1, "check the expired message was deleted");
+
+    let result = ctx
+        .storage
+        .store
+        .get_messages_after(
+            TEST_CLIENT_ID,
+            topic,
+            Origin::Start,
+            &MessageFilter::default(),
+            10,
+        )
+        .await
+        .unwrap();
+    let message_ids: Vec<&str> = result
+        .messages
+        .iter()
+        .map(|message| message.message_id.as_ref())
+        .collect();
+    assert_eq!(message_ids, vec!["1", "2", "not-expired"]);
+}
+
+// Pruning by the time messages were stored would delete the messages of the
+// other tests sharing the storage containers, so it's checked on its own store.
+#[cfg(feature = "memory-store")]
+#[tokio::test]
+async fn test_expired_messages_without_expiry() {
+    let store = gilgamesh::store::memory::MemoryStore::new();
+    let upsert = |message_id: &'static str, expires_at| {
+        store.upsert_message(
+            "publish",
+            TEST_TAG,
+            TEST_CLIENT_ID,
+            "topic",
+            message_id,
+            message_id,
+            expires_at,
+        )
+    };
+
+    upsert("old", None).await.unwrap();
+    upsert("old-not-expired", Some(Utc::now() + Duration::seconds(60)))
+        .await
+        .unwrap();
+    tokio::time::sleep(time::Duration::from_millis(10)).await;
+    let stored_before = Utc::now();
+    tokio::time::sleep(time::Duration::from_millis(10)).await;
+    upsert("new", None).await.unwrap();
+
+    assert_eq!(store.delete_expired_messages(None).await.unwrap(), 0);
+    assert_eq!(
+        store
+            .delete_expired_messages(Some(stored_before))
+            .await
+            .unwrap(),
+        1
+    );
+
+    let result = store
+        .get_messages_after(
+            TEST_CLIENT_ID,
+            "topic",
+            Origin::Start,
+            &MessageFilter::default(),
+            10,
+        )
+        .await
+        .unwrap();
+    let message_ids: Vec<&str> = result
+        .messages
+        .iter()
+        .map(|message| message.message_id.as_ref())
+        .collect();
+    assert_eq!(message_ids, vec!["old-not-expired", "new"]);
+}
+
+// NOTE: Requires the dev storage containers (see
+// `ops/docker-compose.storage.yml`).
+#[named]
+#[test_context(StoreContext)]
+#[tokio::test]
+#[cfg_attr(not(feature = "storage-tests"), ignore)]
+async fn test_time_range(ctx: &StoreContext) {
+    let topic = function_name!();
+    fill_store(ctx, TEST_CLIENT_ID, topic, 10).await;
+
+    let store = &ctx.storage.store;
+    let all = store
+        .get_messages_after(
+            TEST_CLIENT_ID,
+            topic,
+            Origin::Start,
+            &MessageFilter::default(),
+            10,
+        )
+        .await
+        .unwrap();
+
+    // From the 3rd message included to the 7th excluded
+    let filter = MessageFilter {
+        since: Some(all.messages[2].timestamp.to_chrono()),
+        until: Some(all.messages[6].timestamp.to_chrono()),
+    };
+    let message_ids = |messages: &[Message]| -> Vec<String> {
+        messages
+            .iter()
+            .map(|message| message.message_id.to_string())
+            .collect()
+    };
+
+    let result = store
+        .get_messages_after(TEST_CLIENT_ID, topic, Origin::Start, &filter, 2)
+        .await
+        .unwrap();
+    assert_eq!(message_ids(&result.messages), vec!["3", "4"]);
+    let result = store
+        .get_messages_after(
+            TEST_CLIENT_ID,
+            topic,
+            Origin::Cursor(&result.next_cursor.unwrap()),
+            &filter,
+            2,
+        )
+        .await
+        .unwrap();
+    assert_eq!(message_ids(&result.messages), vec!["5", "6"]);
+    assert_eq!(result.next_cursor, None, "check the range's end");
+
+    let result = store
+        .get_messages_before(TEST_CLIENT_ID, topic, Origin::Start, &filter, 10)
+        .await
+        .unwrap();
+    assert_eq!(message_ids(&result.messages), vec!["6", "5", "4", "3"]);
+}
+
+// NOTE: Requires the dev storage containers (see
+// `ops/docker-compose.storage.yml`).
+#[named]
+#[test_context(StoreContext)]
+#[tokio::test]
+#[cfg_attr(not(feature = "storage-tests"), ignore)]
+async fn test_method_and_tag_filter(ctx: &StoreContext) {
+    let topic = function_name!();
+    let store = &ctx.storage.store;
+    for (message_id, method, tag) in [
+        ("1", "publish", 1100),
+        ("2", "subscription", 1101),
+        ("3", "publish", 1108),
+        ("4", "publish", 1109),
+        ("5", "publish", 4000),
+    ] {
+        store
+            .upsert_message(
+                method,
+                tag,
+                TEST_CLIENT_ID,
+                topic,
+                message_id,
+                message_id,
+                None,
+            )
+            .await
+            .unwrap();
+    }
+
+    for (filter, expected) in [
+        (
+            MessageFilter {
+                method: Some(Arc::from("publish")),
+                ..Default::default()
+            },
+            vec!["1", "3", "4", "5"],
+        ),
+        (
+            MessageFilter {
+                tag: Some(TagPattern::parse("11%,!1109").unwrap()),
+                ..Default::default()
+            },
+            vec!["1", "2", "3"],
+        ),
+        (
+            MessageFilter {
+                method: Some(Arc::from("publish")),
+                tag: Some(TagPattern::parse("110*,3000-4000").unwrap()),
+                ..Default::default()
+            },
+            vec!["1", "3", "4", "5"],
+        ),
+    ] {
+        let result = store
+            .get_messages_after(TEST_CLIENT_ID, topic, Origin::Start, &filter, 10)
+            .await
+            .unwrap();
+        let message_ids: Vec<&str> = result
+            .messages
+            .iter()
+            .map(|message| message.message_id.as_ref())
+            .collect();
+        assert_eq!(message_ids, expected, "{filter:?}");
+        assert!(result.messages.iter().all(|message| message.tag.is_some()));
+    }
+}
+
+// NOTE: Requires the dev storage containers (see
+// `ops/docker-compose.storage.yml`).
+#[named]
+#[test_context(StoreContext)]
+#[tokio::test]
+#[cfg_attr(not(feature = "storage-tests"), ignore)]
+async fn test_cursor_forward(ctx: &StoreContext) {
+    let topic = function_name!();
+    fill_store_burst(ctx, TEST_CLIENT_ID, topic, 10).await;
+
+    let (message_ids, prev_cursor) = page_through(ctx, topic, true).await;
+    let expected: Vec<String> = (1..=10).map(|id| id.to_string()).collect();
+    assert_eq!(message_ids, expected, "check every message is paged once");
+
+    // The previous page of the last one goes back over the messages before it.
+    let result = ctx
+        .storage
+        .store
+        .get_messages_before(
+            TEST_CLIENT_ID,
+            topic,
+            Origin::Cursor(&prev_cursor.unwrap()),
+            &MessageFilter::default(),
+            TEST_QUERY_SIZE,
+        )
+        .await
+        .unwrap();
+    let message_ids: Vec<&str> = result
+        .messages
+        .iter()
+        .map(|message| message.message_id.as_ref())
+        .collect();
+    assert_eq!(message_ids, vec!["9", "8", "7"], "check previous page");
+}
+
+// NOTE: Requires the dev storage containers (see
+// `ops/docker-compose.storage.yml`).
+#[named]
+#[test_context(StoreContext)]
+#[tokio::test]
+#[cfg_attr(not(feature = "storage-tests"), ignore)]
+async fn test_cursor_backward(ctx: &StoreContext) {
+    let topic = function_name!();
+    fill_store_burst(ctx, TEST_CLIENT_ID, topic, 10).await;
+
+    let (message_ids, _) = page_through(ctx, topic, false).await;
+    let expected: Vec<String> = (1..=10).rev().map(|id| id.to_string()).collect();
+    assert_eq!(message_ids, expected, "check every message is paged once");
+}
+
+// NOTE: Requires the dev storage containers (see
+// `ops/docker-compose.storage.yml`).
+#[named]
+#[test_context(StoreContext)]
+#[tokio::test]
+#[cfg_attr(not(feature = "storage-tests"), ignore)]
+async fn test_delete_messages(ctx: &StoreContext) {
+    let client_id = format!("{TEST_CLIENT_ID}-{}", function_name!());
+    let topic = function_name!();
+    let other_topic = format!("{topic}-other");
+    fill_store_burst(ctx, &client_id, topic, 5).await;
+    fill_store_burst(ctx, &client_id, &other_topic, 5).await;
+    for topic in [topic, other_topic.as_str()] {
+        ctx.storage
+            .store
+            .upsert_topic(&client_id, topic)
+            .await
+            .unwrap();
+    }
+
+    let store = &ctx.storage.store;
+
+    let deleted = store
+        .delete_messages(&client_id, Some(topic), &["1", "2", "unknown"])
+        .await
+        .unwrap();
+    assert_eq!(deleted, 2, "check messages deleted by id on a topic");
+
+    let deleted = store
+        .delete_messages(&client_id, None, &["3"])
+        .await
+        .unwrap();
+    assert_eq!(deleted, 2, "check messages deleted by id across topics");
+
+    let deleted = store.delete_topic(&client_id, topic).await.unwrap();
+    assert_eq!(deleted, 2, "check the topic's remaining messages deleted");
+    assert!(!store.has_topic(&client_id, topic).await.unwrap());
+    assert!(store.has_topic(&client_id, &other_topic).await.unwrap());
+
+    let deleted = store.delete_all_messages(&client_id).await.unwrap();
+    assert_eq!(deleted, 4, "check the client's remaining messages deleted");
+    assert!(store.get_topics(&client_id).await.unwrap().is_empty());
+}
+
+// NOTE: Requires the dev storage containers (see
+// `ops/docker-compose.storage.yml`).
+#[named]
+#[test_context(StoreContext)]
+#[tokio::test]
+#[cfg_attr(not(feature = "storage-tests"), ignore)]
+async fn test_upsert_messages(ctx: &StoreContext) {
+    let topic = function_name!();
+    let message_ids = ["1", "2", "3"];
+    let messages: Vec<NewMessage> = message_ids
+        .iter()
+        .map(|&message_id| NewMessage {
+            method: "publish",
+            tag: TEST_TAG,
+            client_id: TEST_CLIENT_ID,
+            topic,
+            message_id,
+            message: message_id,
+            expires_at: None,
+        })
+        .collect();
+
+    let store = &ctx.storage.store;
+    let results = store.upsert_messages(&messages).await.unwrap();
+    assert_eq!(results, vec![Ok(()); 3]);
+    store
+        .upsert_topics(&[(TEST_CLIENT_ID, topic)])
+        .await
+        .unwrap();
+
+    // Upserting again replaces the messages instead of duplicating them.
+    let results = store.upsert_messages(&messages).await.unwrap();
+    assert_eq!(results, vec![Ok(()); 3]);
+
+    let result = store
+        .get_messages_after(
+            TEST_CLIENT_ID,
+            topic,
+            Origin::Start,
+            &MessageFilter::default(),
+            10,
+        )
+        .await
+        .unwrap();
+    let stored: Vec<&str> = result
+        .messages
+        .iter()
+        .map(|message| message.message_id.as_ref())
+        .collect();
+    assert_eq!(stored, message_ids);
+    assert!(store.has_topic(TEST_CLIENT_ID, topic).await.unwrap());
+}
+
+// NOTE: Requires the dev storage containers (see
+// `ops/docker-compose.storage.yml`).
+#[named]
+#[test_context(StoreContext)]
+#[tokio::test]
+#[cfg_attr(not(feature = "storage-tests"), ignore)]
+async fn test_upsert_messages_usage(ctx: &StoreContext) {
+    let client_id = format!("{TEST_CLIENT_ID}-{}", function_name!());
+    let topic = function_name!();
+    let store = &ctx.storage.store;
+
+    store_message(ctx, &client_id, topic, "1", "aaa").await;
+
+    // "1" is already stored and "2" is upserted twice, only "2" is new.
+    let messages: Vec<NewMessage> = [("1", "a"), ("2", "bb"), ("2", "bbbb")]
+        .into_iter()
+        .map(|(message_id, message)| NewMessage {
+            method: "publish",
+            tag: TEST_TAG,
+            client_id: &client_id,
+            topic,
+            message_id,
+            message,
+            expires_at: None,
+        })
+        .collect();
+    let results = store.upsert_messages(&messages).await.unwrap();
+    assert_eq!(results, vec![Ok(()); 3]);
+
+    assert_eq!(store.get_usage(&client_id).await.unwrap(), ClientUsage {
+        messages: 2,
+        bytes: 5,
+    });
+
+    let size = |message_id| store.get_message_size(&client_id, topic, message_id);
+    assert_eq!(size("2").await.unwrap(), Some(4));
+    assert_eq!(size("unknown").await.unwrap(), None);
+}
+
+// NOTE: Requires the dev storage containers (see
+// `ops/docker-compose.storage.yml`).
+#[named]
+#[test_context(StoreContext)]
+#[tokio::test]
+#[cfg_attr(not(feature = "storage-tests"), ignore)]
+async fn test_usage_and_eviction(ctx: &StoreContext) {
+    let client_id = format!("{TEST_CLIENT_ID}-{}", function_name!());
+    let topic = function_name!();
+    let other_topic = format!("{topic}-other");
+    let store = &ctx.storage.store;
+
+    let usage = |messages, bytes| ClientUsage { messages, bytes };
+    assert_eq!(store.get_usage(&client_id).await.unwrap(), usage(0, 0));
+
+    store_message(ctx, &client_id, topic, "1", "aaaa").await;
+    store_message(ctx, &client_id, topic, "2", "bb").await;
+    store_message(ctx, &client_id, &other_topic, "3", "cccccc").await;
+    assert_eq!(store.get_usage(&client_id).await.unwrap(), usage(3, 12));
+
+    // Replacing a message only changes its size.
+    store_message(ctx, &client_id, topic, "2", "bbbbb").await;
+    assert_eq!(store.get_usage(&client_id).await.unwrap(), usage(3, 15));
+
+    store
+        .delete_messages(&client_id, None, &["1"])
+        .await
+        .unwrap();
+    assert_eq!(store.get_usage(&client_id).await.unwrap(), usage(2, 11));
+
+    store_message(ctx, &client_id, topic, "4", "dd").await;
+    assert_eq!(store.get_usage(&client_id).await.unwrap(), usage(3, 13));
+
+    // Nothing to evict within the limits.
+    let evicted = store
+        .evict_messages(&client_id, Some(3), Some(13))
+        .await
+        .unwrap();
+    assert_eq!(evicted, 0);
+
+    // "3" is now the oldest, "2" having been replaced after it.
+    let evicted = store
+        .evict_messages(&client_id, Some(2), None)
+        .await
+        .unwrap();
+    assert_eq!(evicted, 1, "check the oldest message evicted");
+    assert_eq!(store.get_usage(&client_id).await.unwrap(), usage(2, 7));
+
+    let evicted = store
+        .evict_messages(&client_id, None, Some(4))
+        .await
+        .unwrap();
+    assert_eq!(evicted, 1, "check messages evicted down to the max bytes");
+    assert_eq!(store.get_usage(&client_id).await.unwrap(), usage(1, 2));
+
+    let result = store
+        .get_messages_after(
+            &client_id,
+            topic,
+            Origin::Start,
+            &MessageFilter::default(),
+            10,
+        )
+        .await
+        .unwrap();
+    let kept: Vec<&str> = result
+        .messages
+        .iter()
+        .map(|message| message.message_id.as_ref())
+        .collect();
+    assert_eq!(kept, vec!["4"]);
+
+    store.delete_all_messages(&client_id).await.unwrap();
+    assert_eq!(store.get_usage(&client_id).await.unwrap(), usage(0, 0));
+}
+
+/// Follows the `next_cursor`s until the last page, returning the paged
+/// message IDs and the last page's `prev_cursor`.
+async fn page_through(
+    ctx: &StoreContext,
+    topic: &str,
+    forward: bool,
+) -> (Vec<String>, Option<MessageCursor>) {
+    let mut message_ids = vec![];
+    let mut cursor: Option<MessageCursor> = None;
+
+    loop {
+        let origin = match &cursor {
+            Some(cursor) => Origin::Cursor(cursor),
+            None => Origin::Start,
+        };
+        let result = if forward {
+            ctx.storage
+                .store
+                .get_messages_after(
+                    TEST_CLIENT_ID,
+                    topic,
+                    origin,
+                    &MessageFilter::default(),
+                    TEST_QUERY_SIZE,
+                )
+                .await
+        } else {
+            ctx.storage
+                .store
+                .get_messages_before(
+                    TEST_CLIENT_ID,
+                    topic,
+                    origin,
+                    &MessageFilter::default(),
+                    TEST_QUERY_SIZE,
+                )
+                .await
+        }
+        .unwrap();
+
+        message_ids.extend(
+            result
+                .messages
+                .iter()
+                .map(|message| message.message_id.to_string()),
+        );
+
+        match result.next_cursor {
+            Some(next_cursor) => cursor = Some(next_cursor),
+            None => return (message_ids, result.prev_cursor),
+        }
+    }
+}
+
+/// Stores `message`, after the previous messages' timestamp.
+async fn store_message(
+    ctx: &StoreContext,
+    client_id: &str,
+    topic: &str,
+    message_id: &str,
+    message: &str,
+) {
+    std::thread::sleep(time::Duration::from_millis(2));
+    ctx.storage
+        .store
+        .upsert_message(
+            "publish", TEST_TAG, client_id, topic, message_id, message, None,
+        )
+        .await
+        .unwrap();
+}
+
+/// Stores messages as fast as possible, so that most of them share their
+/// timestamp.
+async fn fill_store_burst(ctx: &StoreContext, client_id: &str, topic: &str, size: i32) {
+    for id in 1..(size + 1) {
+        ctx.storage
+            .store
+            .upsert_message(
+                "publish",
+                TEST_TAG,
+                client_id,
+                topic,
+                &id.to_string(),
+                id.to_string().as_str(),
+                None,
+            )
+            .await
+            .unwrap();
+    }
+}
+
 async fn fill_store(ctx: &StoreContext, client_id: &str, topic: &str, size: i32) {
     for id in 1..(size + 1) {
         ctx.storage
             .store
             .upsert_message(
                 "publish",
+                TEST_TAG,
                 client_id,
                 topic,
                 &id.to_string(),
                 id.to_string().as_str(),
+                None,
             )
             .await
             .unwrap();
@@ -1,8 +1,17 @@
 use {
     async_trait::async_trait,
-    chrono::Utc,
+    chrono::{DateTime, Utc},
     gilgamesh::store::{
-        messages::{Message, MessagesStore, StoreMessages},
+        messages::{
+            ClientTopic,
+            ClientUsage,
+            Message,
+            MessageCursor,
+            MessageFilter,
+            MessagesStore,
+            Origin,
+            StoreMessages,
+        },
         StoreError,
     },
     moka::future::Cache,
@@ -12,6 +21,7 @@ use {
 #[derive(Debug)]
 pub struct MockMessageStore {
     pub messages: Cache<String, Message>,
+    pub topics: Cache<String, ClientTopic>,
     pub client_id: Option<String>,
 }
 
@@ -19,10 +29,15 @@ fn cache_key(client_id: &str, topic: &str, message_id: &str) -> String {
     format!("{client_id}:{topic}:{message_id}")
 }
 
+fn topic_key(client_id: &str, topic: &str) -> String {
+    format!("{client_id}:{topic}")
+}
+
 impl MockMessageStore {
     pub fn new() -> Self {
         Self {
             messages: Cache::builder().build(),
+            topics: Cache::builder().build(),
             client_id: None,
         }
     }
@@ -49,6 +64,47 @@ impl MockMessageStore {
     pub fn test_get_messages(&self) -> Vec<Message> {
         self.messages.iter().map(|(_, v)| v).collect()
     }
+
+    /// The messages of `client_id` on any of `topics`, oldest first.
+    pub fn test_get_topics_messages(&self, client_id: &str, topics: &[&str]) -> Vec<Message> {
+        let mut messages: Vec<Message> = self
+            .messages
+            .iter()
+            .map(|(_, message)| message)
+            .filter(|message| {
+                message.client_id.as_ref() == client_id && topics.contains(&message.topic.as_ref())
+            })
+            .collect();
+        messages.sort_by_key(|message| message.timestamp);
+
+        messages
+    }
+
+    async fn test_delete(&self, predicate: impl Fn(&Message) -> bool) -> Result<u64, StoreError> {
+        let keys: Vec<String> = self
+            .messages
+            .iter()
+            .filter(|(_, message)| predicate(message))
+            .map(|(key, _)| key.as_ref().clone())
+            .collect();
+
+        for key in &keys {
+            self.messages.invalidate(key).await;
+        }
+
+        Ok(keys.len() as u64)
+    }
+
+    pub async fn test_add_topic(&self, client_id: &str, topic: &str) {
+        self.topics
+            .insert(topic_key(client_id, topic), ClientTopic {
+                id: None,
+                timestamp: Utc::now().into(),
+                client_id: Arc::from(client_id),
+                topic: Arc::from(topic),
+            })
+            .await;
+    }
 }
 
 #[async_trait]
@@ -56,19 +112,23 @@ impl MessagesStore for MockMessageStore {
     async fn upsert_message(
         &self,
         method: &str,
+        tag: u32,
         client_id: &str,
         topic: &str,
         message_id: &str,
         message: &str,
+        expires_at: Option<DateTime<Utc>>,
     ) -> Result<(), StoreError> {
         self.test_add(Message {
             id: None,
             timestamp: Utc::now().into(),
             method: Arc::from(method),
+            tag: Some(tag),
             client_id: Arc::from(client_id),
             message_id: Arc::from(message_id),
             topic: Arc::from(topic),
             message: Arc::from(message),
+            expires_at: expires_at.map(Into::into),
         })
         .await;
 
@@ -77,25 +137,193 @@ impl MessagesStore for MockMessageStore {
 
     async fn get_messages_after(
         &self,
+        _client_id: &str,
         _topic: &str,
-        _origin: Option<&str>,
+        _origin: Origin<'_>,
+        _filter: &MessageFilter,
         _message_count: usize,
     ) -> Result<StoreMessages, StoreError> {
         Ok(StoreMessages {
             messages: self.test_get_messages(),
+            cursors: vec![],
             next_id: Some(Arc::from("after")),
+            next_cursor: Some(MessageCursor::new(2, "after")),
+            prev_cursor: Some(MessageCursor::new(1, "after")),
         })
     }
 
     async fn get_messages_before(
         &self,
+        _client_id: &str,
         _topic: &str,
-        _origin: Option<&str>,
+        _origin: Origin<'_>,
+        _filter: &MessageFilter,
         _message_count: usize,
     ) -> Result<StoreMessages, StoreError> {
         Ok(StoreMessages {
             messages: self.test_get_messages(),
+            cursors: vec![],
             next_id: Some(Arc::from("before")),
+            next_cursor: Some(MessageCursor::new(1, "before")),
+            prev_cursor: Some(MessageCursor::new(2, "before")),
+        })
+    }
+
+    async fn get_topics_messages_after(
+        &self,
+        client_id: &str,
+        topics: &[&str],
+        _cursor: Option<&MessageCursor>,
+        _filter: &MessageFilter,
+        _message_count: usize,
+    ) -> Result<StoreMessages, StoreError> {
+        Ok(StoreMessages {
+            messages: self.test_get_topics_messages(client_id, topics),
+            cursors: vec![],
+            next_id: None,
+            next_cursor: Some(MessageCursor::new(2, "after")),
+            prev_cursor: Some(MessageCursor::new(1, "after")),
+        })
+    }
+
+    async fn get_topics_messages_before(
+        &self,
+        client_id: &str,
+        topics: &[&str],
+        _cursor: Option<&MessageCursor>,
+        _filter: &MessageFilter,
+        _message_count: usize,
+    ) -> Result<StoreMessages, StoreError> {
+        Ok(StoreMessages {
+            messages: self.test_get_topics_messages(client_id, topics),
+            cursors: vec![],
+            next_id: None,
+            next_cursor: Some(MessageCursor::new(1, "before")),
+            prev_cursor: Some(MessageCursor::new(2, "before")),
+        })
+    }
+
+    async fn upsert_topic(&self, client_id: &str, topic: &str) -> Result<(), StoreError> {
+        self.test_add_topic(client_id, topic).await;
+        Ok(())
+    }
+
+    async fn has_topic(&self, client_id: &str, topic: &str) -> Result<bool, StoreError> {
+        Ok(self.topics.get(&topic_key(client_id, topic)).is_some())
+    }
+
+    async fn get_topics(&self, client_id: &str) -> Result<Vec<ClientTopic>, StoreError> {
+        Ok(self
+            .topics
+            .iter()
+            .map(|(_, v)| v)
+            .filter(|topic| topic.client_id.as_ref() == client_id)
+            .collect())
+    }
+
+    async fn delete_expired_messages(
+        &self,
+        stored_before: Option<DateTime<Utc>>,
+    ) -> Result<u64, StoreError> {
+        let now = Utc::now().timestamp_millis();
+        let stored_before = stored_before.map(|stored_before| stored_before.timestamp_millis());
+        self.test_delete(|message| match message.expires_at {
+            Some(expires_at) => expires_at.timestamp_millis() <= now,
+            None => stored_before.map_or(false, |stored_before| {
+                message.timestamp.timestamp_millis() <= stored_before
+            }),
+        })
+        .await
+    }
+
+    async fn delete_messages(
+        &self,
+        client_id: &str,
+        topic: Option<&str>,
+        message_ids: &[&str],
+    ) -> Result<u64, StoreError> {
+        self.test_delete(|message| {
+            message.client_id.as_ref() == client_id
+                && topic.map_or(true, |topic| message.topic.as_ref() == topic)
+                && message_ids.contains(&message.message_id.as_ref())
+        })
+        .await
+    }
+
+    async fn delete_topic(&self, client_id: &str, topic: &str) -> Result<u64, StoreError> {
+        self.topics.invalidate(&topic_key(client_id, topic)).await;
+        self.test_delete(|message| {
+            message.client_id.as_ref() == client_id && message.topic.as_ref() == topic
+        })
+        .await
+    }
+
+    async fn delete_all_messages(&self, client_id: &str) -> Result<u64, StoreError> {
+        let topics: Vec<String> = self
+            .topics
+            .iter()
+            .filter(|(_, topic)| topic.client_id.as_ref() == client_id)
+            .map(|(key, _)| key.as_ref().clone())
+            .collect();
+        for key in &topics {
+            self.topics.invalidate(key).await;
+        }
+
+        self.test_delete(|message| message.client_id.as_ref() == client_id)
+            .await
+    }
+
+    async fn get_usage(&self, client_id: &str) -> Result<ClientUsage, StoreError> {
+        let mut usage = ClientUsage::default();
+        for (_, message) in self.messages.iter() {
+            if message.client_id.as_ref() == client_id {
+                usage.add(&message.message);
+            }
+        }
+
+        Ok(usage)
+    }
+
+    async fn get_message_size(
+        &self,
+        client_id: &str,
+        topic: &str,
+        message_id: &str,
+    ) -> Result<Option<u64>, StoreError> {
+        Ok(self
+            .messages
+            .get(&cache_key(client_id, topic, message_id))
+            .map(|message| message.message.len() as u64))
+    }
+
+    async fn evict_messages(
+        &self,
+        client_id: &str,
+        max_messages: Option<u64>,
+        max_bytes: Option<u64>,
+    ) -> Result<u64, StoreError> {
+        let mut messages: Vec<Message> = self
+            .messages
+            .iter()
+            .map(|(_, message)| message)
+            .filter(|message| message.client_id.as_ref() == client_id)
+            .collect();
+        messages.sort_by(|a, b| (b.timestamp, &b.message_id).cmp(&(a.timestamp, &a.message_id)));
+
+        let mut kept = ClientUsage::default();
+        let mut evicted = vec![];
+        for message in messages {
+            kept.add(&message.message);
+            if max_messages.map_or(false, |max| kept.messages > max)
+                || max_bytes.map_or(false, |max| kept.bytes > max)
+            {
+                evicted.push(message.message_id);
+            }
+        }
+
+        self.test_delete(|message| {
+            message.client_id.as_ref() == client_id && evicted.contains(&message.message_id)
         })
+        .await
     }
 }
@@ -28,12 +28,14 @@ impl RegistrationStore for MockRegistrationStore {
         client_id: &str,
         tags: Vec<&str>,
         relay_url: &str,
+        max_age: Option<u64>,
     ) -> Result<(), StoreError> {
         let reg = Registration {
             id: None,
             client_id: Arc::from(client_id),
             tags: tags.iter().map(|s| Arc::from(s.to_string())).collect(),
             relay_url: Arc::from(relay_url),
+            max_age,
         };
 
         self.registrations.insert(client_id.to_string(), reg).await;
@@ -48,4 +50,14 @@ impl RegistrationStore for MockRegistrationStore {
                 client_id.to_string(),
             ))
     }
+
+    async fn delete_registration(&self, client_id: &str) -> Result<(), StoreError> {
+        let registration = self.registrations.get(client_id);
+        self.registrations.invalidate(client_id).await;
+
+        registration.map(|_| ()).ok_or(StoreError::NotFound(
+            "registration".to_string(),
+            client_id.to_string(),
+        ))
+    }
 }
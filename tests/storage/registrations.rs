@@ -11,7 +11,8 @@ use {
 const TEST_CLIENT_ID: &str = "12345";
 const TEST_RELAY_URL: &str = "https:://test.relay.walletconnect.com";
 
-// NOTE: Requires the dev MongoDB container (see `ops/docker-compose.yml`).
+// NOTE: Requires the dev storage containers (see
+// `ops/docker-compose.storage.yml`).
 #[test_context(StoreContext)]
 #[tokio::test]
 #[cfg_attr(not(feature = "storage-tests"), ignore)]
@@ -19,7 +20,7 @@ async fn test_registration(ctx: &StoreContext) {
     const TAGS: [&str; 2] = ["1234", "5678"];
     ctx.storage
         .store
-        .upsert_registration(TEST_CLIENT_ID, Vec::from(TAGS), TEST_RELAY_URL)
+        .upsert_registration(TEST_CLIENT_ID, Vec::from(TAGS), TEST_RELAY_URL, None)
         .await
         .unwrap();
 
@@ -36,7 +37,8 @@ async fn test_registration(ctx: &StoreContext) {
     assert_eq!(tags, TAGS);
 }
 
-// NOTE: Requires the dev MongoDB container (see `ops/docker-compose.yml`).
+// NOTE: Requires the dev storage containers (see
+// `ops/docker-compose.storage.yml`).
 #[test_context(StoreContext)]
 #[tokio::test]
 #[cfg_attr(not(feature = "storage-tests"), ignore)]
@@ -55,3 +57,66 @@ async fn test_registration_not_found(ctx: &StoreContext) {
         Err(e) => panic!("Expected `StoreError::NotFound` error, got: {e:?}"),
     }
 }
+
+// NOTE: Requires the dev storage containers (see
+// `ops/docker-compose.storage.yml`).
+#[test_context(StoreContext)]
+#[tokio::test]
+#[cfg_attr(not(feature = "storage-tests"), ignore)]
+async fn test_delete_registration(ctx: &StoreContext) {
+    let client_id = format!("{TEST_CLIENT_ID}-delete");
+    ctx.storage
+        .store
+        .upsert_registration(&client_id, vec!["1234"], TEST_RELAY_URL, None)
+        .await
+        .unwrap();
+
+    ctx.storage
+        .store
+        .delete_registration(&client_id)
+        .await
+        .unwrap();
+
+    match ctx.storage.store.get_registration(&client_id).await {
+        Err(StoreError::NotFound(_, _)) => {}
+        res => panic!("Expected `StoreError::NotFound` error, got: {res:?}"),
+    }
+    match ctx.storage.store.delete_registration(&client_id).await {
+        Err(StoreError::NotFound(_, _)) => {}
+        res => panic!("Expected `StoreError::NotFound` error, got: {res:?}"),
+    }
+}
+
+// NOTE: Requires the dev storage containers (see
+// `ops/docker-compose.storage.yml`).
+#[test_context(StoreContext)]
+#[tokio::test]
+#[cfg_attr(not(feature = "storage-tests"), ignore)]
+async fn test_get_registrations(ctx: &StoreContext) {
+    let client_ids = [
+        format!("{TEST_CLIENT_ID}-bulk-1"),
+        format!("{TEST_CLIENT_ID}-bulk-2"),
+    ];
+    for client_id in &client_ids {
+        ctx.storage
+            .store
+            .upsert_registration(client_id, vec!["1234"], TEST_RELAY_URL, None)
+            .await
+            .unwrap();
+    }
+
+    let missing = format!("{TEST_CLIENT_ID}-bulk-missing");
+    let mut registrations = ctx
+        .storage
+        .store
+        .get_registrations(&[&client_ids[0], &client_ids[1], &missing])
+        .await
+        .unwrap();
+    registrations.sort_by(|a, b| a.client_id.cmp(&b.client_id));
+
+    let found: Vec<&str> = registrations
+        .iter()
+        .map(|registration| registration.client_id.as_ref())
+        .collect();
+    assert_eq!(found, client_ids);
+}
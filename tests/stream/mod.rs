@@ -0,0 +1,242 @@
+use {
+    crate::{context::MemoryServerContext, get_client_jwt, TEST_RELAY_URL},
+    axum::http,
+    gilgamesh::{
+        handlers::{
+            save_message::HistoryPayload,
+            stream_messages::{StreamedMessage, MAX_CLIENT_STREAMS},
+        },
+        store::registrations::Registration,
+    },
+    relay_rpc::{
+        auth::{
+            ed25519_dalek::Keypair,
+            rand::{rngs::StdRng, SeedableRng},
+            AuthToken,
+        },
+        domain::{ClientId, DecodedClientId},
+    },
+    std::{sync::Arc, time::Duration},
+    test_context::test_context,
+    tokio::time::timeout,
+};
+
+const TEST_METHOD: &str = "publish";
+const TEST_TOPIC: &str = "test-topic";
+const TEST_MESSAGE: &str = "test-message";
+
+async fn register(ctx: &MemoryServerContext, client_id: &ClientId) {
+    ctx.server
+        .registration_store
+        .registrations
+        .insert(client_id.to_string(), Registration {
+            id: None,
+            client_id: client_id.clone().into_value(),
+            tags: vec![Arc::from("4000")],
+            relay_url: Arc::from(TEST_RELAY_URL),
+            max_age: None,
+        })
+        .await;
+}
+
+async fn save_message(ctx: &MemoryServerContext, client_id: &ClientId, message_id: &str) {
+    let response = reqwest::Client::new()
+        .post(format!("http://{}/messages", ctx.server.public_addr))
+        .json(&HistoryPayload {
+            method: Arc::from(TEST_METHOD),
+            client_id: client_id.clone().into_value(),
+            topic: Arc::from(TEST_TOPIC),
+            message_id: Arc::from(message_id),
+            tag: 4000,
+            message: Arc::from(TEST_MESSAGE),
+        })
+        .send()
+        .await
+        .expect("Call failed");
+    assert!(response.status().is_success());
+}
+
+async fn open_stream(
+    ctx: &MemoryServerContext,
+    jwt: &str,
+    query: &[(&str, &str)],
+) -> reqwest::Response {
+    reqwest::Client::new()
+        .get(format!("http://{}/messages/stream", ctx.server.public_addr))
+        .query(query)
+        .header(http::header::AUTHORIZATION, format!("Bearer {jwt}"))
+        .send()
+        .await
+        .expect("Call failed")
+}
+
+/// Reads the stream until it ends.
+async fn stream_end(response: &mut reqwest::Response) {
+    timeout(Duration::from_secs(5), async {
+        while response.chunk().await.unwrap().is_some() {}
+    })
+    .await
+    .expect("The stream didn't end")
+}
+
+/// Reads the stream until its next `message` event.
+async fn next_message(response: &mut reqwest::Response, buffer: &mut String) -> StreamedMessage {
+    timeout(Duration::from_secs(5), async {
+        loop {
+            if let Some(end) = buffer.find("\n\n") {
+                let event: String = buffer.drain(..end + 2).collect();
+                if let Some(data) = event.lines().find_map(|line| line.strip_prefix("data:")) {
+                    return serde_json::from_str(data.trim()).unwrap();
+                }
+                continue;
+            }
+
+            let chunk = response.chunk().await.unwrap().expect("The stream ended");
+            buffer.push_str(std::str::from_utf8(&chunk).unwrap());
+        }
+    })
+    .await
+    .expect("No message was streamed")
+}
+
+#[test_context(MemoryServerContext)]
+#[tokio::test]
+async fn test_stream_new_messages(ctx: &mut MemoryServerContext) {
+    let (jwt, client_id) = get_client_jwt();
+    register(ctx, &client_id).await;
+    save_message(ctx, &client_id, "1").await;
+
+    let mut response = open_stream(ctx, &jwt, &[("topics", TEST_TOPIC)]).await;
+    assert!(response.status().is_success());
+
+    save_message(ctx, &client_id, "2").await;
+    save_message(ctx, &client_id, "3").await;
+
+    let mut buffer = String::new();
+    for message_id in ["2", "3"] {
+        let streamed = next_message(&mut response, &mut buffer).await;
+        assert_eq!(streamed.topic.as_ref(), TEST_TOPIC);
+        assert_eq!(streamed.message.message_id.as_ref(), message_id);
+    }
+}
+
+#[test_context(MemoryServerContext)]
+#[tokio::test]
+async fn test_stream_replay(ctx: &mut MemoryServerContext) {
+    let (jwt, client_id) = get_client_jwt();
+    register(ctx, &client_id).await;
+    save_message(ctx, &client_id, "1").await;
+
+    let mut response = open_stream(ctx, &jwt, &[("topics", TEST_TOPIC)]).await;
+    save_message(ctx, &client_id, "2").await;
+    let cursor = next_message(&mut response, &mut String::new()).await.cursor;
+    drop(response);
+
+    save_message(ctx, &client_id, "3").await;
+    save_message(ctx, &client_id, "4").await;
+
+    let mut response =
+        open_stream(ctx, &jwt, &[("topics", TEST_TOPIC), ("cursors", &cursor)]).await;
+    assert!(response.status().is_success());
+
+    let mut buffer = String::new();
+    for message_id in ["3", "4"] {
+        let streamed = next_message(&mut response, &mut buffer).await;
+        assert_eq!(streamed.message.message_id.as_ref(), message_id);
+    }
+}
+
+#[test_context(MemoryServerContext)]
+#[tokio::test]
+async fn test_stream_invalid_request(ctx: &mut MemoryServerContext) {
+    let (jwt, client_id) = get_client_jwt();
+    register(ctx, &client_id).await;
+    save_message(ctx, &client_id, "1").await;
+
+    let response = open_stream(ctx, &jwt, &[("topics", "other-topic")]).await;
+    assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+
+    let response = open_stream(ctx, &jwt, &[("topics", TEST_TOPIC), ("cursors", ",")]).await;
+    assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
+}
+
+#[test_context(MemoryServerContext)]
+#[tokio::test]
+async fn test_stream_ends_as_jwt_expires(ctx: &mut MemoryServerContext) {
+    let keypair = Keypair::generate(&mut StdRng::from_entropy());
+    let client_id = ClientId::from(DecodedClientId(*keypair.public_key().as_bytes()));
+    let jwt = AuthToken::new(client_id.to_string())
+        .aud(TEST_RELAY_URL.to_string())
+        .ttl(Some(Duration::from_secs(2)))
+        .as_jwt(&keypair)
+        .unwrap()
+        .to_string();
+    register(ctx, &client_id).await;
+    save_message(ctx, &client_id, "1").await;
+
+    let mut response = open_stream(ctx, &jwt, &[("topics", TEST_TOPIC)]).await;
+    assert!(response.status().is_success());
+
+    stream_end(&mut response).await;
+}
+
+#[test_context(MemoryServerContext)]
+#[tokio::test]
+async fn test_stream_ends_as_messages_are_purged(ctx: &mut MemoryServerContext) {
+    let (jwt, client_id) = get_client_jwt();
+    register(ctx, &client_id).await;
+    save_message(ctx, &client_id, "1").await;
+
+    let mut response = open_stream(ctx, &jwt, &[("topics", TEST_TOPIC)]).await;
+    assert!(response.status().is_success());
+
+    let unregistered = reqwest::Client::new()
+        .delete(format!("http://{}/register", ctx.server.public_addr))
+        .query(&[("purgeMessages", "true")])
+        .header(http::header::AUTHORIZATION, format!("Bearer {jwt}"))
+        .send()
+        .await
+        .expect("Call failed");
+    assert!(unregistered.status().is_success());
+
+    stream_end(&mut response).await;
+}
+
+#[test_context(MemoryServerContext)]
+#[tokio::test]
+async fn test_stream_limit(ctx: &mut MemoryServerContext) {
+    let (jwt, client_id) = get_client_jwt();
+    register(ctx, &client_id).await;
+    save_message(ctx, &client_id, "1").await;
+
+    let mut streams = vec![];
+    for _ in 0..MAX_CLIENT_STREAMS {
+        let response = open_stream(ctx, &jwt, &[("topics", TEST_TOPIC)]).await;
+        assert!(response.status().is_success());
+        streams.push(response);
+    }
+
+    let response = open_stream(ctx, &jwt, &[("topics", TEST_TOPIC)]).await;
+    assert_eq!(response.status(), http::StatusCode::TOO_MANY_REQUESTS);
+
+    // Another client isn't limited
+    let (other_jwt, other_client_id) = get_client_jwt();
+    register(ctx, &other_client_id).await;
+    save_message(ctx, &other_client_id, "1").await;
+    let response = open_stream(ctx, &other_jwt, &[("topics", TEST_TOPIC)]).await;
+    assert!(response.status().is_success());
+
+    // Closing a stream makes room for another, once the server noticed
+    drop(streams.pop());
+    timeout(Duration::from_secs(5), async {
+        while open_stream(ctx, &jwt, &[("topics", TEST_TOPIC)])
+            .await
+            .status()
+            == http::StatusCode::TOO_MANY_REQUESTS
+        {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("The closed stream was still counted");
+}
@@ -0,0 +1,304 @@
+use {
+    crate::{
+        context::{relay::StandInRelay, RelayContext},
+        TEST_RELAY_URL,
+    },
+    axum::http::StatusCode,
+    chrono::{DateTime, Duration, Utc},
+    gilgamesh::{
+        handlers::save_messages::{SaveMessageStatus, SaveMessagesResponse},
+        relay::watch::{
+            encode_jwt,
+            watch_client_id,
+            WatchEventClaims,
+            WatchEventPayload,
+            WatchRegisterClaims,
+            WatchStatus,
+            WatchType,
+            WatchWebhookPayload,
+            WATCH_EVENT_ACTION,
+            WATCH_REGISTER_METHOD,
+        },
+        store::{messages::MessagesStore, registrations::Registration},
+    },
+    relay_rpc::{
+        auth::{
+            ed25519_dalek::Keypair,
+            rand::{rngs::StdRng, SeedableRng},
+        },
+        domain::{ClientId, DecodedClientId},
+        jwt::{JwtBasicClaims, VerifyableClaims},
+    },
+    serde_json::Value,
+    std::sync::Arc,
+    test_context::test_context,
+    tokio::time::{sleep, timeout},
+};
+
+const TEST_TOPIC: &str = "test-topic";
+const TEST_MESSAGE: &str = "test-message";
+
+fn event_auth(
+    ctx: &RelayContext,
+    issuer: &Keypair,
+    client_id: DecodedClientId,
+    tag: u32,
+    issued_at: DateTime<Utc>,
+) -> String {
+    let now = Utc::now();
+    let claims = WatchEventClaims {
+        basic: JwtBasicClaims {
+            iss: watch_client_id(issuer),
+            sub: client_id.to_did_key(),
+            aud: watch_client_id(&ctx.keypair).to_did_key(),
+            iat: issued_at.timestamp(),
+            exp: Some((now + Duration::minutes(5)).timestamp()),
+        },
+        act: WATCH_EVENT_ACTION.to_string(),
+        typ: WatchType::Subscriber,
+        whu: format!("http://{}/messages/watch", ctx.server.public_addr),
+        evt: WatchEventPayload {
+            message_id: 42,
+            status: WatchStatus::Accepted,
+            topic: Arc::from(TEST_TOPIC),
+            message: Arc::from(TEST_MESSAGE),
+            published_at: now.timestamp_millis(),
+            tag,
+        },
+    };
+
+    encode_jwt(&claims, issuer).unwrap()
+}
+
+async fn register_client(ctx: &RelayContext) -> DecodedClientId {
+    let keypair = Keypair::generate(&mut StdRng::from_entropy());
+    let client_id = watch_client_id(&keypair);
+
+    let id = ClientId::from(client_id).into_value();
+    ctx.server
+        .registration_store
+        .registrations
+        .insert(id.to_string(), Registration {
+            id: None,
+            client_id: id,
+            tags: vec![Arc::from("4000")],
+            relay_url: Arc::from(TEST_RELAY_URL),
+            max_age: None,
+        })
+        .await;
+
+    client_id
+}
+
+#[test_context(RelayContext)]
+#[tokio::test]
+async fn test_watch_register(ctx: &mut RelayContext) {
+    let request = timeout(std::time::Duration::from_secs(5), async {
+        loop {
+            if let Some(request) = ctx.relay.requests.lock().unwrap().first() {
+                return request.clone();
+            }
+            sleep(std::time::Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("The watch was not registered");
+
+    assert_eq!(request["method"], WATCH_REGISTER_METHOD);
+    let Value::String(register_auth) = &request["params"]["registerAuth"] else {
+        panic!("Missing `registerAuth`: {request}");
+    };
+
+    let claims = WatchRegisterClaims::try_from_str(register_auth).unwrap();
+    assert_eq!(claims.basic.iss.0, *ctx.keypair.public_key().as_bytes());
+    assert_eq!(claims.basic.aud, ctx.relay.url());
+    assert_eq!(claims.act, WATCH_REGISTER_METHOD);
+    assert_eq!(claims.typ, WatchType::Subscriber);
+    assert_eq!(
+        claims.whu,
+        format!("http://{}/messages/watch", ctx.server.public_addr)
+    );
+}
+
+#[test_context(RelayContext)]
+#[tokio::test]
+async fn test_watch_register_on_relay_reload(ctx: &mut RelayContext) {
+    let mut relay = StandInRelay::start().await;
+    ctx.server.next_config.lock().unwrap().relay_url = relay.url();
+
+    let response = reqwest::Client::new()
+        .post(format!("http://{}/reload", ctx.server.private_addr))
+        .send()
+        .await
+        .expect("Call failed");
+    assert!(response.status().is_success());
+
+    let request = timeout(std::time::Duration::from_secs(5), async {
+        loop {
+            if let Some(request) = relay.requests.lock().unwrap().first() {
+                return request.clone();
+            }
+            sleep(std::time::Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("The watch was not registered with the reloaded relay");
+    relay.shutdown();
+
+    assert_eq!(request["method"], WATCH_REGISTER_METHOD);
+    let Value::String(register_auth) = &request["params"]["registerAuth"] else {
+        panic!("Missing `registerAuth`: {request}");
+    };
+    let claims = WatchRegisterClaims::try_from_str(register_auth).unwrap();
+    assert_eq!(claims.basic.aud, relay.url());
+}
+
+#[test_context(RelayContext)]
+#[tokio::test]
+async fn test_watch_event(ctx: &mut RelayContext) {
+    let client_id = register_client(ctx).await;
+    let payload = WatchWebhookPayload {
+        event_auth: vec![
+            event_auth(ctx, &ctx.relay.keypair, client_id, 4000, Utc::now()),
+            event_auth(ctx, &ctx.relay.keypair, client_id, 4001, Utc::now()),
+        ],
+    };
+
+    let client = reqwest::Client::new();
+    let response = client
+        .post(format!("http://{}/messages/watch", ctx.server.public_addr))
+        .json(&payload)
+        .send()
+        .await
+        .expect("Call failed");
+
+    assert!(
+        response.status().is_success(),
+        "Response was not successful: {:?} - {:?}",
+        response.status(),
+        response.text().await
+    );
+
+    let response: SaveMessagesResponse = response.json().await.unwrap();
+    let statuses: Vec<SaveMessageStatus> = response
+        .results
+        .iter()
+        .map(|result| result.status)
+        .collect();
+    assert_eq!(statuses, vec![
+        SaveMessageStatus::Stored,
+        SaveMessageStatus::Skipped
+    ]);
+
+    let message = ctx
+        .server
+        .message_store
+        .test_get(ClientId::from(client_id).value(), TEST_TOPIC, "42")
+        .await
+        .expect("The message was not stored");
+    assert_eq!(message.message.as_ref(), TEST_MESSAGE);
+    assert_eq!(message.method.as_ref(), "subscription");
+}
+
+#[test_context(RelayContext)]
+#[tokio::test]
+async fn test_watch_event_not_issued_by_relay(ctx: &mut RelayContext) {
+    let client_id = register_client(ctx).await;
+    let impostor = Keypair::generate(&mut StdRng::from_entropy());
+    let payload = WatchWebhookPayload {
+        event_auth: vec![event_auth(ctx, &impostor, client_id, 4000, Utc::now())],
+    };
+
+    let client = reqwest::Client::new();
+    let response = client
+        .post(format!("http://{}/messages/watch", ctx.server.public_addr))
+        .json(&payload)
+        .send()
+        .await
+        .expect("Call failed");
+
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert!(ctx
+        .server
+        .message_store
+        .test_get(ClientId::from(client_id).value(), TEST_TOPIC, "42")
+        .await
+        .is_none());
+}
+
+#[test_context(RelayContext)]
+#[tokio::test]
+async fn test_watch_event_replayed(ctx: &mut RelayContext) {
+    let client_id = register_client(ctx).await;
+    let payload = WatchWebhookPayload {
+        event_auth: vec![event_auth(
+            ctx,
+            &ctx.relay.keypair,
+            client_id,
+            4000,
+            Utc::now(),
+        )],
+    };
+
+    let client = reqwest::Client::new();
+    let response = client
+        .post(format!("http://{}/messages/watch", ctx.server.public_addr))
+        .json(&payload)
+        .send()
+        .await
+        .expect("Call failed");
+    assert!(response.status().is_success());
+
+    // The client erases the message, which a replay must not bring back
+    ctx.server
+        .message_store
+        .delete_messages(ClientId::from(client_id).value(), None, &["42"])
+        .await
+        .unwrap();
+
+    let response = client
+        .post(format!("http://{}/messages/watch", ctx.server.public_addr))
+        .json(&payload)
+        .send()
+        .await
+        .expect("Call failed");
+
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert!(ctx
+        .server
+        .message_store
+        .test_get(ClientId::from(client_id).value(), TEST_TOPIC, "42")
+        .await
+        .is_none());
+}
+
+#[test_context(RelayContext)]
+#[tokio::test]
+async fn test_watch_event_stale(ctx: &mut RelayContext) {
+    let client_id = register_client(ctx).await;
+    let payload = WatchWebhookPayload {
+        event_auth: vec![event_auth(
+            ctx,
+            &ctx.relay.keypair,
+            client_id,
+            4000,
+            Utc::now() - Duration::minutes(10),
+        )],
+    };
+
+    let client = reqwest::Client::new();
+    let response = client
+        .post(format!("http://{}/messages/watch", ctx.server.public_addr))
+        .json(&payload)
+        .send()
+        .await
+        .expect("Call failed");
+
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert!(ctx
+        .server
+        .message_store
+        .test_get(ClientId::from(client_id).value(), TEST_TOPIC, "42")
+        .await
+        .is_none());
+}